@@ -0,0 +1,3 @@
+ALTER TABLE blocks DROP COLUMN signature;
+ALTER TABLE blocks DROP COLUMN public_key;
+DROP TABLE identities;
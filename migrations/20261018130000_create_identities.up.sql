@@ -0,0 +1,8 @@
+CREATE TABLE identities (
+    name TEXT PRIMARY KEY,
+    public_key TEXT NOT NULL,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+);
+
+ALTER TABLE blocks ADD COLUMN public_key TEXT;
+ALTER TABLE blocks ADD COLUMN signature TEXT;
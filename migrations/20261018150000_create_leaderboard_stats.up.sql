@@ -0,0 +1,10 @@
+-- Per name and round (week starting Monday) aggregates, refreshed whenever a block is added.
+-- Best singles are read from leaderboard_moves, which leaves out mean of N challenge blocks.
+CREATE TABLE leaderboard_stats (
+    name TEXT NOT NULL,
+    round_start DATE NOT NULL,
+    block_count INTEGER NOT NULL,
+    main_chain_count INTEGER NOT NULL,
+
+    PRIMARY KEY (name, round_start)
+);
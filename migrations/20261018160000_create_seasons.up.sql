@@ -0,0 +1,19 @@
+-- A season groups the rounds (weeks starting Monday) between starts_on and ends_on, both included
+CREATE TABLE seasons (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    name TEXT NOT NULL,
+    starts_on DATE NOT NULL,
+    ends_on DATE NOT NULL,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE TABLE season_standings (
+    season_id INTEGER NOT NULL,
+    name TEXT NOT NULL,
+    points REAL NOT NULL,
+    block_count INTEGER NOT NULL,
+    round_count INTEGER NOT NULL,
+
+    PRIMARY KEY (season_id, name),
+    FOREIGN KEY(season_id) REFERENCES seasons(id) ON DELETE CASCADE
+);
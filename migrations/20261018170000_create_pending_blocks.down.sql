@@ -0,0 +1,2 @@
+DROP TABLE pending_blocks;
+DROP TABLE commit_reveal_rounds;
@@ -0,0 +1,19 @@
+-- Rounds in which solutions are committed to first and only revealed after the round closes
+CREATE TABLE commit_reveal_rounds (
+    round_start DATE PRIMARY KEY,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+);
+
+-- Blocks of a commit-reveal round whose solution is not revealed yet. The hash is reserved, and
+-- the block moves to the blocks table once a solution matching the commitment is revealed.
+CREATE TABLE pending_blocks (
+    hash TEXT PRIMARY KEY,
+    parent_hash TEXT NOT NULL,
+    name TEXT NOT NULL,
+    message TEXT NOT NULL,
+    commitment TEXT NOT NULL,
+    user_id INTEGER REFERENCES users(id),
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+
+    FOREIGN KEY(parent_hash) REFERENCES blocks(hash) ON DELETE CASCADE
+);
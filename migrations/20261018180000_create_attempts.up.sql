@@ -0,0 +1,15 @@
+-- Attempts: when the scramble of a block (by the hash of parent, name and message) was first
+-- revealed to a user, and how long it took them to submit a solution for it. Untimed reveals
+-- are recorded too, so a scramble already seen can't be attempted timed.
+CREATE TABLE attempts (
+    hash TEXT NOT NULL,
+    user_id INTEGER NOT NULL,
+    revealed_at TIMESTAMP NOT NULL,
+    submitted_at TIMESTAMP,
+    elapsed_seconds INTEGER,
+    official BOOLEAN NOT NULL DEFAULT FALSE,
+    timed BOOLEAN NOT NULL DEFAULT TRUE,
+
+    PRIMARY KEY (hash, user_id),
+    FOREIGN KEY(user_id) REFERENCES users(id) ON DELETE CASCADE
+);
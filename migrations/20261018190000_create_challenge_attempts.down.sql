@@ -0,0 +1,2 @@
+DROP TABLE challenge_attempts;
+ALTER TABLE blocks DROP COLUMN mean_of;
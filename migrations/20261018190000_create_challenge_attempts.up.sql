@@ -0,0 +1,14 @@
+-- Mean of N challenges: the block stores every solution and their rounded mean, the attempts
+-- are kept separately with the hash seeding their scramble
+ALTER TABLE blocks ADD COLUMN mean_of INTEGER;
+
+CREATE TABLE challenge_attempts (
+    block_hash TEXT NOT NULL,
+    attempt_index INTEGER NOT NULL,
+    hash TEXT NOT NULL,
+    solution TEXT NOT NULL,
+    solution_moves INTEGER NOT NULL,
+
+    PRIMARY KEY (block_hash, attempt_index),
+    FOREIGN KEY(block_hash) REFERENCES blocks(hash) ON DELETE CASCADE
+);
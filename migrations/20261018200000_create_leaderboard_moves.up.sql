@@ -0,0 +1,10 @@
+-- Per name and round histogram of solution lengths, so means of the best N solutions can be
+-- computed from aggregates
+CREATE TABLE leaderboard_moves (
+    name TEXT NOT NULL,
+    round_start DATE NOT NULL,
+    solution_moves INTEGER NOT NULL,
+    count INTEGER NOT NULL,
+
+    PRIMARY KEY (name, round_start, solution_moves)
+);
@@ -0,0 +1,253 @@
+use actix_web::{
+    HttpRequest,
+    cookie::{Cookie, SameSite, time::Duration as CookieDuration},
+};
+use argon2::{
+    Argon2,
+    password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
+};
+use chrono::{Duration, NaiveDateTime, Utc};
+use rand_core::{OsRng, RngCore};
+use serde::Serialize;
+use sqlx::{FromRow, SqlitePool};
+
+use crate::validation;
+
+pub const SESSION_COOKIE_NAME: &str = "session";
+const SESSION_DAYS: i64 = 30;
+const MIN_PASSWORD_LENGTH: usize = 8;
+
+#[derive(Debug, Clone, FromRow, Serialize)]
+pub struct User {
+    pub id: i64,
+    pub username: String,
+    #[serde(skip)]
+    pub password_hash: String,
+    pub created_at: Option<NaiveDateTime>,
+}
+
+impl User {
+    pub async fn create(
+        db: &SqlitePool,
+        username: &str,
+        password: &str,
+    ) -> Result<Self, sqlx::Error> {
+        let password_hash = hash_password(password);
+        let mut tx = db.begin().await?;
+        let user = sqlx::query_as::<_, User>(
+            "INSERT INTO users (username, password_hash) VALUES (?, ?)
+            RETURNING id, username, password_hash, created_at",
+        )
+        .bind(username)
+        .bind(password_hash)
+        .fetch_one(&mut *tx)
+        .await?;
+        tx.commit().await?;
+
+        Ok(user)
+    }
+
+    pub async fn find_by_username(
+        db: &SqlitePool,
+        username: &str,
+    ) -> Result<Option<Self>, sqlx::Error> {
+        // Usernames are stored in NFC, so a name typed with other code points finds its account
+        sqlx::query_as::<_, User>(
+            "SELECT id, username, password_hash, created_at FROM users WHERE username = ?",
+        )
+        .bind(validation::normalize(username))
+        .fetch_optional(db)
+        .await
+    }
+
+    // Find the user with the given credentials
+    pub async fn authenticate(
+        db: &SqlitePool,
+        username: &str,
+        password: &str,
+    ) -> Result<Option<Self>, sqlx::Error> {
+        Ok(Self::find_by_username(db, username)
+            .await?
+            .filter(|user| verify_password(&user.password_hash, password)))
+    }
+
+    // Fetch the user of an unexpired session
+    pub async fn find_by_session(
+        db: &SqlitePool,
+        token: &str,
+    ) -> Result<Option<Self>, sqlx::Error> {
+        sqlx::query_as::<_, User>(
+            "SELECT u.id, u.username, u.password_hash, u.created_at
+            FROM sessions s
+            JOIN users u ON u.id = s.user_id
+            WHERE s.token = ? AND s.expires_at > ?",
+        )
+        .bind(token)
+        .bind(Utc::now().naive_utc())
+        .fetch_optional(db)
+        .await
+    }
+
+    // Start a new session, returning its token
+    pub async fn create_session(&self, db: &SqlitePool) -> Result<String, sqlx::Error> {
+        let mut bytes = [0u8; 32];
+        OsRng.fill_bytes(&mut bytes);
+        let token = hex::encode(bytes);
+
+        sqlx::query("INSERT INTO sessions (token, user_id, expires_at) VALUES (?, ?, ?)")
+            .bind(&token)
+            .bind(self.id)
+            .bind(Utc::now().naive_utc() + Duration::days(SESSION_DAYS))
+            .execute(db)
+            .await?;
+
+        Ok(token)
+    }
+}
+
+pub async fn delete_session(db: &SqlitePool, token: &str) -> Result<(), sqlx::Error> {
+    sqlx::query("DELETE FROM sessions WHERE token = ?")
+        .bind(token)
+        .execute(db)
+        .await?;
+    Ok(())
+}
+
+pub fn hash_password(password: &str) -> String {
+    let salt = SaltString::generate(&mut OsRng);
+    Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .expect("Failed to hash password")
+        .to_string()
+}
+
+pub fn verify_password(password_hash: &str, password: &str) -> bool {
+    PasswordHash::new(password_hash)
+        .map(|hash| {
+            Argon2::default()
+                .verify_password(password.as_bytes(), &hash)
+                .is_ok()
+        })
+        .unwrap_or(false)
+}
+
+// Returns the username as it is stored, or a description of what is wrong with the
+// registration. Usernames are the names of their blocks, so they follow the same rules and
+// are limited to `max_length`, the maximum length of names.
+pub fn validate_registration(
+    username: &str,
+    password: &str,
+    max_length: usize,
+) -> Result<String, String> {
+    if username.is_empty() {
+        return Err(format!(
+            "Usernames must be between 1 and {} characters long.",
+            max_length
+        ));
+    }
+    let username = validation::check_field("username", username, max_length, false)
+        .map_err(|e| e.message())?;
+    if username.trim() != username {
+        return Err("Usernames can't start or end with whitespace.".to_string());
+    }
+    if password.chars().count() < MIN_PASSWORD_LENGTH {
+        return Err(format!(
+            "Passwords must be at least {} characters long.",
+            MIN_PASSWORD_LENGTH
+        ));
+    }
+    Ok(username)
+}
+
+pub fn session_cookie(token: &str) -> Cookie<'static> {
+    Cookie::build(SESSION_COOKIE_NAME, token.to_string())
+        .path("/")
+        .secure(true)
+        .http_only(true)
+        .same_site(SameSite::Lax)
+        .max_age(CookieDuration::days(SESSION_DAYS))
+        .finish()
+}
+
+pub fn removal_cookie() -> Cookie<'static> {
+    let mut cookie = Cookie::build(SESSION_COOKIE_NAME, "")
+        .path("/")
+        .secure(true)
+        .http_only(true)
+        .same_site(SameSite::Lax)
+        .finish();
+    cookie.make_removal();
+    cookie
+}
+
+// The logged in user of the request, if any
+pub async fn current_user(request: &HttpRequest, db: &SqlitePool) -> Option<User> {
+    let token = request.cookie(SESSION_COOKIE_NAME)?;
+    User::find_by_session(db, token.value())
+        .await
+        .ok()
+        .flatten()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_password_hashing() {
+        let hash = hash_password("correct horse");
+
+        assert!(hash.starts_with("$argon2"));
+        assert!(verify_password(&hash, "correct horse"));
+        assert!(!verify_password(&hash, "wrong horse"));
+        assert!(!verify_password("not a hash", "correct horse"));
+    }
+
+    #[test]
+    fn test_validate_registration() {
+        assert_eq!(
+            validate_registration("Alice", "long enough", 32),
+            Ok("Alice".to_string())
+        );
+        assert!(validate_registration("", "long enough", 32).is_err());
+        assert!(validate_registration(" Alice", "long enough", 32).is_err());
+        assert!(validate_registration("Alice", "short", 32).is_err());
+        assert!(validate_registration(&"a".repeat(33), "long enough", 32).is_err());
+        // Usernames can't be longer than the names of blocks
+        assert!(validate_registration("Alice", "long enough", 4).is_err());
+        assert!(validate_registration("Ali", "long enough", 4).is_ok());
+
+        // Usernames follow the rules of block names, and are stored in NFC
+        assert_eq!(
+            validate_registration("Jose\u{301}", "long enough", 32),
+            Ok("Jos\u{e9}".to_string())
+        );
+        assert!(validate_registration("Alice\u{202E}", "long enough", 32).is_err());
+        assert!(validate_registration("Al\u{7}ice", "long enough", 32).is_err());
+    }
+
+    #[sqlx::test]
+    async fn test_sessions(db: SqlitePool) {
+        let user = User::create(&db, "Alice", "password123").await.unwrap();
+
+        assert!(
+            User::authenticate(&db, "Alice", "password123")
+                .await
+                .unwrap()
+                .is_some()
+        );
+        assert!(
+            User::authenticate(&db, "Alice", "password")
+                .await
+                .unwrap()
+                .is_none()
+        );
+
+        let token = user.create_session(&db).await.unwrap();
+        let found = User::find_by_session(&db, &token).await.unwrap().unwrap();
+        assert_eq!(found.id, user.id);
+
+        delete_session(&db, &token).await.unwrap();
+        assert!(User::find_by_session(&db, &token).await.unwrap().is_none());
+    }
+}
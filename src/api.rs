@@ -0,0 +1,214 @@
+use actix_web::{HttpResponse, ResponseError, http::StatusCode, http::header};
+use serde::Serialize;
+use std::fmt::{Display, Formatter};
+use utoipa::ToSchema;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(tag = "error", rename_all = "snake_case")]
+pub enum ApiError {
+    InvalidRequest { detail: String },
+    MissingFields { fields: Vec<String> },
+    InvalidField { field: String, detail: String },
+    InvalidNotation { position: usize, found: char },
+    WrongSolution,
+    DuplicateSolution,
+    ParentNotEligible,
+    ParentNotFound,
+    BlockNotFound,
+    NameReserved,
+    InvalidSignature,
+    InvalidPublicKey,
+    NameTaken,
+    IdentityNotFound,
+    SeasonNotFound,
+    ReplayNotFound,
+    DuplicateBlock,
+    CommitmentRequired,
+    CommitRevealDisabled,
+    InvalidCommitment,
+    CommitmentNotFound,
+    CommitmentMismatch,
+    RoundNotClosed,
+    RateLimited { retry_after: u64 },
+    Internal,
+}
+
+impl ApiError {
+    // Human-readable explanation, also used for the HTML form responses
+    pub fn message(&self) -> String {
+        match self {
+            ApiError::InvalidRequest { detail } | ApiError::InvalidField { detail, .. } => {
+                detail.clone()
+            }
+            ApiError::MissingFields { .. } => "All fields are required.".to_string(),
+            ApiError::InvalidNotation { position, found } => format!(
+                "Invalid move notation: unexpected '{}' at position {}.",
+                found, position
+            ),
+            ApiError::WrongSolution => "Incorrect solution".to_string(),
+            ApiError::DuplicateSolution => "This solution already exists".to_string(),
+            ApiError::ParentNotEligible => {
+                "This block cannot be used as a parent for a new block.".to_string()
+            }
+            ApiError::ParentNotFound => "Parent block not found".to_string(),
+            ApiError::BlockNotFound => "Block not found".to_string(),
+            ApiError::NameReserved => {
+                "This name is reserved: blocks using it must be signed with its key or posted from its account.".to_string()
+            }
+            ApiError::InvalidSignature => {
+                "The signature does not match the key registered for this name.".to_string()
+            }
+            ApiError::InvalidPublicKey => {
+                "The public key must be a hex encoded ed25519 key.".to_string()
+            }
+            ApiError::NameTaken => {
+                "This name is already bound to a key or an account, or used by existing blocks.".to_string()
+            }
+            ApiError::IdentityNotFound => "No key is registered for this name.".to_string(),
+            ApiError::SeasonNotFound => "Season not found".to_string(),
+            ApiError::ReplayNotFound => {
+                "Replays are only available for blocks with a single solution.".to_string()
+            }
+            ApiError::DuplicateBlock => {
+                "A block with this parent, name and message already exists or is pending.".to_string()
+            }
+            ApiError::CommitmentRequired => {
+                "This round uses commit-reveal: commit to your solution now and reveal it after the round closes.".to_string()
+            }
+            ApiError::CommitRevealDisabled => {
+                "This round doesn't use commit-reveal: submit your solution directly.".to_string()
+            }
+            ApiError::InvalidCommitment => {
+                "The commitment must be a hex encoded SHA-256 hash.".to_string()
+            }
+            ApiError::CommitmentNotFound => "No pending block with this hash".to_string(),
+            ApiError::CommitmentMismatch => {
+                "The solution and salt don't match the commitment.".to_string()
+            }
+            ApiError::RoundNotClosed => {
+                "Solutions can only be revealed after the round closes.".to_string()
+            }
+            ApiError::RateLimited { retry_after } => format!(
+                "Too many requests. Please try again in {} seconds.",
+                retry_after
+            ),
+            ApiError::Internal => "Internal server error. Please try again later.".to_string(),
+        }
+    }
+}
+
+impl Display for ApiError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message())
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ApiErrorBody {
+    #[serde(flatten)]
+    pub error: ApiError,
+    pub message: String,
+}
+
+impl ResponseError for ApiError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ApiError::InvalidRequest { .. }
+            | ApiError::MissingFields { .. }
+            | ApiError::InvalidField { .. } => StatusCode::BAD_REQUEST,
+            ApiError::InvalidNotation { .. }
+            | ApiError::WrongSolution
+            | ApiError::ParentNotEligible
+            | ApiError::InvalidSignature
+            | ApiError::InvalidPublicKey
+            | ApiError::InvalidCommitment
+            | ApiError::CommitmentMismatch => StatusCode::UNPROCESSABLE_ENTITY,
+            ApiError::NameReserved => StatusCode::FORBIDDEN,
+            ApiError::DuplicateSolution
+            | ApiError::NameTaken
+            | ApiError::DuplicateBlock
+            | ApiError::CommitmentRequired
+            | ApiError::CommitRevealDisabled
+            | ApiError::RoundNotClosed => StatusCode::CONFLICT,
+            ApiError::ParentNotFound
+            | ApiError::BlockNotFound
+            | ApiError::IdentityNotFound
+            | ApiError::SeasonNotFound
+            | ApiError::ReplayNotFound
+            | ApiError::CommitmentNotFound => StatusCode::NOT_FOUND,
+            ApiError::RateLimited { .. } => StatusCode::TOO_MANY_REQUESTS,
+            ApiError::Internal => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let mut builder = HttpResponse::build(self.status_code());
+        if let ApiError::RateLimited { retry_after } = self {
+            builder.insert_header((header::RETRY_AFTER, *retry_after));
+        }
+        builder.json(ApiErrorBody {
+            error: self.clone(),
+            message: self.message(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_error_serialization() {
+        let error = ApiError::InvalidNotation {
+            position: 4,
+            found: 'w',
+        };
+        let body = serde_json::to_value(ApiErrorBody {
+            message: error.message(),
+            error,
+        })
+        .unwrap();
+
+        assert_eq!(body["error"], "invalid_notation");
+        assert_eq!(body["position"], 4);
+        assert_eq!(body["found"], "w");
+
+        assert_eq!(
+            ApiError::WrongSolution.status_code(),
+            StatusCode::UNPROCESSABLE_ENTITY
+        );
+
+        let body = serde_json::to_value(ApiErrorBody {
+            error: ApiError::DuplicateSolution,
+            message: ApiError::DuplicateSolution.message(),
+        })
+        .unwrap();
+        assert_eq!(body["error"], "duplicate_solution");
+        assert_eq!(body["message"], "This solution already exists");
+
+        // The explanation of invalid requests doesn't clash with the message of the body
+        let error = ApiError::InvalidRequest {
+            detail: "expected value".to_string(),
+        };
+        let body = serde_json::to_string(&ApiErrorBody {
+            message: error.message(),
+            error,
+        })
+        .unwrap();
+        assert_eq!(body.matches("\"message\"").count(), 1);
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&body).unwrap()["detail"],
+            "expected value"
+        );
+
+        // Internal errors are returned by every endpoint, not only block creation
+        assert_eq!(
+            ApiError::Internal.message(),
+            "Internal server error. Please try again later."
+        );
+
+        let resp = ApiError::RateLimited { retry_after: 30 }.error_response();
+        assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(resp.headers().get(header::RETRY_AFTER).unwrap(), "30");
+    }
+}
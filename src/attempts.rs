@@ -0,0 +1,210 @@
+use chrono::{Duration, NaiveDateTime};
+use serde::Serialize;
+use sqlx::{FromRow, SqlitePool};
+
+// Official FMC rules: one hour from seeing the scramble
+pub const TIME_LIMIT_SECONDS: i64 = 60 * 60;
+
+// An attempt at the scramble of a block, started when the scramble was first shown to the
+// user. The hash is that of (parent, name, message), so it is also the hash of the block.
+#[derive(Debug, Clone, FromRow, Serialize)]
+pub struct Attempt {
+    pub hash: String,
+    pub user_id: i64,
+    pub revealed_at: NaiveDateTime,
+    pub submitted_at: Option<NaiveDateTime>,
+    pub elapsed_seconds: Option<i64>,
+    // Timed attempts are official if submitted within the time limit
+    pub official: bool,
+    // The scramble was first shown with the timer running
+    pub timed: bool,
+}
+
+impl Attempt {
+    // Record the first reveal of a scramble, or return the existing attempt so reloading
+    // doesn't reset the clock. An attempt stays untimed if the scramble was first shown
+    // without the timer.
+    pub async fn start(
+        db: &SqlitePool,
+        hash: &str,
+        user_id: i64,
+        now: NaiveDateTime,
+        timed: bool,
+    ) -> Result<Self, sqlx::Error> {
+        sqlx::query(
+            "INSERT OR IGNORE INTO attempts (hash, user_id, revealed_at, timed) VALUES (?, ?, ?, ?)",
+        )
+        .bind(hash)
+        .bind(user_id)
+        .bind(now)
+        .bind(timed)
+        .execute(db)
+        .await?;
+        Ok(Self::find(db, hash, user_id)
+            .await?
+            .expect("Attempt should exist after inserting it"))
+    }
+
+    pub async fn find(
+        db: &SqlitePool,
+        hash: &str,
+        user_id: i64,
+    ) -> Result<Option<Self>, sqlx::Error> {
+        sqlx::query_as::<_, Attempt>(
+            "SELECT hash, user_id, revealed_at, submitted_at, elapsed_seconds, official, timed
+            FROM attempts
+            WHERE hash = ? AND user_id = ?",
+        )
+        .bind(hash)
+        .bind(user_id)
+        .fetch_optional(db)
+        .await
+    }
+
+    // The submitted attempt that produced a block, if it was timed
+    pub async fn find_submitted(db: &SqlitePool, hash: &str) -> Result<Option<Self>, sqlx::Error> {
+        sqlx::query_as::<_, Attempt>(
+            "SELECT hash, user_id, revealed_at, submitted_at, elapsed_seconds, official, timed
+            FROM attempts
+            WHERE hash = ? AND submitted_at IS NOT NULL AND timed",
+        )
+        .bind(hash)
+        .fetch_optional(db)
+        .await
+    }
+
+    // Record the submission of the block, if its scramble was revealed to the user
+    pub async fn finish(
+        db: &SqlitePool,
+        hash: &str,
+        user_id: i64,
+        now: NaiveDateTime,
+    ) -> Result<Option<Self>, sqlx::Error> {
+        let Some(attempt) = Self::find(db, hash, user_id).await? else {
+            return Ok(None);
+        };
+        if attempt.submitted_at.is_some() {
+            return Ok(Some(attempt));
+        }
+
+        let elapsed_seconds = (now - attempt.revealed_at).num_seconds();
+        sqlx::query(
+            "UPDATE attempts SET submitted_at = ?, elapsed_seconds = ?, official = ?
+            WHERE hash = ? AND user_id = ?",
+        )
+        .bind(now)
+        .bind(elapsed_seconds)
+        .bind(attempt.timed && elapsed_seconds <= TIME_LIMIT_SECONDS)
+        .bind(hash)
+        .bind(user_id)
+        .execute(db)
+        .await?;
+        Self::find(db, hash, user_id).await
+    }
+
+    pub fn deadline(&self) -> NaiveDateTime {
+        self.revealed_at + Duration::seconds(TIME_LIMIT_SECONDS)
+    }
+
+    // Seconds left to submit an official solution
+    pub fn remaining_seconds(&self, now: NaiveDateTime) -> i64 {
+        (self.deadline() - now).num_seconds().max(0)
+    }
+
+    pub fn elapsed_display(&self) -> String {
+        self.elapsed_seconds
+            .map(format_duration)
+            .unwrap_or_else(|| "-".to_string())
+    }
+}
+
+// Format seconds as m:ss, or h:mm:ss from an hour on
+pub fn format_duration(seconds: i64) -> String {
+    let (hours, minutes, seconds) = (seconds / 3600, seconds % 3600 / 60, seconds % 60);
+    if hours > 0 {
+        format!("{}:{:02}:{:02}", hours, minutes, seconds)
+    } else {
+        format!("{}:{:02}", minutes, seconds)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::accounts::User;
+
+    #[test]
+    fn test_format_duration() {
+        assert_eq!(format_duration(0), "0:00");
+        assert_eq!(format_duration(61), "1:01");
+        assert_eq!(format_duration(3600), "1:00:00");
+        assert_eq!(format_duration(3725), "1:02:05");
+    }
+
+    #[sqlx::test]
+    async fn test_attempts(db: SqlitePool) {
+        let user = User::create(&db, "Alice", "password123").await.unwrap();
+        let at = |s: &str| NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S").unwrap();
+
+        // Revealing the scramble again keeps the first reveal time
+        let attempt = Attempt::start(&db, "HASH", user.id, at("2025-01-06 10:00:00"), true)
+            .await
+            .unwrap();
+        let again = Attempt::start(&db, "HASH", user.id, at("2025-01-06 10:30:00"), false)
+            .await
+            .unwrap();
+        assert_eq!(again.revealed_at, attempt.revealed_at);
+        assert_eq!(again.remaining_seconds(at("2025-01-06 10:30:00")), 30 * 60);
+        assert_eq!(again.remaining_seconds(at("2025-01-06 12:00:00")), 0);
+
+        let finished = Attempt::finish(&db, "HASH", user.id, at("2025-01-06 10:59:59"))
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(finished.official);
+        assert_eq!(finished.elapsed_display(), "59:59");
+        assert!(
+            Attempt::find_submitted(&db, "HASH")
+                .await
+                .unwrap()
+                .is_some()
+        );
+
+        Attempt::start(&db, "LATE", user.id, at("2025-01-06 10:00:00"), true)
+            .await
+            .unwrap();
+        let late = Attempt::finish(&db, "LATE", user.id, at("2025-01-06 11:00:01"))
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(!late.official);
+
+        // Blocks whose scramble wasn't revealed to the user have no attempt
+        assert!(
+            Attempt::finish(&db, "UNSEEN", user.id, at("2025-01-06 10:00:00"))
+                .await
+                .unwrap()
+                .is_none()
+        );
+
+        // A scramble first shown without the timer stays untimed and is never official
+        let untimed = Attempt::start(&db, "UNTIMED", user.id, at("2025-01-06 10:00:00"), false)
+            .await
+            .unwrap();
+        let again = Attempt::start(&db, "UNTIMED", user.id, at("2025-01-06 10:10:00"), true)
+            .await
+            .unwrap();
+        assert!(!untimed.timed && !again.timed);
+        let finished = Attempt::finish(&db, "UNTIMED", user.id, at("2025-01-06 10:20:00"))
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(!finished.official);
+        assert!(
+            Attempt::find_submitted(&db, "UNTIMED")
+                .await
+                .unwrap()
+                .is_none()
+        );
+    }
+}
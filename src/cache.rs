@@ -4,6 +4,8 @@ use std::hash::Hash;
 use std::sync::{Arc, RwLock};
 use std::time::{Duration, Instant};
 
+use crate::metrics;
+
 #[derive(Debug, Clone)]
 struct CacheEntry<V> {
     value: V,
@@ -110,10 +112,11 @@ where
 
             if let Some(entry) = cache.get(key) {
                 // Check if the entry is expired
-                if let Some(expiry) = entry.expiry {
-                    if now > expiry {
-                        return Ok(None);
-                    }
+                if let Some(expiry) = entry.expiry
+                    && now > expiry
+                {
+                    metrics::record_cache_lookup(false);
+                    return Ok(None);
                 }
 
                 entry_to_return = Some(entry.value.clone());
@@ -129,6 +132,7 @@ where
             }
         }
 
+        metrics::record_cache_lookup(entry_to_return.is_some());
         Ok(entry_to_return)
     }
 
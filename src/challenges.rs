@@ -0,0 +1,141 @@
+use serde::Serialize;
+use sqlx::{FromRow, SqlitePool};
+use utoipa::ToSchema;
+
+use crate::cube::Move;
+use crate::utils::{calculate_hash, format_moves, scramble_from_hash};
+
+// FMC competitions rank by the mean of 3 attempts
+pub const MEAN_OF: u8 = 3;
+
+// Separates the solutions of the attempts in the solution of a challenge block
+pub const SOLUTION_SEPARATOR: &str = " / ";
+
+// One of the attempts of a mean of 3 challenge block
+#[derive(Debug, Clone, PartialEq, FromRow, Serialize, ToSchema)]
+pub struct ChallengeAttempt {
+    // Starts at 1
+    pub attempt_index: u8,
+    // Hash seeding the scramble of the attempt
+    pub hash: String,
+    pub solution: String,
+    pub solution_moves: u8,
+}
+
+impl ChallengeAttempt {
+    pub fn new(attempt_index: u8, hash: String, solution: &[Move]) -> Self {
+        ChallengeAttempt {
+            attempt_index,
+            hash,
+            solution: format_moves(solution),
+            solution_moves: solution.len() as u8,
+        }
+    }
+
+    pub async fn find_by_block(
+        db: &SqlitePool,
+        block_hash: &str,
+    ) -> Result<Vec<Self>, sqlx::Error> {
+        sqlx::query_as::<_, ChallengeAttempt>(
+            "SELECT attempt_index, hash, solution, solution_moves
+            FROM challenge_attempts
+            WHERE block_hash = ?
+            ORDER BY attempt_index",
+        )
+        .bind(block_hash)
+        .fetch_all(db)
+        .await
+    }
+
+    pub async fn exists(db: &SqlitePool, hash: &str) -> Result<bool, sqlx::Error> {
+        sqlx::query_scalar::<_, bool>(
+            "SELECT EXISTS(SELECT 1 FROM challenge_attempts WHERE hash = ?)",
+        )
+        .bind(hash)
+        .fetch_one(db)
+        .await
+    }
+
+    pub fn scramble(&self) -> String {
+        format_moves(&scramble_from_hash(&self.hash))
+    }
+}
+
+// The hash seeding the scramble of an attempt: like the block hash, with the attempt index.
+// The prefix keeps it from being the hash of a block whose message ends in "|<index>".
+pub fn attempt_hash(parent_hash: &str, name: &str, message: &str, attempt_index: u8) -> String {
+    calculate_hash(
+        format!(
+            "challenge|{}|{}|{}|{}",
+            parent_hash, name, message, attempt_index
+        )
+        .as_bytes(),
+    )
+}
+
+// Split the solution of a challenge block into the solutions of its attempts
+pub fn split_solutions(solution: &str) -> Vec<&str> {
+    solution.split('/').map(str::trim).collect()
+}
+
+pub fn join_solutions(attempts: &[ChallengeAttempt]) -> String {
+    attempts
+        .iter()
+        .map(|a| a.solution.as_str())
+        .collect::<Vec<_>>()
+        .join(SOLUTION_SEPARATOR)
+}
+
+// Mean move count, rounded to the nearest move as blocks store whole moves
+pub fn rounded_mean(moves: &[u8]) -> u8 {
+    let total = moves.iter().map(|m| *m as f64).sum::<f64>();
+    (total / moves.len().max(1) as f64).round() as u8
+}
+
+pub fn mean_display(attempts: &[ChallengeAttempt]) -> String {
+    let total = attempts
+        .iter()
+        .map(|a| a.solution_moves as f64)
+        .sum::<f64>();
+    format!("{:.2}", total / attempts.len().max(1) as f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::format_data;
+
+    #[test]
+    fn test_attempt_hash() {
+        let hashes = (1..=MEAN_OF)
+            .map(|i| attempt_hash("parent", "Alice", "message", i))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            hashes[0],
+            calculate_hash("challenge|parent|Alice|message|1".as_bytes())
+        );
+        assert_ne!(hashes[0], hashes[1]);
+        assert_ne!(hashes[1], hashes[2]);
+
+        // No attempt shares its scramble with a block
+        assert_ne!(
+            hashes[0],
+            calculate_hash(&format_data("parent", "Alice", "message|1"))
+        );
+    }
+
+    #[test]
+    fn test_solutions() {
+        assert_eq!(split_solutions("R U / F2 /D' L"), vec!["R U", "F2", "D' L"]);
+        assert_eq!(rounded_mean(&[20, 21, 21]), 21);
+        assert_eq!(rounded_mean(&[20, 20, 21]), 20);
+
+        let attempts = vec![
+            ChallengeAttempt::new(1, "A".to_string(), &[Move::R(1), Move::U(1)]),
+            ChallengeAttempt::new(2, "B".to_string(), &[Move::F(2)]),
+            ChallengeAttempt::new(3, "C".to_string(), &[Move::D(3)]),
+        ];
+        assert_eq!(join_solutions(&attempts), "R U / F2 / D'");
+        assert_eq!(mean_display(&attempts), "1.33");
+    }
+}
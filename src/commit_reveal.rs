@@ -0,0 +1,88 @@
+use chrono::{NaiveDate, NaiveDateTime};
+use sha2::{Digest, Sha256};
+use sqlx::SqlitePool;
+
+use crate::leaderboard::round_of;
+use crate::utils::start_of_week;
+
+// Whether solutions of the round starting on (or containing) the given date must be committed
+// to before they are revealed
+pub async fn is_enabled(db: &SqlitePool, round: NaiveDate) -> Result<bool, sqlx::Error> {
+    sqlx::query_scalar::<_, bool>(
+        "SELECT EXISTS(SELECT 1 FROM commit_reveal_rounds WHERE round_start = ?)",
+    )
+    .bind(round_of(round))
+    .fetch_one(db)
+    .await
+}
+
+pub async fn enable(db: &SqlitePool, round: NaiveDate) -> Result<NaiveDate, sqlx::Error> {
+    let round = round_of(round);
+    sqlx::query("INSERT OR IGNORE INTO commit_reveal_rounds (round_start) VALUES (?)")
+        .bind(round)
+        .execute(db)
+        .await?;
+    Ok(round)
+}
+
+// Hex encoded sha256(solution || salt), with the solution exactly as it will be revealed
+pub fn commitment(solution: &str, salt: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(solution.as_bytes());
+    hasher.update(salt.as_bytes());
+    hex::encode(hasher.finalize())
+}
+
+pub fn is_valid_commitment(commitment: &str) -> bool {
+    commitment.len() == 64 && commitment.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+// Solutions can be revealed once the round they were committed in has closed
+pub fn is_round_closed(committed_at: NaiveDateTime, now: NaiveDateTime) -> bool {
+    committed_at < start_of_week(now)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_commitment() {
+        let commitment = commitment("R U R'", "salt");
+        assert!(is_valid_commitment(&commitment));
+        assert_eq!(
+            commitment,
+            hex::encode(Sha256::digest("R U R'salt".as_bytes()))
+        );
+        assert!(!is_valid_commitment("abc"));
+        assert!(!is_valid_commitment(&"z".repeat(64)));
+    }
+
+    #[test]
+    fn test_is_round_closed() {
+        let at = |s: &str| NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S").unwrap();
+        // 2025-01-06 and 2025-01-13 are Mondays
+        let committed_at = at("2025-01-08 12:00:00");
+        assert!(!is_round_closed(committed_at, at("2025-01-12 23:59:59")));
+        assert!(is_round_closed(committed_at, at("2025-01-13 00:00:00")));
+    }
+
+    #[sqlx::test]
+    async fn test_enable(db: SqlitePool) {
+        let wednesday = NaiveDate::from_ymd_opt(2025, 1, 8).unwrap();
+        assert!(!is_enabled(&db, wednesday).await.unwrap());
+
+        let round = enable(&db, wednesday).await.unwrap();
+        assert_eq!(round, NaiveDate::from_ymd_opt(2025, 1, 6).unwrap());
+        assert!(is_enabled(&db, round).await.unwrap());
+        assert!(is_enabled(&db, wednesday).await.unwrap());
+        assert!(
+            !is_enabled(&db, NaiveDate::from_ymd_opt(2025, 1, 13).unwrap())
+                .await
+                .unwrap()
+        );
+
+        // Enabling twice is fine
+        enable(&db, round).await.unwrap();
+    }
+}
@@ -1,13 +1,117 @@
+use actix_web::cookie::Key;
 use dotenv::dotenv;
 use std::env;
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogFormat {
+    Text,
+    Json,
+}
+
+impl std::str::FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(format!("Unknown log format: {}", s)),
+        }
+    }
+}
+
+// Whether other sites may show the pages in a frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameOptions {
+    Deny,
+    SameOrigin,
+}
+
+impl std::str::FromStr for FrameOptions {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "deny" => Ok(FrameOptions::Deny),
+            "sameorigin" => Ok(FrameOptions::SameOrigin),
+            _ => Err(format!("Unknown frame options: {}", s)),
+        }
+    }
+}
+
+// Key signing and encrypting cookies, derived from SECRET_KEY. Without one a random key is
+// used, so cookies don't survive restarts and aren't shared between replicas.
+#[derive(Clone)]
+pub struct SecretKey {
+    key: Key,
+    generated: bool,
+}
+
+impl SecretKey {
+    pub const MIN_LENGTH: usize = 32;
+
+    pub fn derive_from(secret: &str) -> Result<Self, String> {
+        if secret.len() < Self::MIN_LENGTH {
+            return Err(format!(
+                "The secret key must be at least {} bytes long",
+                Self::MIN_LENGTH
+            ));
+        }
+        Ok(SecretKey {
+            key: Key::derive_from(secret.as_bytes()),
+            generated: false,
+        })
+    }
+
+    pub fn generate() -> Self {
+        SecretKey {
+            key: Key::generate(),
+            generated: true,
+        }
+    }
+
+    pub fn key(&self) -> &Key {
+        &self.key
+    }
+
+    pub fn is_generated(&self) -> bool {
+        self.generated
+    }
+}
+
+// The key itself is never printed
+impl std::fmt::Debug for SecretKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SecretKey")
+            .field("generated", &self.generated)
+            .finish_non_exhaustive()
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Config {
     pub host: String,
     pub port: u16,
+    // Port of the server answering `/metrics`, kept off the public port
+    pub metrics_port: u16,
     pub static_dir: String,
     pub database_url: String,
     pub cloudflare_code: Option<String>,
+    pub scoring_points: f64,
+    pub scoring_main_chain_bonus: f64,
+    pub scoring_trivial_penalty: f64,
+    pub log_format: LogFormat,
+    pub rate_limit_burst: u32,
+    pub rate_limit_per_minute: f64,
+    pub trust_proxy_headers: bool,
+    pub secret_key: SecretKey,
+    pub hsts_max_age: u64,
+    pub frame_options: FrameOptions,
+    pub referrer_policy: String,
+    pub csp_report_only: bool,
+    pub max_name_length: usize,
+    pub max_message_length: usize,
+    pub max_description_length: usize,
 }
 
 impl Config {
@@ -20,9 +124,64 @@ impl Config {
                 .unwrap_or_else(|_| "8080".to_string())
                 .parse()
                 .unwrap_or(8080),
+            metrics_port: env_or("METRICS_PORT", 9090),
             static_dir: env::var("STATIC_DIR").unwrap_or_else(|_| "/static".to_string()),
             database_url: env::var("DATABASE_URL").expect("DATABASE_URL must be set"),
             cloudflare_code: env::var("CLOUDFLARE_CODE").ok(),
+            scoring_points: env_or("SCORING_POINTS", 100.0),
+            scoring_main_chain_bonus: env_or("SCORING_MAIN_CHAIN_BONUS", 25.0),
+            scoring_trivial_penalty: env_or("SCORING_TRIVIAL_PENALTY", 50.0),
+            log_format: env_or("LOG_FORMAT", LogFormat::Text),
+            rate_limit_burst: env_or("RATE_LIMIT_BURST", 10),
+            rate_limit_per_minute: env_or("RATE_LIMIT_PER_MINUTE", 2.0),
+            trust_proxy_headers: env_or("TRUST_PROXY_HEADERS", false),
+            secret_key: match env::var("SECRET_KEY") {
+                Ok(secret) => SecretKey::derive_from(&secret).expect("Invalid SECRET_KEY"),
+                Err(_) => SecretKey::generate(),
+            },
+            hsts_max_age: env_or("HSTS_MAX_AGE", 31_536_000),
+            frame_options: env_or("FRAME_OPTIONS", FrameOptions::Deny),
+            referrer_policy: env::var("REFERRER_POLICY")
+                .unwrap_or_else(|_| "strict-origin-when-cross-origin".to_string()),
+            csp_report_only: env_or("CSP_REPORT_ONLY", false),
+            max_name_length: env_or("MAX_NAME_LENGTH", 32),
+            max_message_length: env_or("MAX_MESSAGE_LENGTH", 280),
+            max_description_length: env_or("MAX_DESCRIPTION_LENGTH", 2000),
         }
     }
 }
+
+fn env_or<T: std::str::FromStr>(key: &str, default: T) -> T {
+    env::var(key)
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(default)
+}
+
+// Configuration for the handler tests
+#[cfg(test)]
+pub fn test_config() -> Config {
+    Config {
+        host: "127.0.0.1".to_string(),
+        port: 8080,
+        metrics_port: 9090,
+        static_dir: "/static".to_string(),
+        database_url: "sqlite::memory:".to_string(),
+        cloudflare_code: None,
+        scoring_points: 100.0,
+        scoring_main_chain_bonus: 25.0,
+        scoring_trivial_penalty: 50.0,
+        log_format: LogFormat::Text,
+        rate_limit_burst: 10,
+        rate_limit_per_minute: 2.0,
+        trust_proxy_headers: false,
+        secret_key: SecretKey::generate(),
+        hsts_max_age: 31_536_000,
+        frame_options: FrameOptions::Deny,
+        referrer_policy: "strict-origin-when-cross-origin".to_string(),
+        csp_report_only: false,
+        max_name_length: 32,
+        max_message_length: 280,
+        max_description_length: 2000,
+    }
+}
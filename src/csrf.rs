@@ -0,0 +1,237 @@
+use actix_web::body::{BoxBody, MessageBody};
+use actix_web::cookie::{Cookie, CookieJar, SameSite};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::Method;
+use actix_web::middleware::Next;
+use actix_web::{HttpMessage, HttpRequest, ResponseError, web};
+use rand_core::{OsRng, RngCore};
+
+use crate::accounts;
+use crate::config::Config;
+use crate::errors::AppError;
+use crate::messages::FlashMessage;
+
+pub const CSRF_COOKIE_NAME: &str = "csrf";
+// HTMX sends the token with every request through `hx-headers` on the page body
+pub const CSRF_HEADER: &str = "x-csrf-token";
+
+// Token of the request, available to handlers as a request extension
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CsrfToken(pub String);
+
+// Token to embed in the page, empty when the middleware doesn't run
+pub fn token(request: &HttpRequest) -> String {
+    request
+        .extensions()
+        .get::<CsrfToken>()
+        .map(|t| t.0.clone())
+        .unwrap_or_default()
+}
+
+fn new_token() -> String {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    hex::encode(bytes)
+}
+
+// The token is signed so it can't be planted through a cookie set by a sibling domain
+fn signed_token(config: &Config, request: &ServiceRequest) -> Option<String> {
+    let mut jar = CookieJar::new();
+    jar.add_original(request.cookie(CSRF_COOKIE_NAME)?);
+    let cookie = jar.signed(config.secret_key.key()).get(CSRF_COOKIE_NAME)?;
+    Some(cookie.value().to_string())
+}
+
+fn token_cookie(config: &Config, token: &str) -> Option<Cookie<'static>> {
+    let mut jar = CookieJar::new();
+    jar.signed_mut(config.secret_key.key()).add(
+        Cookie::build(CSRF_COOKIE_NAME, token.to_string())
+            .path("/")
+            .http_only(true)
+            .same_site(SameSite::Lax)
+            .finish(),
+    );
+    jar.get(CSRF_COOKIE_NAME).cloned()
+}
+
+// Compare without returning early, so the time taken doesn't reveal the token
+fn tokens_match(a: &str, b: &str) -> bool {
+    a.len() == b.len()
+        && a.bytes()
+            .zip(b.bytes())
+            .fold(0, |acc, (x, y)| acc | (x ^ y))
+            == 0
+}
+
+// The JSON API only needs the token when the request carries a session cookie, as other
+// sites could otherwise commit or post blocks for the logged in user
+fn is_protected(request: &ServiceRequest) -> bool {
+    !matches!(
+        *request.method(),
+        Method::GET | Method::HEAD | Method::OPTIONS
+    ) && (!request.path().starts_with("/api/")
+        || request.cookie(accounts::SESSION_COOKIE_NAME).is_some())
+}
+
+// Middleware rejecting form submissions that don't carry the token of the cookie. Requests
+// without a cookie get a new token, which is set on the response. It runs inside
+// `flash_messages`, which shows why a submission was rejected.
+pub async fn protect(
+    request: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, actix_web::Error> {
+    let config = request
+        .app_data::<web::Data<Config>>()
+        .cloned()
+        .ok_or_else(|| actix_web::error::ErrorInternalServerError("Config is not available"))?;
+
+    let existing = signed_token(&config, &request);
+    let token = existing.clone().unwrap_or_else(new_token);
+
+    if is_protected(&request) {
+        let submitted = request
+            .headers()
+            .get(CSRF_HEADER)
+            .and_then(|h| h.to_str().ok());
+        let valid = matches!(
+            (&existing, submitted),
+            (Some(expected), Some(submitted)) if tokens_match(expected, submitted)
+        );
+        if !valid {
+            tracing::info!(path = %request.path(), "rejected request without a valid CSRF token");
+            let error = AppError::Forbidden(
+                "This form has expired. Please reload the page and try again.".to_string(),
+            );
+            let response = FlashMessage::error(&error.message()).set(error.error_response());
+            let response = request.into_response(response);
+            return with_token_cookie(&config, response, existing.is_none(), &token);
+        }
+    }
+
+    request.extensions_mut().insert(CsrfToken(token.clone()));
+    let response = next.call(request).await?.map_into_boxed_body();
+    with_token_cookie(&config, response, existing.is_none(), &token)
+}
+
+fn with_token_cookie(
+    config: &Config,
+    mut response: ServiceResponse<BoxBody>,
+    is_new: bool,
+    token: &str,
+) -> Result<ServiceResponse<BoxBody>, actix_web::Error> {
+    if is_new && let Some(cookie) = token_cookie(config, token) {
+        response.response_mut().add_cookie(&cookie)?;
+    }
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::test_config;
+    use actix_web::http::StatusCode;
+    use actix_web::{App, HttpResponse, get, middleware::from_fn, post, test};
+
+    #[get("/form")]
+    async fn form(request: HttpRequest) -> HttpResponse {
+        HttpResponse::Ok().body(token(&request))
+    }
+
+    #[post("/form")]
+    async fn submit_form() -> HttpResponse {
+        HttpResponse::Ok().body("submitted")
+    }
+
+    #[post("/api/v1/blocks")]
+    async fn api() -> HttpResponse {
+        HttpResponse::Created().finish()
+    }
+
+    #[actix_web::test]
+    async fn test_protect() {
+        let app = test::init_service(
+            App::new()
+                .wrap(from_fn(protect))
+                .app_data(web::Data::new(test_config()))
+                .service(form)
+                .service(submit_form)
+                .service(api),
+        )
+        .await;
+
+        // Pages hand out a token along with its cookie
+        let resp =
+            test::call_service(&app, test::TestRequest::get().uri("/form").to_request()).await;
+        let cookie = resp
+            .response()
+            .cookies()
+            .find(|c| c.name() == CSRF_COOKIE_NAME)
+            .unwrap()
+            .into_owned();
+        assert!(cookie.http_only().unwrap());
+        let token = String::from_utf8(test::read_body(resp).await.to_vec()).unwrap();
+        assert_eq!(token.len(), 64);
+
+        // The same token is used while the cookie lasts
+        let resp = test::call_service(
+            &app,
+            test::TestRequest::get()
+                .uri("/form")
+                .cookie(cookie.clone())
+                .to_request(),
+        )
+        .await;
+        assert!(resp.response().cookies().next().is_none());
+        assert_eq!(test::read_body(resp).await, token.as_str());
+
+        let submit = |cookie: Option<&Cookie<'static>>, header: Option<&str>| {
+            let mut request = test::TestRequest::post().uri("/form");
+            if let Some(cookie) = cookie {
+                request = request.cookie(cookie.clone());
+            }
+            if let Some(header) = header {
+                request = request.insert_header((CSRF_HEADER, header));
+            }
+            request.to_request()
+        };
+        let resp = test::call_service(&app, submit(Some(&cookie), Some(&token))).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        // Cross-site submissions have no token, or not the one of the cookie
+        let resp = test::call_service(&app, submit(Some(&cookie), None)).await;
+        assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+        let resp = test::call_service(&app, submit(None, Some(&token))).await;
+        assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+        let resp = test::call_service(&app, submit(Some(&cookie), Some(&new_token()))).await;
+        assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+
+        // Unsigned cookies aren't trusted
+        let planted = Cookie::new(CSRF_COOKIE_NAME, "planted");
+        let resp = test::call_service(&app, submit(Some(&planted), Some("planted"))).await;
+        assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+
+        // The JSON API doesn't need a token, unless it is used with a session
+        let resp = test::call_service(
+            &app,
+            test::TestRequest::post().uri("/api/v1/blocks").to_request(),
+        )
+        .await;
+        assert_eq!(resp.status(), StatusCode::CREATED);
+        let session = Cookie::new(accounts::SESSION_COOKIE_NAME, "session token");
+        let api_submit = |header: Option<&str>| {
+            let mut request = test::TestRequest::post()
+                .uri("/api/v1/blocks")
+                .cookie(session.clone())
+                .cookie(cookie.clone());
+            if let Some(header) = header {
+                request = request.insert_header((CSRF_HEADER, header));
+            }
+            request.to_request()
+        };
+        // A cross-site request carries the cookies, but can't read the token
+        let resp = test::call_service(&app, api_submit(None)).await;
+        assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+        let resp = test::call_service(&app, api_submit(Some(&token))).await;
+        assert_eq!(resp.status(), StatusCode::CREATED);
+    }
+}
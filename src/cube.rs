@@ -1,4 +1,7 @@
-use std::fmt::{Display, Formatter};
+use serde::Serialize;
+use std::fmt::{Display, Formatter, Write};
+use std::sync::OnceLock;
+use utoipa::ToSchema;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Move {
@@ -57,3 +60,382 @@ impl Move {
         }
     }
 }
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Face {
+    U,
+    R,
+    F,
+    D,
+    L,
+    B,
+}
+
+impl Face {
+    pub const ALL: [Face; 6] = [Face::U, Face::R, Face::F, Face::D, Face::L, Face::B];
+
+    pub fn letter(&self) -> char {
+        match self {
+            Face::U => 'U',
+            Face::R => 'R',
+            Face::F => 'F',
+            Face::D => 'D',
+            Face::L => 'L',
+            Face::B => 'B',
+        }
+    }
+
+    // Colour scheme with white on top and green in front
+    pub fn colour(&self) -> &'static str {
+        match self {
+            Face::U => "#ffffff",
+            Face::R => "#d32f2f",
+            Face::F => "#2e9e48",
+            Face::D => "#ffd500",
+            Face::L => "#ff8c00",
+            Face::B => "#1565c0",
+        }
+    }
+
+    // Outward normal, with x pointing right, y up and z to the front
+    fn normal(&self) -> [i8; 3] {
+        match self {
+            Face::U => [0, 1, 0],
+            Face::R => [1, 0, 0],
+            Face::F => [0, 0, 1],
+            Face::D => [0, -1, 0],
+            Face::L => [-1, 0, 0],
+            Face::B => [0, 0, -1],
+        }
+    }
+
+    // Position of the sticker in `row` and `col` as seen in the unfolded net
+    fn sticker_position(&self, row: i8, col: i8) -> [i8; 3] {
+        match self {
+            Face::U => [col - 1, 1, row - 1],
+            Face::R => [1, 1 - row, 1 - col],
+            Face::F => [col - 1, 1 - row, 1],
+            Face::D => [col - 1, -1, 1 - row],
+            Face::L => [-1, 1 - row, col - 1],
+            Face::B => [1 - col, 1 - row, -1],
+        }
+    }
+}
+
+impl Move {
+    fn face(&self) -> (Face, u8) {
+        match *self {
+            Move::U(n) => (Face::U, n),
+            Move::D(n) => (Face::D, n),
+            Move::L(n) => (Face::L, n),
+            Move::R(n) => (Face::R, n),
+            Move::F(n) => (Face::F, n),
+            Move::B(n) => (Face::B, n),
+        }
+    }
+}
+
+fn cross(a: [i8; 3], b: [i8; 3]) -> [i8; 3] {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+fn dot(a: [i8; 3], b: [i8; 3]) -> i8 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+// Rotate a vector a quarter turn clockwise when looking at the face with normal `axis`
+fn rotate_clockwise(v: [i8; 3], axis: [i8; 3]) -> [i8; 3] {
+    let c = cross(axis, v);
+    let d = dot(axis, v);
+    [axis[0] * d - c[0], axis[1] * d - c[1], axis[2] * d - c[2]]
+}
+
+// Sticker positions and normals, indexed like the facelets of a `CubeState`
+fn stickers() -> Vec<([i8; 3], [i8; 3])> {
+    Face::ALL
+        .iter()
+        .flat_map(|face| (0..9).map(move |i| (face.sticker_position(i / 3, i % 3), face.normal())))
+        .collect()
+}
+
+// For every face, the destination of each facelet after a clockwise quarter turn
+fn quarter_turns() -> &'static [[usize; 54]; 6] {
+    static TURNS: OnceLock<[[usize; 54]; 6]> = OnceLock::new();
+    TURNS.get_or_init(|| {
+        let stickers = stickers();
+        let mut turns = [[0; 54]; 6];
+        for (f, face) in Face::ALL.iter().enumerate() {
+            let axis = face.normal();
+            for (i, &(position, normal)) in stickers.iter().enumerate() {
+                let target = if dot(position, axis) == 1 {
+                    (
+                        rotate_clockwise(position, axis),
+                        rotate_clockwise(normal, axis),
+                    )
+                } else {
+                    (position, normal)
+                };
+                turns[f][i] = stickers
+                    .iter()
+                    .position(|&s| s == target)
+                    .expect("Rotated sticker should exist");
+            }
+        }
+        turns
+    })
+}
+
+// Facelet representation of a cube, in U, R, F, D, L, B order with each face read row by row
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CubeState {
+    facelets: [Face; 54],
+}
+
+impl Default for CubeState {
+    fn default() -> Self {
+        let mut facelets = [Face::U; 54];
+        for (i, facelet) in facelets.iter_mut().enumerate() {
+            *facelet = Face::ALL[i / 9];
+        }
+        Self { facelets }
+    }
+}
+
+impl CubeState {
+    pub fn from_moves(moves: &[Move]) -> Self {
+        let mut state = Self::default();
+        state.apply_moves(moves);
+        state
+    }
+
+    pub fn apply_move(&mut self, m: &Move) {
+        let (face, count) = m.face();
+        let face_index = Face::ALL.iter().position(|&f| f == face).unwrap();
+        let turn = &quarter_turns()[face_index];
+        for _ in 0..count {
+            let mut facelets = self.facelets;
+            for (from, &to) in turn.iter().enumerate() {
+                facelets[to] = self.facelets[from];
+            }
+            self.facelets = facelets;
+        }
+    }
+
+    pub fn apply_moves(&mut self, moves: &[Move]) {
+        for m in moves {
+            self.apply_move(m);
+        }
+    }
+
+    pub fn is_solved(&self) -> bool {
+        *self == Self::default()
+    }
+
+    pub fn facelets(&self) -> &[Face; 54] {
+        &self.facelets
+    }
+
+    // Facelets as a string of face letters, e.g. "UUUUUUUUURRRRRRRRR..." when solved
+    pub fn facelet_string(&self) -> String {
+        self.facelets.iter().map(|f| f.letter()).collect()
+    }
+
+    // Top-left corner of each facelet in the unfolded net: U above F, then L, F, R and B in a
+    // row and D below F
+    pub fn net_positions(sticker_size: u32) -> Vec<(u32, u32)> {
+        let offsets = [(1, 0), (2, 1), (1, 1), (1, 2), (0, 1), (3, 1)];
+        let face_size = sticker_size * 3;
+        (0..54u32)
+            .map(|i| {
+                let (face_col, face_row) = offsets[i as usize / 9];
+                (
+                    1 + face_col * face_size + (i % 3) * sticker_size,
+                    1 + face_row * face_size + (i % 9 / 3) * sticker_size,
+                )
+            })
+            .collect()
+    }
+
+    // Width and height of the unfolded net
+    pub fn net_size(sticker_size: u32) -> (u32, u32) {
+        (sticker_size * 12 + 2, sticker_size * 9 + 2)
+    }
+
+    pub fn to_svg(&self, sticker_size: u32) -> String {
+        let (width, height) = Self::net_size(sticker_size);
+
+        let mut svg = String::new();
+        let _ = write!(
+            svg,
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="{width}" height="{height}" viewBox="0 0 {width} {height}">"#
+        );
+        let positions = Self::net_positions(sticker_size);
+        for (facelet, (x, y)) in self.facelets.iter().zip(positions) {
+            let _ = write!(
+                svg,
+                r##"<rect x="{x}" y="{y}" width="{sticker_size}" height="{sticker_size}" fill="{}" stroke="#000000" stroke-width="1"/>"##,
+                facelet.colour()
+            );
+        }
+        svg.push_str("</svg>");
+        svg
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, ToSchema)]
+pub struct ReplayStep {
+    // The move leading to this state, absent for the scrambled starting state
+    #[serde(rename = "move")]
+    pub move_notation: Option<String>,
+    pub facelets: String,
+    // Label of the annotated step which ends with this move
+    pub step_end: Option<String>,
+}
+
+// Cube states from the scrambled cube through every move of the solution
+pub fn replay(
+    scramble: &[Move],
+    solution: &[Move],
+    step_ends: &[(usize, String)],
+) -> Vec<ReplayStep> {
+    let mut state = CubeState::from_moves(scramble);
+    let mut steps = vec![ReplayStep {
+        move_notation: None,
+        facelets: state.facelet_string(),
+        step_end: None,
+    }];
+
+    for (i, m) in solution.iter().enumerate() {
+        state.apply_move(m);
+        steps.push(ReplayStep {
+            move_notation: Some(m.to_string()),
+            facelets: state.facelet_string(),
+            step_end: step_ends
+                .iter()
+                .find(|(end, _)| *end == i + 1)
+                .map(|(_, label)| label.clone()),
+        });
+    }
+
+    steps
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::{format_moves, parse_moves, scramble_from_hash, verify_solution};
+
+    #[test]
+    fn test_single_moves() {
+        let state = CubeState::from_moves(&[Move::U(1)]);
+        // The front's top row comes from the right face
+        assert_eq!(&state.facelet_string()[18..27], "RRRFFFFFF");
+        assert_eq!(&state.facelet_string()[36..45], "FFFLLLLLL");
+
+        let state = CubeState::from_moves(&[Move::R(1)]);
+        // The up face's right column comes from the front
+        assert_eq!(&state.facelet_string()[0..9], "UUFUUFUUF");
+
+        for m in [
+            Move::U(1),
+            Move::D(1),
+            Move::L(1),
+            Move::R(1),
+            Move::F(1),
+            Move::B(1),
+        ] {
+            let mut state = CubeState::default();
+            state.apply_moves(&[m, m, m]);
+            assert_eq!(state, CubeState::from_moves(&[m.inverse()]));
+            state.apply_move(&m);
+            assert!(state.is_solved(), "{} four times should solve the cube", m);
+        }
+    }
+
+    #[test]
+    fn test_sexy_move_order() {
+        let sexy = parse_moves("R U R' U'");
+        let mut state = CubeState::default();
+        for i in 1..=6 {
+            state.apply_moves(&sexy);
+            assert_eq!(state.is_solved(), i == 6);
+        }
+    }
+
+    #[test]
+    fn test_matches_verify_solution() {
+        // Whether the solution solves the scramble, by the cube state
+        let solves = |scramble: &[Move], solution: &[Move]| {
+            let mut state = CubeState::from_moves(scramble);
+            state.apply_moves(solution);
+            state.is_solved()
+        };
+
+        let scramble =
+            parse_moves("R' U' F L2 B2 L2 F2 D U L2 U F2 U' R D2 U' F' L D2 F D' U2 B2 U' R' U' F");
+        let solution = parse_moves("D L' U F2 B2 L2 U2 B2 D' L2 F2 U2 B2 F' U' L2 F2 U B' U R D'");
+        assert!(!solves(&scramble, &[]));
+        assert!(solves(&scramble, &solution));
+        assert!(verify_solution(&scramble, &solution));
+
+        for hash in 0..200 {
+            let scramble = scramble_from_hash(&format!("{:0>18}", hash));
+            let inverse = scramble
+                .iter()
+                .rev()
+                .map(|m| m.inverse())
+                .collect::<Vec<_>>();
+            let other = scramble_from_hash(&format!("{:0>18}", hash + 1000));
+            let mut extra = inverse.clone();
+            extra.push(Move::R(1));
+
+            for solution in [
+                inverse.clone(),
+                inverse[1..].to_vec(),
+                inverse[..inverse.len() - 1].to_vec(),
+                extra,
+                other,
+            ] {
+                assert_eq!(
+                    solves(&scramble, &solution),
+                    verify_solution(&scramble, &solution),
+                    "{} solved by {}",
+                    format_moves(&scramble),
+                    format_moves(&solution)
+                );
+            }
+            assert!(solves(&scramble, &inverse));
+            assert!(!solves(&scramble, &inverse[1..]));
+        }
+    }
+
+    #[test]
+    fn test_replay() {
+        let scramble = parse_moves("R U");
+        let solution = parse_moves("U' R'");
+        let steps = replay(&scramble, &solution, &[(1, "Cross".to_string())]);
+
+        assert_eq!(steps.len(), 3);
+        assert_eq!(steps[0].move_notation, None);
+        assert_eq!(
+            steps[0].facelets,
+            CubeState::from_moves(&scramble).facelet_string()
+        );
+        assert_eq!(steps[1].move_notation.as_deref(), Some("U'"));
+        assert_eq!(steps[1].step_end.as_deref(), Some("Cross"));
+        assert_eq!(steps[2].step_end, None);
+        assert_eq!(steps[2].facelets, CubeState::default().facelet_string());
+    }
+
+    #[test]
+    fn test_to_svg() {
+        let svg = CubeState::default().to_svg(10);
+        assert!(svg.starts_with("<svg"));
+        assert_eq!(svg.matches("<rect").count(), 54);
+        assert_eq!(svg.matches("#2e9e48").count(), 9);
+    }
+}
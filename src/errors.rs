@@ -0,0 +1,247 @@
+use actix_web::body::{BoxBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::{StatusCode, header};
+use actix_web::middleware::Next;
+use actix_web::{HttpRequest, HttpResponse, HttpResponseBuilder, ResponseError};
+use askama::Template;
+use serde::Serialize;
+use std::fmt::{Display, Formatter};
+
+use crate::cache::CacheError;
+use crate::rate_limit::RateLimited;
+use crate::utils::is_htmx_request;
+
+// Failures of the HTML handlers. The JSON API reports its own errors with `ApiError`.
+#[derive(Debug)]
+pub enum AppError {
+    Db(sqlx::Error),
+    NotFound(String),
+    BadRequest(String),
+    Forbidden(String),
+    Cache(CacheError),
+    Template(askama::Error),
+    RateLimited(RateLimited),
+}
+
+#[derive(Serialize)]
+struct AppErrorBody<'a> {
+    error: &'a str,
+    message: String,
+}
+
+#[derive(Template)]
+#[template(source = "{{ message }}", ext = "html")]
+struct AppErrorTemplate {
+    message: String,
+}
+
+impl AppError {
+    pub fn not_found(message: &str) -> Self {
+        AppError::NotFound(message.to_string())
+    }
+
+    pub fn bad_request(message: &str) -> Self {
+        AppError::BadRequest(message.to_string())
+    }
+
+    pub fn kind(&self) -> &'static str {
+        match self {
+            AppError::Db(_) => "database",
+            AppError::NotFound(_) => "not_found",
+            AppError::BadRequest(_) => "bad_request",
+            AppError::Forbidden(_) => "forbidden",
+            AppError::Cache(_) => "cache",
+            AppError::Template(_) => "template",
+            AppError::RateLimited(_) => "rate_limited",
+        }
+    }
+
+    // Shown to the user, so internal failures don't leak any details
+    pub fn message(&self) -> String {
+        match self {
+            AppError::NotFound(message)
+            | AppError::BadRequest(message)
+            | AppError::Forbidden(message) => message.clone(),
+            AppError::RateLimited(limited) => format!(
+                "Too many requests. Please try again in {} seconds.",
+                limited.retry_after_secs()
+            ),
+            AppError::Db(_) | AppError::Cache(_) | AppError::Template(_) => {
+                "Something went wrong. Please try again later.".to_string()
+            }
+        }
+    }
+
+    // Status of the error, with when to retry for rate limited requests
+    fn response_builder(&self) -> HttpResponseBuilder {
+        let mut builder = HttpResponse::build(self.status_code());
+        if let AppError::RateLimited(limited) = self {
+            builder.insert_header((header::RETRY_AFTER, limited.retry_after_secs()));
+        }
+        builder
+    }
+
+    pub fn html_response(&self) -> HttpResponse {
+        let message = self.message();
+        let body = AppErrorTemplate {
+            message: message.clone(),
+        }
+        .render()
+        .unwrap_or(message);
+        self.response_builder()
+            .content_type("text/html; charset=utf-8")
+            .body(body)
+    }
+
+    pub fn json_response(&self) -> HttpResponse {
+        self.response_builder().json(AppErrorBody {
+            error: self.kind(),
+            message: self.message(),
+        })
+    }
+}
+
+impl Display for AppError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AppError::Db(e) => write!(f, "Database error: {}", e),
+            AppError::NotFound(message) => write!(f, "Not found: {}", message),
+            AppError::BadRequest(message) => write!(f, "Bad request: {}", message),
+            AppError::Forbidden(message) => write!(f, "Forbidden: {}", message),
+            AppError::Cache(e) => write!(f, "Cache error: {:?}", e),
+            AppError::Template(e) => write!(f, "Template error: {}", e),
+            AppError::RateLimited(limited) => {
+                write!(f, "Rate limited for {:?}", limited.retry_after)
+            }
+        }
+    }
+}
+
+impl From<sqlx::Error> for AppError {
+    fn from(e: sqlx::Error) -> Self {
+        AppError::Db(e)
+    }
+}
+
+impl From<CacheError> for AppError {
+    fn from(e: CacheError) -> Self {
+        AppError::Cache(e)
+    }
+}
+
+impl From<askama::Error> for AppError {
+    fn from(e: askama::Error) -> Self {
+        AppError::Template(e)
+    }
+}
+
+impl From<RateLimited> for AppError {
+    fn from(limited: RateLimited) -> Self {
+        AppError::RateLimited(limited)
+    }
+}
+
+impl ResponseError for AppError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            AppError::NotFound(_) => StatusCode::NOT_FOUND,
+            AppError::BadRequest(_) => StatusCode::BAD_REQUEST,
+            AppError::Forbidden(_) => StatusCode::FORBIDDEN,
+            AppError::RateLimited(_) => StatusCode::TOO_MANY_REQUESTS,
+            AppError::Db(_) | AppError::Cache(_) | AppError::Template(_) => {
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        }
+    }
+
+    // HTML by default: `render_errors` swaps in JSON for clients that asked for it
+    fn error_response(&self) -> HttpResponse {
+        if self.status_code().is_server_error() {
+            tracing::error!(kind = self.kind(), error = %self, "request failed");
+        }
+        self.html_response()
+    }
+}
+
+// HTMX and browsers get HTML, clients accepting only JSON get JSON
+pub fn wants_json(request: &HttpRequest) -> bool {
+    !is_htmx_request(request)
+        && request
+            .headers()
+            .get(header::ACCEPT)
+            .and_then(|h| h.to_str().ok())
+            .is_some_and(|accept| {
+                accept.contains("application/json") && !accept.contains("text/html")
+            })
+}
+
+// Middleware answering `AppError`s in the format the request asked for
+pub async fn render_errors(
+    request: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, actix_web::Error> {
+    let json = wants_json(request.request());
+    let response = next.call(request).await?.map_into_boxed_body();
+    if !json {
+        return Ok(response);
+    }
+
+    let Some(error) = response
+        .response()
+        .error()
+        .and_then(|e| e.as_error::<AppError>())
+    else {
+        return Ok(response);
+    };
+    let json_response = error.json_response();
+    Ok(response.into_response(json_response))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{App, get, middleware::from_fn, test};
+    use serde_json::Value;
+
+    #[get("/missing")]
+    async fn missing() -> Result<HttpResponse, AppError> {
+        Err(AppError::not_found("No <such> block"))
+    }
+
+    #[actix_web::test]
+    async fn test_render_errors() {
+        let app =
+            test::init_service(App::new().wrap(from_fn(render_errors)).service(missing)).await;
+
+        let resp =
+            test::call_service(&app, test::TestRequest::get().uri("/missing").to_request()).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+        let body = test::read_body(resp).await;
+        assert_eq!(body, "No &#60;such&#62; block");
+
+        let resp = test::call_service(
+            &app,
+            test::TestRequest::get()
+                .uri("/missing")
+                .insert_header((header::ACCEPT, "application/json"))
+                .to_request(),
+        )
+        .await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+        let body: Value = test::read_body_json(resp).await;
+        assert_eq!(body["error"], "not_found");
+        assert_eq!(body["message"], "No <such> block");
+
+        let error = AppError::RateLimited(RateLimited {
+            retry_after: std::time::Duration::from_millis(4_200),
+        });
+        let resp = error.json_response();
+        assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(resp.headers().get(header::RETRY_AFTER).unwrap(), "5");
+
+        // Internal errors don't leak their cause
+        let error = AppError::Db(sqlx::Error::PoolTimedOut);
+        assert_eq!(error.status_code(), StatusCode::INTERNAL_SERVER_ERROR);
+        assert!(!error.message().contains("pool"));
+    }
+}
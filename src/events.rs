@@ -0,0 +1,138 @@
+use actix_web::{rt, web::Bytes};
+use chrono::{NaiveDateTime, Utc};
+use serde::Serialize;
+use tokio::sync::broadcast;
+
+use crate::models::Block;
+use crate::utils::start_of_week;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ChainEvent {
+    BlockCreated {
+        hash: String,
+        parent_hash: Option<String>,
+        height: i64,
+        name: String,
+        solution_moves: u8,
+    },
+    TipChanged {
+        hash: String,
+        previous_hash: Option<String>,
+        height: i64,
+    },
+    RoundStarted {
+        started_at: NaiveDateTime,
+    },
+}
+
+impl ChainEvent {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ChainEvent::BlockCreated { .. } => "block_created",
+            ChainEvent::TipChanged { .. } => "tip_changed",
+            ChainEvent::RoundStarted { .. } => "round_started",
+        }
+    }
+
+    pub fn block_created(block: &Block) -> Self {
+        ChainEvent::BlockCreated {
+            hash: block.hash.clone(),
+            parent_hash: block.parent_hash.clone(),
+            height: block.height,
+            name: block.name.clone(),
+            solution_moves: block.solution_moves,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct EventBus {
+    sender: broadcast::Sender<ChainEvent>,
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        let (sender, _) = broadcast::channel(64);
+        Self { sender }
+    }
+}
+
+impl EventBus {
+    pub fn publish(&self, event: ChainEvent) {
+        // Sending only fails if nobody is listening, which is fine
+        let _ = self.sender.send(event);
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<ChainEvent> {
+        self.sender.subscribe()
+    }
+
+    // Announce the start of every new round (Monday 00:00 UTC)
+    pub fn start_round_task(&self) {
+        let events = self.clone();
+
+        rt::spawn(async move {
+            loop {
+                let now = Utc::now().naive_utc();
+                let next_round = start_of_week(now) + chrono::Duration::weeks(1);
+                let wait = (next_round - now).to_std().unwrap_or_default();
+                rt::time::sleep(wait).await;
+
+                events.publish(ChainEvent::RoundStarted {
+                    started_at: next_round,
+                });
+            }
+        });
+    }
+}
+
+// Format a Server-Sent Event, splitting multi-line data over multiple data fields
+pub fn format_sse(event: &str, data: &str) -> Bytes {
+    let mut message = format!("event: {}\n", event);
+    for line in data.lines() {
+        message.push_str("data: ");
+        message.push_str(line);
+        message.push('\n');
+    }
+    if data.is_empty() {
+        message.push_str("data: \n");
+    }
+    message.push('\n');
+    Bytes::from(message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_sse() {
+        assert_eq!(
+            format_sse("block_created", "{\"a\":1}"),
+            Bytes::from("event: block_created\ndata: {\"a\":1}\n\n")
+        );
+        assert_eq!(
+            format_sse("block_created", "<li>\n</li>"),
+            Bytes::from("event: block_created\ndata: <li>\ndata: </li>\n\n")
+        );
+        assert_eq!(
+            format_sse("tip_changed", ""),
+            Bytes::from("event: tip_changed\ndata: \n\n")
+        );
+    }
+
+    #[test]
+    fn test_event_serialization() {
+        let event = ChainEvent::TipChanged {
+            hash: "new".to_string(),
+            previous_hash: Some("old".to_string()),
+            height: 3,
+        };
+        let json = serde_json::to_value(&event).unwrap();
+
+        assert_eq!(event.name(), "tip_changed");
+        assert_eq!(json["type"], "tip_changed");
+        assert_eq!(json["previous_hash"], "old");
+    }
+}
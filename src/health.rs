@@ -0,0 +1,254 @@
+use serde::Serialize;
+use sqlx::{FromRow, SqlitePool};
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::cache::{Cache, MemoryCache};
+use crate::metrics;
+use crate::models::Block;
+
+const CACHE_PROBE_KEY: &str = "health_probe";
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Status {
+    Ok,
+    // Still serving, but something needs attention
+    Degraded,
+    Error,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ComponentStatus {
+    pub status: Status,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+}
+
+impl ComponentStatus {
+    fn ok() -> Self {
+        ComponentStatus {
+            status: Status::Ok,
+            detail: None,
+        }
+    }
+
+    fn degraded(detail: impl Into<String>) -> Self {
+        ComponentStatus {
+            status: Status::Degraded,
+            detail: Some(detail.into()),
+        }
+    }
+
+    fn error(detail: impl Into<String>) -> Self {
+        ComponentStatus {
+            status: Status::Error,
+            detail: Some(detail.into()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Readiness {
+    pub status: Status,
+    pub components: BTreeMap<&'static str, ComponentStatus>,
+}
+
+impl Readiness {
+    // Degraded components still serve traffic
+    pub fn is_ready(&self) -> bool {
+        self.status != Status::Error
+    }
+}
+
+#[derive(FromRow)]
+struct BlockRow {
+    rowid: i64,
+    #[sqlx(flatten)]
+    block: Block,
+}
+
+#[derive(Debug, Default)]
+struct VerifiedBlocks {
+    // Blocks are only ever inserted, so everything up to this rowid was verified
+    last_rowid: i64,
+    invalid: BTreeSet<String>,
+}
+
+// Remembers up to which block the chain was verified, so readiness probes only load and check
+// blocks created since the previous probe. Blocks never change once created.
+#[derive(Debug, Clone, Default)]
+pub struct ChainVerifier {
+    verified: Arc<Mutex<VerifiedBlocks>>,
+}
+
+impl ChainVerifier {
+    // Verify the blocks that weren't verified yet, returning the hashes of every invalid block
+    // found so far
+    pub async fn verify(&self, db: &SqlitePool) -> Result<Vec<String>, sqlx::Error> {
+        let last_rowid = self.lock().last_rowid;
+        let rows = sqlx::query_as::<_, BlockRow>(
+            "SELECT rowid, version, hash, parent_hash, height, name, message, solution, solution_moves, solution_description, created_at, public_key, signature, user_id, mean_of
+             FROM blocks
+             WHERE rowid > ?
+             ORDER BY rowid",
+        )
+        .bind(last_rowid)
+        .fetch_all(db)
+        .await?;
+
+        // Verify outside the lock, another probe may have verified some of the blocks since
+        let invalid = rows
+            .iter()
+            .filter(|row| !row.block.is_valid())
+            .map(|row| row.block.hash.clone())
+            .collect::<Vec<_>>();
+        let mut verified = self.lock();
+        verified.invalid.extend(invalid);
+        if let Some(row) = rows.last() {
+            verified.last_rowid = verified.last_rowid.max(row.rowid);
+        }
+        metrics::INVALID_BLOCKS.set(verified.invalid.len() as i64);
+        Ok(verified.invalid.iter().cloned().collect())
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, VerifiedBlocks> {
+        self.verified
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+async fn check_database(db: &SqlitePool) -> ComponentStatus {
+    match sqlx::query_scalar::<_, i64>("SELECT 1").fetch_one(db).await {
+        Ok(_) => ComponentStatus::ok(),
+        Err(e) => ComponentStatus::error(e.to_string()),
+    }
+}
+
+// Migrations embedded in the binary that weren't applied to the database
+pub async fn pending_migrations(db: &SqlitePool) -> Result<Vec<i64>, sqlx::Error> {
+    let applied =
+        sqlx::query_scalar::<_, i64>("SELECT version FROM _sqlx_migrations WHERE success = 1")
+            .fetch_all(db)
+            .await?
+            .into_iter()
+            .collect::<HashSet<_>>();
+
+    Ok(sqlx::migrate!("./migrations")
+        .iter()
+        .filter(|m| !m.migration_type.is_down_migration())
+        .map(|m| m.version)
+        .filter(|version| !applied.contains(version))
+        .collect())
+}
+
+async fn check_migrations(db: &SqlitePool) -> ComponentStatus {
+    match pending_migrations(db).await {
+        Ok(pending) if pending.is_empty() => ComponentStatus::ok(),
+        Ok(pending) => ComponentStatus::error(format!(
+            "{} pending migration(s), first {}",
+            pending.len(),
+            pending[0]
+        )),
+        Err(e) => ComponentStatus::error(e.to_string()),
+    }
+}
+
+// Invalid blocks don't stop the server from answering, so they degrade the chain instead of
+// failing readiness, which would only take every replica out of rotation
+async fn check_chain(db: &SqlitePool, verifier: &ChainVerifier) -> ComponentStatus {
+    match verifier.verify(db).await {
+        Ok(invalid) if invalid.is_empty() => ComponentStatus::ok(),
+        Ok(invalid) => ComponentStatus::degraded(format!(
+            "{} invalid block(s), first {}",
+            invalid.len(),
+            invalid[0]
+        )),
+        Err(e) => ComponentStatus::error(e.to_string()),
+    }
+}
+
+// The cache answers if a value can be written and read back
+fn check_cache(cache: &MemoryCache<String, String>) -> ComponentStatus {
+    let key = CACHE_PROBE_KEY.to_string();
+    let written = cache.set(&key, "ok".to_string(), Some(Duration::from_secs(60)));
+    match (written, cache.get(&key)) {
+        (Ok(()), Ok(Some(_))) => ComponentStatus::ok(),
+        (Err(e), _) | (_, Err(e)) => ComponentStatus::error(format!("{:?}", e)),
+        (Ok(()), Ok(None)) => ComponentStatus::error("Probe value was not read back"),
+    }
+}
+
+pub async fn readiness(
+    db: &SqlitePool,
+    verifier: &ChainVerifier,
+    cache: &MemoryCache<String, String>,
+) -> Readiness {
+    let components = BTreeMap::from([
+        ("database", check_database(db).await),
+        ("migrations", check_migrations(db).await),
+        ("chain", check_chain(db, verifier).await),
+        ("cache", check_cache(cache)),
+    ]);
+    let status = if components.values().any(|c| c.status == Status::Error) {
+        Status::Error
+    } else if components.values().any(|c| c.status == Status::Degraded) {
+        Status::Degraded
+    } else {
+        Status::Ok
+    };
+
+    Readiness { status, components }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[sqlx::test]
+    async fn test_readiness(pool: SqlitePool) {
+        // The fixture blocks aren't solved, so start from a real genesis block
+        crate::setup::run_setup(&pool).await.unwrap();
+        let verifier = ChainVerifier::default();
+        let cache = MemoryCache::default();
+
+        let readiness = readiness(&pool, &verifier, &cache).await;
+        assert!(readiness.is_ready(), "{:?}", readiness);
+        assert_eq!(readiness.components.len(), 4);
+
+        // Tampering with a block degrades the chain, but the server stays ready
+        sqlx::query("UPDATE blocks SET solution = 'R U' WHERE height = 0")
+            .execute(&pool)
+            .await
+            .unwrap();
+        // ... and can start again, leaving the invalid block to the verifier
+        crate::setup::run_setup(&pool).await.unwrap();
+        let tampered = ChainVerifier::default();
+        let readiness = super::readiness(&pool, &tampered, &cache).await;
+        assert!(readiness.is_ready());
+        assert_eq!(readiness.status, Status::Degraded);
+        assert_eq!(readiness.components["chain"].status, Status::Degraded);
+        assert_eq!(readiness.components["database"].status, Status::Ok);
+
+        // Blocks verified before aren't checked again, and invalid ones stay reported
+        assert!(verifier.verify(&pool).await.unwrap().is_empty());
+        assert_eq!(tampered.verify(&pool).await.unwrap().len(), 1);
+        assert_eq!(tampered.lock().last_rowid, 1);
+    }
+
+    #[sqlx::test]
+    async fn test_pending_migrations(pool: SqlitePool) {
+        assert!(pending_migrations(&pool).await.unwrap().is_empty());
+
+        sqlx::query(
+            "DELETE FROM _sqlx_migrations
+            WHERE version = (SELECT MAX(version) FROM _sqlx_migrations)",
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+        assert_eq!(pending_migrations(&pool).await.unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,129 @@
+use chrono::NaiveDateTime;
+use ed25519_dalek::{Signature, Verifier, VerifyingKey};
+use serde::Serialize;
+use sqlx::{FromRow, SqlitePool};
+use std::collections::{HashMap, HashSet};
+use utoipa::ToSchema;
+
+use crate::validation;
+
+// A name bound to an ed25519 public key. Blocks signed with the matching private key are
+// shown as verified, and unsigned blocks can no longer use the name.
+#[derive(Debug, Clone, FromRow, Serialize, ToSchema)]
+pub struct Identity {
+    pub name: String,
+    pub public_key: String,
+    pub created_at: Option<NaiveDateTime>,
+}
+
+impl Identity {
+    // Bound names are stored in NFC like block names, and looked up the same way
+    pub async fn find_by_name(db: &SqlitePool, name: &str) -> Result<Option<Self>, sqlx::Error> {
+        sqlx::query_as::<_, Identity>(
+            "SELECT name, public_key, created_at FROM identities WHERE name = ?",
+        )
+        .bind(validation::normalize(name))
+        .fetch_optional(db)
+        .await
+    }
+
+    pub async fn create(
+        db: &SqlitePool,
+        name: &str,
+        public_key: &str,
+    ) -> Result<Self, sqlx::Error> {
+        let mut tx = db.begin().await?;
+        let identity = sqlx::query_as::<_, Identity>(
+            "INSERT INTO identities (name, public_key) VALUES (?, ?)
+            RETURNING name, public_key, created_at",
+        )
+        .bind(name)
+        .bind(public_key)
+        .fetch_one(&mut *tx)
+        .await?;
+        tx.commit().await?;
+
+        Ok(identity)
+    }
+}
+
+// Keys currently bound to a set of names, telling which blocks by them are verified
+#[derive(Debug, Clone, Default)]
+pub struct Bindings(HashMap<String, String>);
+
+impl Bindings {
+    pub async fn for_names<'a>(
+        db: &SqlitePool,
+        names: impl IntoIterator<Item = &'a str>,
+    ) -> Result<Self, sqlx::Error> {
+        let names = names.into_iter().collect::<HashSet<_>>();
+        let bindings = sqlx::query_as::<_, (String, String)>(
+            "SELECT name, public_key FROM identities
+            WHERE name IN (SELECT value FROM json_each(?))",
+        )
+        .bind(serde_json::to_string(&names).expect("Names should serialize"))
+        .fetch_all(db)
+        .await?;
+
+        Ok(Bindings(bindings.into_iter().collect()))
+    }
+
+    pub fn public_key(&self, name: &str) -> Option<&str> {
+        self.0.get(name).map(String::as_str)
+    }
+}
+
+// Data signed to register a key, proving possession of the private key
+pub fn registration_data(name: &str) -> Vec<u8> {
+    format!("register|{}", name).as_bytes().to_vec()
+}
+
+// Data signed for a block: its hash (which covers parent, name and message) and the
+// solution in canonical notation, so a signature can't be reused for another solution
+pub fn block_data(hash: &str, solution: &str) -> Vec<u8> {
+    format!("{}|{}", hash, solution).as_bytes().to_vec()
+}
+
+pub fn parse_public_key(public_key: &str) -> Option<VerifyingKey> {
+    let bytes: [u8; 32] = hex::decode(public_key).ok()?.try_into().ok()?;
+    VerifyingKey::from_bytes(&bytes).ok()
+}
+
+// Verify a hex encoded signature made with the hex encoded public key
+pub fn verify_signature(public_key: &str, data: &[u8], signature: &str) -> bool {
+    let Some(key) = parse_public_key(public_key) else {
+        return false;
+    };
+    let Some(bytes) = hex::decode(signature)
+        .ok()
+        .and_then(|b| <[u8; 64]>::try_from(b).ok())
+    else {
+        return false;
+    };
+
+    key.verify(data, &Signature::from_bytes(&bytes)).is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ed25519_dalek::{Signer, SigningKey};
+
+    #[test]
+    fn test_verify_signature() {
+        let key = SigningKey::from_bytes(&[7; 32]);
+        let public_key = hex::encode(key.verifying_key().as_bytes());
+        let data = block_data("HASH", "R U R'");
+        let signature = hex::encode(key.sign(&data).to_bytes());
+
+        assert!(verify_signature(&public_key, &data, &signature));
+        assert!(!verify_signature(
+            &public_key,
+            &block_data("HASH", "R U R"),
+            &signature
+        ));
+        assert!(!verify_signature(&public_key, &data, "not hex"));
+        assert!(!verify_signature("abcd", &data, &signature));
+        assert!(parse_public_key(&public_key).is_some());
+    }
+}
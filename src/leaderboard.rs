@@ -0,0 +1,565 @@
+use chrono::{NaiveDate, Weekday};
+use serde::{Deserialize, Serialize};
+use sqlx::{Acquire, Sqlite, SqlitePool};
+use utoipa::ToSchema;
+
+pub const DEFAULT_MEAN_OF: u32 = 3;
+pub const DEFAULT_LIMIT: usize = 10;
+
+// SQL expression for the round (week starting Monday) a block was created in
+const ROUND_START: &str = "date(created_at, 'weekday 0', '-6 days')";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum RankingKind {
+    BestSingle,
+    BestMean,
+    MainChainBlocks,
+    Rounds,
+}
+
+impl RankingKind {
+    pub const ALL: [RankingKind; 4] = [
+        RankingKind::BestSingle,
+        RankingKind::BestMean,
+        RankingKind::MainChainBlocks,
+        RankingKind::Rounds,
+    ];
+
+    pub fn title(&self, mean_of: u32) -> String {
+        match self {
+            RankingKind::BestSingle => "Best single".to_string(),
+            RankingKind::BestMean => format!("Best mean of {}", mean_of),
+            RankingKind::MainChainBlocks => "Most main chain blocks".to_string(),
+            RankingKind::Rounds => "Most rounds participated".to_string(),
+        }
+    }
+
+    // Whether lower values rank higher
+    fn ascending(&self) -> bool {
+        matches!(self, RankingKind::BestSingle | RankingKind::BestMean)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
+pub struct LeaderboardEntry {
+    // Competition ranking: tied entries share a rank and the next rank is skipped (1, 2, 2, 4)
+    pub rank: usize,
+    pub name: String,
+    pub value: f64,
+    // True if another entry has the same value
+    pub tied: bool,
+}
+
+impl LeaderboardEntry {
+    pub fn value_display(&self) -> String {
+        if self.value.fract() == 0.0 {
+            format!("{:.0}", self.value)
+        } else {
+            format!("{:.2}", self.value)
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct Ranking {
+    pub kind: RankingKind,
+    pub title: String,
+    pub entries: Vec<LeaderboardEntry>,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct Leaderboard {
+    // Monday the round started, or null for all-time rankings
+    pub round: Option<NaiveDate>,
+    pub mean_of: u32,
+    pub rankings: Vec<Ranking>,
+}
+
+// The Monday starting the round that contains the given date
+pub fn round_of(date: NaiveDate) -> NaiveDate {
+    date.week(Weekday::Mon).first_day()
+}
+
+// Rebuild the aggregates from the blocks table, e.g. at startup. New blocks update them with
+// `record_block` instead.
+pub async fn refresh(db: &SqlitePool) -> Result<(), sqlx::Error> {
+    let mut tx = db.begin().await?;
+    sqlx::query("DELETE FROM leaderboard_stats")
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query("DELETE FROM leaderboard_moves")
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query(&format!(
+        "WITH RECURSIVE main_chain AS (
+            SELECT hash, parent_hash
+            FROM blocks
+            WHERE hash = (
+                SELECT hash
+                FROM blocks
+                ORDER BY height DESC, solution_moves ASC
+                LIMIT 1
+            )
+
+            UNION ALL
+
+            SELECT b.hash, b.parent_hash
+            FROM blocks b
+            INNER JOIN main_chain mc ON b.hash = mc.parent_hash
+        )
+        INSERT INTO leaderboard_stats (name, round_start, block_count, main_chain_count)
+        SELECT
+            name,
+            {ROUND_START},
+            COUNT(*),
+            SUM(hash IN (SELECT hash FROM main_chain))
+        FROM blocks
+        WHERE height > 0
+        GROUP BY name, {ROUND_START}"
+    ))
+    .execute(&mut *tx)
+    .await?;
+    sqlx::query(&format!(
+        "INSERT INTO leaderboard_moves (name, round_start, solution_moves, count)
+        SELECT name, {ROUND_START}, solution_moves, COUNT(*)
+        FROM blocks
+        WHERE height > 0 AND mean_of IS NULL
+        GROUP BY name, {ROUND_START}, solution_moves"
+    ))
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await
+}
+
+// Add a new block to the aggregates of its name and round, given the tip of the main chain
+// before and after it was added. Only a switch to another branch changes the main chain
+// counts of other blocks.
+pub async fn record_block<'c>(
+    db: impl Acquire<'c, Database = Sqlite>,
+    hash: &str,
+    previous_tip: Option<&str>,
+    tip: Option<&str>,
+) -> Result<(), sqlx::Error> {
+    let mut tx = db.begin().await?;
+    sqlx::query(&format!(
+        "INSERT INTO leaderboard_stats (name, round_start, block_count, main_chain_count)
+        SELECT name, {ROUND_START}, 1, 0
+        FROM blocks
+        WHERE hash = ?1 AND height > 0
+        ON CONFLICT (name, round_start) DO UPDATE SET block_count = block_count + 1"
+    ))
+    .bind(hash)
+    .execute(&mut *tx)
+    .await?;
+    sqlx::query(&format!(
+        "INSERT INTO leaderboard_moves (name, round_start, solution_moves, count)
+        SELECT name, {ROUND_START}, solution_moves, 1
+        FROM blocks
+        WHERE hash = ?1 AND height > 0 AND mean_of IS NULL
+        ON CONFLICT (name, round_start, solution_moves) DO UPDATE SET count = count + 1"
+    ))
+    .bind(hash)
+    .execute(&mut *tx)
+    .await?;
+
+    let Some(tip) = tip.filter(|tip| Some(*tip) != previous_tip) else {
+        return tx.commit().await;
+    };
+    let tip_parent =
+        sqlx::query_scalar::<_, Option<String>>("SELECT parent_hash FROM blocks WHERE hash = ?")
+            .bind(tip)
+            .fetch_one(&mut *tx)
+            .await?;
+
+    // Blocks leaving and joining the main chain: only the new tip when the chain grew, the
+    // blocks of both branches below it when it switched branches
+    let changes = if tip_parent.as_deref() == previous_tip {
+        "changes AS (SELECT ?2 AS hash, 1 AS delta)"
+    } else {
+        "old_chain AS (
+            SELECT hash, parent_hash FROM blocks WHERE hash = ?1
+
+            UNION ALL
+
+            SELECT b.hash, b.parent_hash
+            FROM blocks b
+            INNER JOIN old_chain c ON b.hash = c.parent_hash
+        ),
+        new_chain AS (
+            SELECT hash, parent_hash FROM blocks WHERE hash = ?2
+
+            UNION ALL
+
+            SELECT b.hash, b.parent_hash
+            FROM blocks b
+            INNER JOIN new_chain c ON b.hash = c.parent_hash
+        ),
+        changes AS (
+            SELECT hash, -1 AS delta FROM old_chain
+            WHERE hash NOT IN (SELECT hash FROM new_chain)
+
+            UNION ALL
+
+            SELECT hash, 1 AS delta FROM new_chain
+            WHERE hash NOT IN (SELECT hash FROM old_chain)
+        )"
+    };
+    sqlx::query(&format!(
+        "WITH RECURSIVE {changes},
+        deltas AS (
+            SELECT name, {ROUND_START} AS round_start, SUM(delta) AS delta
+            FROM changes
+            INNER JOIN blocks USING (hash)
+            WHERE height > 0
+            GROUP BY name, {ROUND_START}
+        )
+        UPDATE leaderboard_stats
+        SET main_chain_count = main_chain_count + deltas.delta
+        FROM deltas
+        WHERE leaderboard_stats.name = deltas.name
+            AND leaderboard_stats.round_start = deltas.round_start"
+    ))
+    .bind(previous_tip)
+    .bind(tip)
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await
+}
+
+// Rounds anyone participated in, most recent first
+pub async fn rounds(db: &SqlitePool) -> Result<Vec<NaiveDate>, sqlx::Error> {
+    sqlx::query_scalar::<_, NaiveDate>(
+        "SELECT DISTINCT round_start FROM leaderboard_stats ORDER BY round_start DESC",
+    )
+    .fetch_all(db)
+    .await
+}
+
+// Assign competition ranks to rows that are already sorted best first, keeping every entry
+// that ranks within `limit` so ties at the boundary are not cut off arbitrarily
+pub fn assign_ranks(rows: Vec<(String, f64)>, limit: usize) -> Vec<LeaderboardEntry> {
+    let values = rows.iter().map(|(_, v)| *v).collect::<Vec<_>>();
+    let mut entries = Vec::new();
+
+    for (i, (name, value)) in rows.into_iter().enumerate() {
+        let rank = if i > 0 && values[i - 1] == value {
+            entries.last().map_or(1, |e: &LeaderboardEntry| e.rank)
+        } else {
+            i + 1
+        };
+        if rank > limit {
+            break;
+        }
+        let tied = values.iter().filter(|v| **v == value).count() > 1;
+        entries.push(LeaderboardEntry {
+            rank,
+            name,
+            value,
+            tied,
+        });
+    }
+
+    entries
+}
+
+pub async fn ranking(
+    db: &SqlitePool,
+    kind: RankingKind,
+    round: Option<NaiveDate>,
+    mean_of: u32,
+    limit: usize,
+) -> Result<Ranking, sqlx::Error> {
+    let order = if kind.ascending() { "ASC" } else { "DESC" };
+    let query = match kind {
+        // The rounded mean of a challenge block isn't a single, so only the histogram of
+        // single solutions counts
+        RankingKind::BestSingle => format!(
+            "SELECT name, CAST(MIN(solution_moves) AS REAL) AS value
+            FROM leaderboard_moves
+            WHERE ?1 IS NULL OR round_start = ?1
+            GROUP BY name
+            ORDER BY value {order}, name"
+        ),
+        RankingKind::MainChainBlocks => format!(
+            "SELECT name, CAST(SUM(main_chain_count) AS REAL) AS value
+            FROM leaderboard_stats
+            WHERE ?1 IS NULL OR round_start = ?1
+            GROUP BY name
+            HAVING value > 0
+            ORDER BY value {order}, name"
+        ),
+        RankingKind::Rounds => format!(
+            "SELECT name, CAST(COUNT(*) AS REAL) AS value
+            FROM leaderboard_stats
+            WHERE ?1 IS NULL OR round_start = ?1
+            GROUP BY name
+            ORDER BY value {order}, name"
+        ),
+        // Means of each name's N best solutions, taking as many solutions of each length as
+        // are needed from the histogram, shortest first
+        RankingKind::BestMean => format!(
+            "WITH histogram AS (
+                SELECT name, solution_moves, SUM(count) AS count
+                FROM leaderboard_moves
+                WHERE ?1 IS NULL OR round_start = ?1
+                GROUP BY name, solution_moves
+            ),
+            cumulative AS (
+                SELECT
+                    name,
+                    solution_moves,
+                    MIN(count, ?2 - (SUM(count) OVER (
+                        PARTITION BY name ORDER BY solution_moves
+                        ROWS UNBOUNDED PRECEDING
+                    ) - count)) AS taken
+                FROM histogram
+            )
+            SELECT name, CAST(SUM(solution_moves * taken) AS REAL) / ?2 AS value
+            FROM cumulative
+            WHERE taken > 0
+            GROUP BY name
+            HAVING SUM(taken) = ?2
+            ORDER BY value {order}, name"
+        ),
+    };
+
+    let rows = sqlx::query_as::<_, (String, f64)>(&query)
+        .bind(round)
+        .bind(mean_of)
+        .fetch_all(db)
+        .await?;
+
+    Ok(Ranking {
+        kind,
+        title: kind.title(mean_of),
+        entries: assign_ranks(rows, limit),
+    })
+}
+
+pub async fn leaderboard(
+    db: &SqlitePool,
+    round: Option<NaiveDate>,
+    mean_of: u32,
+    limit: usize,
+) -> Result<Leaderboard, sqlx::Error> {
+    let mut rankings = Vec::new();
+    for kind in RankingKind::ALL {
+        rankings.push(ranking(db, kind, round, mean_of, limit).await?);
+    }
+
+    Ok(Leaderboard {
+        round,
+        mean_of,
+        rankings,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::challenges::ChallengeAttempt;
+    use crate::cube::Move;
+    use crate::models::Block;
+
+    #[test]
+    fn test_assign_ranks() {
+        let rows = vec![
+            ("a".to_string(), 20.0),
+            ("b".to_string(), 22.0),
+            ("c".to_string(), 22.0),
+            ("d".to_string(), 25.0),
+            ("e".to_string(), 25.0),
+            ("f".to_string(), 30.0),
+        ];
+        let entries = assign_ranks(rows.clone(), 10);
+        let ranks = entries.iter().map(|e| e.rank).collect::<Vec<_>>();
+        assert_eq!(ranks, vec![1, 2, 2, 4, 4, 6]);
+        assert!(!entries[0].tied);
+        assert!(entries[1].tied && entries[2].tied);
+
+        // Ties at the limit are kept together
+        let entries = assign_ranks(rows, 4);
+        assert_eq!(entries.len(), 5);
+        assert_eq!(entries.last().unwrap().name, "e");
+    }
+
+    #[test]
+    fn test_round_of() {
+        let monday = NaiveDate::from_ymd_opt(2025, 1, 6).unwrap();
+        assert_eq!(round_of(monday), monday);
+        assert_eq!(
+            round_of(NaiveDate::from_ymd_opt(2025, 1, 12).unwrap()),
+            monday
+        );
+    }
+
+    #[sqlx::test(fixtures("../fixtures/blocks.sql"))]
+    async fn test_leaderboard(pool: SqlitePool) {
+        // The rounded mean of a challenge block doesn't rank as a single
+        sqlx::query("UPDATE blocks SET mean_of = 3, solution_moves = 0 WHERE name = 'Eve'")
+            .execute(&pool)
+            .await
+            .unwrap();
+        refresh(&pool).await.unwrap();
+
+        let rounds = rounds(&pool).await.unwrap();
+        assert!(!rounds.is_empty());
+        assert!(rounds.iter().all(|r| round_of(*r) == *r));
+
+        let board = leaderboard(&pool, None, 2, DEFAULT_LIMIT).await.unwrap();
+        assert_eq!(board.rankings.len(), 4);
+
+        let best_single = &board.rankings[0];
+        let expected_best = sqlx::query_scalar::<_, i64>(
+            "SELECT MIN(solution_moves) FROM blocks WHERE height > 0 AND mean_of IS NULL",
+        )
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+        assert!(expected_best > 0);
+        assert_eq!(best_single.entries[0].rank, 1);
+        assert_eq!(best_single.entries[0].value, expected_best as f64);
+        assert!(best_single.entries.iter().all(|e| e.name != "Eve"));
+
+        // Every main chain block except genesis is counted once
+        let main_chain = &board.rankings[2];
+        let main_chain_total: f64 = main_chain.entries.iter().map(|e| e.value).sum();
+        assert_eq!(main_chain_total, 3.0);
+
+        // Per round rankings only include that round
+        let round = rounds[0];
+        let board = leaderboard(&pool, Some(round), 2, DEFAULT_LIMIT)
+            .await
+            .unwrap();
+        assert!(board.rankings[3].entries.iter().all(|e| e.value == 1.0));
+    }
+
+    type Snapshot = (
+        Vec<(String, NaiveDate, i64, i64)>,
+        Vec<(String, NaiveDate, i64, i64)>,
+    );
+
+    async fn snapshot(pool: &SqlitePool) -> Snapshot {
+        let stats = sqlx::query_as(
+            "SELECT name, round_start, block_count, main_chain_count
+            FROM leaderboard_stats
+            ORDER BY name, round_start",
+        )
+        .fetch_all(pool)
+        .await
+        .unwrap();
+        let moves = sqlx::query_as(
+            "SELECT name, round_start, solution_moves, count
+            FROM leaderboard_moves
+            ORDER BY name, round_start, solution_moves",
+        )
+        .fetch_all(pool)
+        .await
+        .unwrap();
+        (stats, moves)
+    }
+
+    #[sqlx::test(fixtures("../fixtures/blocks.sql"))]
+    async fn test_record_block(pool: SqlitePool) {
+        refresh(&pool).await.unwrap();
+
+        // A block next to the main chain, a challenge block, a shorter sibling of the tip,
+        // which switches branches, and a child of the new tip
+        let tip = Block::find_tip(&pool).await.unwrap().unwrap();
+        let challenge = (1..=3)
+            .map(|i| ChallengeAttempt::new(i, format!("attempt_{}", i), &[Move::R(1)]))
+            .collect::<Vec<_>>();
+        let additions = [
+            ("genesis_block_hash_001", "Alice", 25, &[][..]),
+            ("genesis_block_hash_001", "Alice", 1, &challenge[..]),
+            (
+                tip.parent_hash.as_deref().unwrap(),
+                "reorg",
+                tip.solution_moves.min(2) - 1,
+                &[][..],
+            ),
+            ("reorg_block_0", "grows", 30, &[][..]),
+        ];
+        for (parent_hash, name, moves, attempts) in additions {
+            let parent = Block::find_by_hash(&pool, parent_hash).await.unwrap();
+            let hash = format!("{}_block_{}", name, attempts.len());
+            let mut tx = pool.begin().await.unwrap();
+            parent
+                .create_child(
+                    &mut *tx, &hash, name, "", "R", moves, "", None, None, None, attempts,
+                )
+                .await
+                .unwrap();
+            let previous_tip = Block::find_tip_excluding(&mut *tx, Some(&hash))
+                .await
+                .unwrap()
+                .map(|b| b.hash);
+            let tip = Block::find_tip(&mut *tx).await.unwrap().map(|b| b.hash);
+            record_block(&mut *tx, &hash, previous_tip.as_deref(), tip.as_deref())
+                .await
+                .unwrap();
+            tx.commit().await.unwrap();
+
+            // The updated aggregates match rebuilding them from scratch
+            let recorded = snapshot(&pool).await;
+            refresh(&pool).await.unwrap();
+            assert_eq!(recorded, snapshot(&pool).await, "After adding {}", hash);
+        }
+        assert_eq!(
+            Block::find_tip(&pool).await.unwrap().unwrap().hash,
+            "grows_block_0"
+        );
+    }
+
+    #[sqlx::test(fixtures("../fixtures/blocks.sql"))]
+    async fn test_best_mean(pool: SqlitePool) {
+        refresh(&pool).await.unwrap();
+
+        // The means read from the histogram match the means of the best blocks themselves
+        let mut periods = rounds(&pool)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(Some)
+            .collect::<Vec<_>>();
+        periods.push(None);
+        for round in periods {
+            for mean_of in 1..=4 {
+                let expected = sqlx::query_as::<_, (String, f64)>(&format!(
+                    "WITH ranked AS (
+                        SELECT
+                            name,
+                            solution_moves,
+                            ROW_NUMBER() OVER (PARTITION BY name ORDER BY solution_moves) AS position
+                        FROM blocks
+                        WHERE height > 0
+                            AND mean_of IS NULL
+                            AND (?1 IS NULL OR {ROUND_START} = ?1)
+                    )
+                    SELECT name, AVG(solution_moves) AS value
+                    FROM ranked
+                    WHERE position <= ?2
+                    GROUP BY name
+                    HAVING COUNT(*) = ?2
+                    ORDER BY value ASC, name"
+                ))
+                .bind(round)
+                .bind(mean_of)
+                .fetch_all(&pool)
+                .await
+                .unwrap();
+                let ranking = ranking(&pool, RankingKind::BestMean, round, mean_of, 100)
+                    .await
+                    .unwrap();
+                let actual = ranking
+                    .entries
+                    .into_iter()
+                    .map(|e| (e.name, e.value))
+                    .collect::<Vec<_>>();
+                assert_eq!(actual, expected, "Mean of {} in {:?}", mean_of, round);
+            }
+        }
+    }
+}
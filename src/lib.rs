@@ -1,9 +1,29 @@
+pub mod accounts;
+pub mod api;
+pub mod attempts;
 pub mod cache;
+pub mod challenges;
+pub mod commit_reveal;
 pub mod config;
+pub mod csrf;
 pub mod cube;
+pub mod errors;
+pub mod events;
+pub mod health;
+pub mod identity;
+pub mod leaderboard;
 pub mod messages;
+pub mod metrics;
 pub mod models;
+pub mod rate_limit;
 pub mod routes;
+pub mod scoring;
+pub mod security;
 pub mod setup;
+pub mod stats;
+pub mod telemetry;
+pub mod tree;
 pub mod utils;
+pub mod validation;
 pub mod views;
+pub mod webhooks;
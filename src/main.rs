@@ -1,48 +1,196 @@
 use actix_files as fs;
-use actix_web::{App, HttpServer, middleware::Logger, web};
-use env_logger::Env;
+use actix_web::{App, HttpServer, middleware::from_fn, web};
 use sqlx::SqlitePool;
+use std::sync::Arc;
 
 use fm_chain::cache::MemoryCache;
+use fm_chain::commit_reveal;
 use fm_chain::config;
+use fm_chain::csrf;
+use fm_chain::errors;
+use fm_chain::events::EventBus;
+use fm_chain::health::ChainVerifier;
+use fm_chain::messages;
+use fm_chain::metrics;
+use fm_chain::rate_limit::{RateLimitConfig, RateLimiter};
 use fm_chain::routes;
+use fm_chain::scoring::{self, RelativeScoring, Season};
+use fm_chain::security;
 use fm_chain::setup::run_setup;
+use fm_chain::telemetry;
+use fm_chain::webhooks;
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
     let conf = config::Config::from_env();
+    telemetry::init(conf.log_format);
+    if conf.secret_key.is_generated() {
+        tracing::warn!(
+            "SECRET_KEY is not set: using a random key, so cookies won't survive restarts"
+        );
+    }
+
     let db = SqlitePool::connect(&conf.database_url)
         .await
         .expect("DB failed");
 
     run_setup(&db).await.expect("Failed to setup database");
 
+    let formula = RelativeScoring::from_config(&conf);
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    match args.iter().map(String::as_str).collect::<Vec<_>>()[..] {
+        [] => {}
+        ["recompute-standings"] => {
+            scoring::recompute_standings(&db, &formula)
+                .await
+                .expect("Failed to recompute standings");
+            println!("Recomputed the standings of every season");
+            return Ok(());
+        }
+        ["create-season", name, starts_on, ends_on] => {
+            let parse = |s: &str| {
+                chrono::NaiveDate::parse_from_str(s, "%Y-%m-%d").expect("Dates must be YYYY-MM-DD")
+            };
+            let season = Season::create(&db, name, parse(starts_on), parse(ends_on))
+                .await
+                .expect("Failed to create season");
+            scoring::recompute_standings(&db, &formula)
+                .await
+                .expect("Failed to recompute standings");
+            println!("Created season {} ({})", season.id, season.name);
+            return Ok(());
+        }
+        ["commit-reveal", date] => {
+            let date = chrono::NaiveDate::parse_from_str(date, "%Y-%m-%d")
+                .expect("Dates must be YYYY-MM-DD");
+            let round = commit_reveal::enable(&db, date)
+                .await
+                .expect("Failed to enable commit-reveal");
+            println!("Enabled commit-reveal for the round starting {}", round);
+            return Ok(());
+        }
+        ["list-webhooks"] => {
+            for webhook in webhooks::Webhook::find_all(&db)
+                .await
+                .expect("Failed to fetch webhooks")
+            {
+                println!("{}\t{}\t{}", webhook.id, webhook.url, webhook.events);
+            }
+            return Ok(());
+        }
+        ["create-webhook", url, secret, events] => {
+            if let Err(e) = webhooks::Webhook::validate(url, events) {
+                eprintln!("{}", e);
+                std::process::exit(2);
+            }
+            let webhook = webhooks::Webhook::create(&db, url, secret, events)
+                .await
+                .expect("Failed to create webhook");
+            println!("Created webhook {} ({})", webhook.id, webhook.url);
+            return Ok(());
+        }
+        ["delete-webhook", id] => {
+            let id = id.parse().expect("Webhook ids are numbers");
+            if webhooks::Webhook::delete(&db, id)
+                .await
+                .expect("Failed to delete webhook")
+            {
+                println!("Deleted webhook {}", id);
+            } else {
+                eprintln!("No webhook with id {}", id);
+                std::process::exit(1);
+            }
+            return Ok(());
+        }
+        _ => {
+            eprintln!(
+                "Usage: fm_chain [recompute-standings | create-season NAME START END | commit-reveal DATE | list-webhooks | create-webhook URL SECRET EVENTS | delete-webhook ID]"
+            );
+            std::process::exit(2);
+        }
+    }
+
     let cache = MemoryCache::<String, String>::default();
     cache.start_cleanup_task(60);
+    let limiter = RateLimiter::new(cache.clone(), RateLimitConfig::from_config(&conf));
+
+    // Verify the chain once up front, so readiness probes only check new blocks
+    let verifier = ChainVerifier::default();
+    let invalid = verifier
+        .verify(&db)
+        .await
+        .expect("Failed to verify the chain");
+    if let Some(first) = invalid.first() {
+        tracing::warn!(count = invalid.len(), first = %first, "invalid blocks in the chain");
+    }
 
-    env_logger::init_from_env(Env::default().default_filter_or("info"));
+    let events = EventBus::default();
+    events.start_round_task();
+    webhooks::start_delivery_task(db.clone(), &events);
+    scoring::start_standings_task(db.clone(), &events, Arc::new(formula));
+    metrics::start_chain_gauges_task(db.clone(), &events);
 
-    println!("Starting server at http://{}:{}/", &conf.host, conf.port);
+    tracing::info!(
+        host = %conf.host,
+        port = conf.port,
+        metrics_port = conf.metrics_port,
+        "starting server"
+    );
 
     let conf_clone = conf.clone();
 
-    HttpServer::new(move || {
+    let server = HttpServer::new(move || {
         App::new()
-            .wrap(Logger::default())
+            .wrap(from_fn(csrf::protect))
+            .wrap(from_fn(messages::flash_messages))
+            .wrap(from_fn(errors::render_errors))
+            .wrap(from_fn(security::security_headers))
+            .wrap(from_fn(metrics::track_requests))
+            .wrap(from_fn(telemetry::request_id))
             .app_data(web::Data::new(db.clone()))
             .app_data(web::Data::new(conf_clone.clone()))
             .app_data(web::Data::new(cache.clone()))
+            .app_data(web::Data::new(events.clone()))
+            .app_data(web::Data::new(verifier.clone()))
+            .app_data(web::Data::new(limiter.clone()))
             .service(fs::Files::new(&conf.static_dir, "static"))
             .service(routes::favicon)
             .service(routes::get_health)
+            .service(routes::get_health_live)
+            .service(routes::get_health_ready)
             .service(routes::get_index)
             .service(routes::get_parent)
             .service(routes::get_block)
             .service(routes::get_solution)
             .service(routes::post_solution)
             .service(routes::get_blocks)
+            .service(routes::get_block_details)
+            .service(routes::get_block_replay)
+            .service(routes::get_tree)
+            .service(routes::get_scramble_image)
+            .service(routes::get_events)
+            .service(routes::get_feed)
+            .service(routes::get_user_feed)
+            .service(routes::get_profile)
+            .service(routes::get_leaderboard)
+            .service(routes::get_seasons)
+            .service(routes::get_account)
+            .service(routes::get_login)
+            .service(routes::post_login)
+            .service(routes::get_register)
+            .service(routes::post_register)
+            .service(routes::post_logout)
+            .configure(routes::api_config)
     })
-    .bind((conf.host, conf.port))?
-    .run()
-    .await
+    .bind((conf.host.clone(), conf.port))?
+    .run();
+
+    // Metrics are served on their own port, so they can stay internal to the cluster
+    let metrics_server = HttpServer::new(|| App::new().service(routes::get_metrics))
+        .workers(1)
+        .bind((conf.host, conf.metrics_port))?
+        .run();
+
+    tokio::try_join!(server, metrics_server)?;
+    Ok(())
 }
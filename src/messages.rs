@@ -1,23 +1,32 @@
-use actix_web::{
-    HttpResponse,
-    cookie::{Cookie, time::Duration},
-};
+use actix_web::body::{BoxBody, MessageBody, to_bytes};
+use actix_web::cookie::{Cookie, CookieJar, SameSite, time::Duration};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{HeaderName, HeaderValue};
+use actix_web::middleware::Next;
+use actix_web::{HttpMessage, HttpRequest, HttpResponse, web};
 use serde::{Deserialize, Serialize};
 
+use crate::config::Config;
+use crate::utils::is_htmx_request;
+use crate::views;
+
 const FLASH_COOKIE_NAME: &str = "flash";
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum FlashMessageLevel {
     Info,
     Error,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct FlashMessage {
     level: FlashMessageLevel,
     message: String,
 }
 
+// Marks requests whose incoming flash message was shown, so its cookie can be removed
+struct FlashConsumed;
+
 impl FlashMessage {
     pub fn new(level: FlashMessageLevel, message: &str) -> Self {
         FlashMessage {
@@ -34,12 +43,248 @@ impl FlashMessage {
         FlashMessage::new(FlashMessageLevel::Error, message)
     }
 
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    // CSS modifier of the message, e.g. "flash-message-error"
+    pub fn level_class(&self) -> &'static str {
+        match self.level {
+            FlashMessageLevel::Info => "info",
+            FlashMessageLevel::Error => "error",
+        }
+    }
+
+    // Attach the message to a response. `flash_messages` shows it right away in HTMX
+    // responses, or keeps it in a cookie for the next page.
     pub fn set(&self, mut resp: HttpResponse) -> HttpResponse {
-        let cookie = Cookie::build(FLASH_COOKIE_NAME, serde_json::to_string(self).unwrap())
-            .path("/")
-            .max_age(Duration::minutes(1))
-            .finish();
-        resp.add_cookie(&cookie).unwrap();
+        resp.extensions_mut().insert(self.clone());
         resp
     }
+
+    // The message left by the previous response, if any. It is only shown once.
+    pub fn take(request: &HttpRequest) -> Option<FlashMessage> {
+        let mut extensions = request.extensions_mut();
+        let message = extensions.remove::<FlashMessage>()?;
+        extensions.insert(FlashConsumed);
+        Some(message)
+    }
+}
+
+fn flash_cookie(value: String) -> Cookie<'static> {
+    Cookie::build(FLASH_COOKIE_NAME, value)
+        .path("/")
+        .http_only(true)
+        .same_site(SameSite::Lax)
+        .max_age(Duration::minutes(1))
+        .finish()
+}
+
+// Messages are encrypted, so they can't be forged to show arbitrary text
+fn encrypt(config: &Config, message: &FlashMessage) -> Option<Cookie<'static>> {
+    let mut jar = CookieJar::new();
+    jar.private_mut(config.secret_key.key())
+        .add(flash_cookie(serde_json::to_string(message).ok()?));
+    jar.get(FLASH_COOKIE_NAME).cloned()
+}
+
+fn decrypt(config: &Config, cookie: Cookie<'static>) -> Option<FlashMessage> {
+    let mut jar = CookieJar::new();
+    jar.add_original(cookie);
+    let cookie = jar
+        .private(config.secret_key.key())
+        .get(FLASH_COOKIE_NAME)?;
+    serde_json::from_str(cookie.value()).ok()
+}
+
+// Middleware passing flash messages between requests. The message in the cookie of the
+// request is available to `FlashMessage::take`, and the cookie removed once it is shown.
+// Messages set on the response are rendered out-of-band into HTMX responses that stay on the
+// page, and stored in the cookie otherwise.
+pub async fn flash_messages(
+    request: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, actix_web::Error> {
+    let config = request
+        .app_data::<web::Data<Config>>()
+        .cloned()
+        .ok_or_else(|| actix_web::error::ErrorInternalServerError("Config is not available"))?;
+
+    let incoming = request.cookie(FLASH_COOKIE_NAME);
+    let readable = match incoming.clone().and_then(|c| decrypt(&config, c)) {
+        Some(message) => {
+            request.extensions_mut().insert(message);
+            true
+        }
+        None => false,
+    };
+    let htmx = is_htmx_request(request.request());
+
+    let mut response = next.call(request).await?.map_into_boxed_body();
+    let outgoing = response
+        .response()
+        .extensions()
+        .get::<FlashMessage>()
+        .cloned();
+    let redirect = response.headers().contains_key("HX-Redirect");
+
+    match outgoing {
+        Some(message) if htmx && !redirect => return render_inline(response, &message).await,
+        Some(message) => {
+            if let Some(cookie) = encrypt(&config, &message) {
+                response.response_mut().add_cookie(&cookie)?;
+            }
+        }
+        // Messages not shown yet stay for the next page, while shown and forged ones go
+        None => {
+            let consumed = response.request().extensions().contains::<FlashConsumed>();
+            if incoming.is_some() && (consumed || !readable) {
+                let mut removal = flash_cookie(String::new());
+                removal.make_removal();
+                response.response_mut().add_cookie(&removal)?;
+            }
+        }
+    }
+    Ok(response)
+}
+
+// Prepend the message to the body as an out-of-band swap. Error responses only carry the
+// message, so they don't replace the content HTMX targeted.
+async fn render_inline(
+    response: ServiceResponse<BoxBody>,
+    message: &FlashMessage,
+) -> Result<ServiceResponse<BoxBody>, actix_web::Error> {
+    let html = views::get_partial_flash(Some(message), true)
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    let (request, response) = response.into_parts();
+    let (mut response, body) = response.into_parts();
+    let body = to_bytes(body)
+        .await
+        .map_err(|_| actix_web::error::ErrorInternalServerError("Failed to read the response"))?;
+
+    if response.status().is_client_error() || response.status().is_server_error() {
+        response.headers_mut().insert(
+            HeaderName::from_static("hx-reswap"),
+            HeaderValue::from_static("none"),
+        );
+    }
+    let mut combined = html.into_bytes();
+    combined.extend_from_slice(&body);
+    Ok(ServiceResponse::new(
+        request,
+        response.set_body(BoxBody::new(combined)),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::test_config;
+    use actix_web::{App, get, middleware::from_fn, test};
+
+    #[get("/set")]
+    async fn set() -> HttpResponse {
+        FlashMessage::error("Incorrect <solution>").set(HttpResponse::BadRequest().body("error"))
+    }
+
+    #[get("/redirect")]
+    async fn redirect() -> HttpResponse {
+        FlashMessage::info("Welcome!").set(
+            HttpResponse::TemporaryRedirect()
+                .append_header(("HX-Redirect", "/"))
+                .finish(),
+        )
+    }
+
+    #[get("/show")]
+    async fn show(request: HttpRequest) -> HttpResponse {
+        let message = FlashMessage::take(&request);
+        HttpResponse::Ok().body(message.map(|m| m.message).unwrap_or_default())
+    }
+
+    #[actix_web::test]
+    async fn test_flash_messages() {
+        let app = test::init_service(
+            App::new()
+                .wrap(from_fn(flash_messages))
+                .app_data(web::Data::new(test_config()))
+                .service(set)
+                .service(redirect)
+                .service(show),
+        )
+        .await;
+
+        // HTMX requests staying on the page get the message right away
+        let resp = test::call_service(
+            &app,
+            test::TestRequest::get()
+                .uri("/set")
+                .insert_header(("HX-Request", "true"))
+                .to_request(),
+        )
+        .await;
+        assert_eq!(resp.headers().get("hx-reswap").unwrap(), "none");
+        assert!(resp.response().cookies().next().is_none());
+        let body = String::from_utf8(test::read_body(resp).await.to_vec()).unwrap();
+        assert!(body.contains("hx-swap-oob=\"true\""));
+        assert!(body.contains("flash-message-error"));
+        assert!(body.contains("Incorrect &#60;solution&#62;"));
+        assert!(body.ends_with("error"));
+
+        // Redirects keep it in an encrypted cookie for the next page
+        let resp = test::call_service(
+            &app,
+            test::TestRequest::get()
+                .uri("/redirect")
+                .insert_header(("HX-Request", "true"))
+                .to_request(),
+        )
+        .await;
+        let cookie = resp
+            .response()
+            .cookies()
+            .find(|c| c.name() == FLASH_COOKIE_NAME)
+            .unwrap()
+            .into_owned();
+        assert!(cookie.http_only().unwrap());
+        assert!(!cookie.value().contains("Welcome"));
+
+        // The next page shows it once and removes the cookie
+        let resp = test::call_service(
+            &app,
+            test::TestRequest::get()
+                .uri("/show")
+                .cookie(cookie.clone())
+                .to_request(),
+        )
+        .await;
+        let removal = resp
+            .response()
+            .cookies()
+            .find(|c| c.name() == FLASH_COOKIE_NAME)
+            .unwrap()
+            .into_owned();
+        assert_eq!(removal.value(), "");
+        assert_eq!(test::read_body(resp).await, "Welcome!");
+
+        // Forged cookies are ignored and removed
+        let forged = Cookie::new(
+            FLASH_COOKIE_NAME,
+            serde_json::to_string(&FlashMessage::info("Forged")).unwrap(),
+        );
+        let resp = test::call_service(
+            &app,
+            test::TestRequest::get()
+                .uri("/show")
+                .cookie(forged)
+                .to_request(),
+        )
+        .await;
+        assert!(
+            resp.response()
+                .cookies()
+                .any(|c| c.name() == FLASH_COOKIE_NAME)
+        );
+        assert_eq!(test::read_body(resp).await, "");
+    }
 }
@@ -0,0 +1,261 @@
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::middleware::Next;
+use actix_web::rt;
+use chrono::Utc;
+use prometheus::{
+    Encoder, Histogram, HistogramOpts, HistogramVec, IntCounterVec, IntGauge, Opts, Registry,
+    TextEncoder,
+};
+use sqlx::SqlitePool;
+use std::sync::LazyLock;
+use std::time::Instant;
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::api::ApiError;
+use crate::events::{ChainEvent, EventBus};
+use crate::models::Block;
+use crate::utils::start_of_week;
+
+// Metrics live in their own registry rather than the global default one, so only what is
+// defined here is exported
+static REGISTRY: LazyLock<Registry> = LazyLock::new(Registry::new);
+
+fn register<T: prometheus::core::Collector + Clone + 'static>(collector: T) -> T {
+    REGISTRY
+        .register(Box::new(collector.clone()))
+        .expect("Metric names should be unique");
+    collector
+}
+
+pub static HTTP_REQUESTS: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register(
+        IntCounterVec::new(
+            Opts::new("http_requests_total", "HTTP requests by route and status"),
+            &["method", "route", "status"],
+        )
+        .expect("Valid metric"),
+    )
+});
+
+pub static HTTP_REQUEST_DURATION: LazyLock<HistogramVec> = LazyLock::new(|| {
+    register(
+        HistogramVec::new(
+            HistogramOpts::new(
+                "http_request_duration_seconds",
+                "Time to answer HTTP requests by route",
+            ),
+            &["method", "route"],
+        )
+        .expect("Valid metric"),
+    )
+});
+
+pub static BLOCK_SUBMISSIONS: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register(
+        IntCounterVec::new(
+            Opts::new("block_submissions_total", "Submitted blocks by outcome"),
+            &["outcome"],
+        )
+        .expect("Valid metric"),
+    )
+});
+
+pub static CACHE_LOOKUPS: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register(
+        IntCounterVec::new(
+            Opts::new(
+                "cache_lookups_total",
+                "Cache lookups by result (hit or miss)",
+            ),
+            &["result"],
+        )
+        .expect("Valid metric"),
+    )
+});
+
+pub static VERIFICATION_DURATION: LazyLock<Histogram> = LazyLock::new(|| {
+    register(
+        Histogram::with_opts(
+            HistogramOpts::new(
+                "solution_verification_duration_seconds",
+                "Time to check that a solution solves its scramble",
+            )
+            .buckets(vec![0.0001, 0.0005, 0.001, 0.005, 0.01, 0.05, 0.1]),
+        )
+        .expect("Valid metric"),
+    )
+});
+
+pub static CHAIN_HEIGHT: LazyLock<IntGauge> = LazyLock::new(|| {
+    register(IntGauge::new("chain_height", "Height of the highest block").expect("Valid metric"))
+});
+
+pub static MAIN_CHAIN_LENGTH: LazyLock<IntGauge> = LazyLock::new(|| {
+    register(
+        IntGauge::new(
+            "main_chain_length",
+            "Blocks in the main chain, genesis included",
+        )
+        .expect("Valid metric"),
+    )
+});
+
+pub static INVALID_BLOCKS: LazyLock<IntGauge> = LazyLock::new(|| {
+    register(
+        IntGauge::new(
+            "chain_invalid_blocks",
+            "Blocks that failed verification, as of the last readiness check",
+        )
+        .expect("Valid metric"),
+    )
+});
+
+pub static ROUND_BLOCKS: LazyLock<IntGauge> = LazyLock::new(|| {
+    register(
+        IntGauge::new("round_blocks", "Blocks created in the current round").expect("Valid metric"),
+    )
+});
+
+// Outcome label of a block submission
+pub fn submission_outcome(result: &Result<Block, ApiError>) -> &'static str {
+    match result {
+        Ok(_) => "accepted",
+        Err(ApiError::WrongSolution) => "wrong",
+        Err(ApiError::DuplicateSolution | ApiError::DuplicateBlock) => "duplicate",
+        Err(ApiError::ParentNotEligible) => "ineligible_parent",
+        Err(ApiError::RateLimited { .. }) => "rate_limited",
+        Err(ApiError::Internal) => "error",
+        Err(_) => "invalid",
+    }
+}
+
+pub fn record_submission(result: &Result<Block, ApiError>) {
+    BLOCK_SUBMISSIONS
+        .with_label_values(&[submission_outcome(result)])
+        .inc();
+}
+
+pub fn record_cache_lookup(hit: bool) {
+    CACHE_LOOKUPS
+        .with_label_values(&[if hit { "hit" } else { "miss" }])
+        .inc();
+}
+
+// Read the chain gauges from the database
+pub async fn update_chain_gauges(db: &SqlitePool) -> Result<(), sqlx::Error> {
+    let tip = Block::find_tip(db).await?;
+    CHAIN_HEIGHT.set(tip.map_or(0, |b| b.height));
+    MAIN_CHAIN_LENGTH.set(Block::get_main_chain_hashes(db).await?.len() as i64);
+    let round_start = start_of_week(Utc::now().naive_utc());
+    ROUND_BLOCKS.set(Block::count_created_since(db, round_start).await?);
+    Ok(())
+}
+
+// Keep the chain gauges up to date, so scrapes don't query the database. They only change
+// when a block is added or a new round starts.
+pub fn start_chain_gauges_task(db: SqlitePool, events: &EventBus) {
+    let mut receiver = events.subscribe();
+
+    rt::spawn(async move {
+        loop {
+            if let Err(e) = update_chain_gauges(&db).await {
+                tracing::error!(error = %e, "failed to update chain metrics");
+            }
+
+            loop {
+                match receiver.recv().await {
+                    Ok(ChainEvent::BlockCreated { .. } | ChainEvent::RoundStarted { .. })
+                    | Err(RecvError::Lagged(_)) => break,
+                    Ok(ChainEvent::TipChanged { .. }) => continue,
+                    Err(RecvError::Closed) => return,
+                }
+            }
+        }
+    });
+}
+
+// Every metric in the Prometheus text format
+pub fn render() -> String {
+    // Make sure every metric shows up, even before its first observation
+    for outcome in [
+        "accepted",
+        "wrong",
+        "duplicate",
+        "ineligible_parent",
+        "rate_limited",
+    ] {
+        BLOCK_SUBMISSIONS.with_label_values(&[outcome]);
+    }
+    for result in ["hit", "miss"] {
+        CACHE_LOOKUPS.with_label_values(&[result]);
+    }
+    LazyLock::force(&VERIFICATION_DURATION);
+    LazyLock::force(&CHAIN_HEIGHT);
+    LazyLock::force(&MAIN_CHAIN_LENGTH);
+    LazyLock::force(&ROUND_BLOCKS);
+    LazyLock::force(&INVALID_BLOCKS);
+    LazyLock::force(&HTTP_REQUESTS);
+    LazyLock::force(&HTTP_REQUEST_DURATION);
+
+    let mut buffer = Vec::new();
+    TextEncoder::new()
+        .encode(&REGISTRY.gather(), &mut buffer)
+        .expect("Metrics should encode to text");
+    String::from_utf8(buffer).expect("Metrics text should be UTF-8")
+}
+
+// Middleware counting requests and their latency by route pattern (e.g. "/b/{hash}"), so the
+// labels don't grow with every block
+pub async fn track_requests(
+    request: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let start = Instant::now();
+    let method = request.method().to_string();
+    let response = next.call(request).await?;
+
+    let route = response
+        .request()
+        .match_pattern()
+        .unwrap_or_else(|| "unmatched".to_string());
+    HTTP_REQUESTS
+        .with_label_values(&[&method, &route, response.status().as_str()])
+        .inc();
+    HTTP_REQUEST_DURATION
+        .with_label_values(&[&method, &route])
+        .observe(start.elapsed().as_secs_f64());
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[sqlx::test(fixtures("../fixtures/blocks.sql"))]
+    async fn test_chain_gauges(pool: SqlitePool) {
+        update_chain_gauges(&pool).await.unwrap();
+
+        let main_chain_length = Block::get_main_chain_hashes(&pool).await.unwrap().len();
+        assert_eq!(MAIN_CHAIN_LENGTH.get(), main_chain_length as i64);
+        let tip = Block::find_tip(&pool).await.unwrap().unwrap();
+        assert_eq!(CHAIN_HEIGHT.get(), tip.height);
+        assert!(render().contains(&format!("main_chain_length {}", main_chain_length)));
+    }
+
+    #[test]
+    fn test_render() {
+        record_cache_lookup(true);
+        assert_eq!(
+            submission_outcome(&Err(ApiError::DuplicateBlock)),
+            "duplicate"
+        );
+
+        let text = render();
+        assert!(text.contains("# TYPE block_submissions_total counter"));
+        assert!(text.contains("block_submissions_total{outcome=\"ineligible_parent\"}"));
+        assert!(text.contains("cache_lookups_total{result=\"hit\"}"));
+        assert!(text.contains("# TYPE solution_verification_duration_seconds histogram"));
+        assert!(text.contains("# TYPE chain_height gauge"));
+    }
+}
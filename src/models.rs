@@ -1,8 +1,13 @@
-use chrono::{Datelike, NaiveDateTime, Utc, Weekday};
-use sqlx::{FromRow, SqlitePool};
+use chrono::{NaiveDate, NaiveDateTime, Utc};
+use serde::Serialize;
+use sqlx::{Acquire, FromRow, Sqlite, SqliteExecutor, SqlitePool};
 use std::collections::HashSet;
+use utoipa::ToSchema;
 
-use crate::utils;
+use crate::challenges::{self, ChallengeAttempt};
+use crate::cube::{self, Move, ReplayStep};
+use crate::identity::{self, Bindings};
+use crate::{utils, validation};
 
 #[derive(Debug, PartialEq, Eq)]
 pub enum BlockTag {
@@ -10,6 +15,7 @@ pub enum BlockTag {
     New,
     Recommended,
     MainChain,
+    Verified,
 }
 
 impl BlockTag {
@@ -19,6 +25,7 @@ impl BlockTag {
             BlockTag::New => "New",
             BlockTag::Recommended => "Recommended",
             BlockTag::MainChain => "Main Chain",
+            BlockTag::Verified => "✓ Verified",
         }
     }
 
@@ -28,11 +35,12 @@ impl BlockTag {
             BlockTag::New => "new",
             BlockTag::Recommended => "recommended",
             BlockTag::MainChain => "main_chain",
+            BlockTag::Verified => "verified",
         }
     }
 }
 
-#[derive(Debug, Clone, FromRow)]
+#[derive(Debug, Clone, FromRow, Serialize, ToSchema)]
 pub struct Block {
     pub version: u8,
     pub hash: String,
@@ -44,9 +52,29 @@ pub struct Block {
     pub solution_moves: u8,
     pub solution_description: String,
     pub created_at: Option<NaiveDateTime>,
+    // Hex encoded ed25519 key of the identity that signed the block
+    pub public_key: Option<String>,
+    // Hex encoded signature over `identity::block_data`
+    pub signature: Option<String>,
+    // Account that posted the block, if any
+    pub user_id: Option<i64>,
+    // Number of attempts of a mean of N challenge, whose solutions are separated by " / "
+    // and whose solution_moves is their rounded mean. None for a single solution.
+    pub mean_of: Option<u8>,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct BlockReplay {
+    pub hash: String,
+    pub scramble: String,
+    pub solution: String,
+    pub steps: Vec<ReplayStep>,
 }
 
 impl Block {
+    // Version of the blocks created now, see `validation::NORMALIZED_VERSION`
+    pub const CURRENT_VERSION: u8 = validation::NORMALIZED_VERSION;
+
     // Check if the block hash and solutions are valid
     pub fn is_valid(&self) -> bool {
         let expected_hash = utils::calculate_hash(&utils::format_data(
@@ -54,23 +82,93 @@ impl Block {
             &self.name,
             &self.message,
         ));
-        let scramble = utils::parse_moves(&self.scramble());
-        let solution = utils::parse_moves(&self.solution);
 
-        utils::verify_solution(&scramble, &solution)
-            && self.solution_moves == solution.len() as u8
+        self.has_valid_solution()
             && !self.hash.is_empty()
             && self.hash == expected_hash
+            && validation::follows_rules(self.version, &self.name, &self.message)
+            && self.has_valid_signature()
     }
 
-    // Get scramble for this block
-    pub fn scramble(&self) -> String {
-        let scramble = match self.version {
-            1 => utils::scramble_from_hash_v1(&self.hash),
-            2 => utils::scramble_from_hash(&self.hash),
-            _ => panic!("Unsupported block version"),
+    // A single solution must solve the scramble of the block, the solutions of a challenge
+    // block the scrambles of their attempts
+    fn has_valid_solution(&self) -> bool {
+        let Some(mean_of) = self.mean_of else {
+            let Some(scramble) = self.scramble_moves() else {
+                return false;
+            };
+            let solution = utils::parse_moves(&self.solution);
+            return utils::verify_solution(&scramble, &solution)
+                && self.solution_moves == solution.len() as u8;
         };
-        utils::format_moves(&scramble)
+
+        let solutions = challenges::split_solutions(&self.solution);
+        let parent_hash = self.parent_hash.as_deref().unwrap_or("");
+        let mut moves = Vec::new();
+        for (attempt_index, solution) in (1..=mean_of).zip(&solutions) {
+            let hash =
+                challenges::attempt_hash(parent_hash, &self.name, &self.message, attempt_index);
+            let solution = utils::parse_moves(solution);
+            if !utils::verify_solution(&utils::scramble_from_hash(&hash), &solution) {
+                return false;
+            }
+            moves.push(solution.len() as u8);
+        }
+
+        solutions.len() == mean_of as usize
+            && self.solution_moves == challenges::rounded_mean(&moves)
+    }
+
+    // Unsigned blocks are valid, signed blocks need a signature matching their public key
+    fn has_valid_signature(&self) -> bool {
+        match (&self.public_key, &self.signature) {
+            (None, None) => true,
+            (Some(public_key), Some(signature)) => identity::verify_signature(
+                public_key,
+                &identity::block_data(&self.hash, &self.solution),
+                signature,
+            ),
+            _ => false,
+        }
+    }
+
+    // Returns true if the block carries a valid signature by the key bound to its name
+    pub fn is_verified(&self, bound_key: Option<&str>) -> bool {
+        self.signature.is_some()
+            && bound_key.is_some_and(|key| self.public_key.as_deref() == Some(key))
+            && self.has_valid_signature()
+    }
+
+    // Get scramble for this block, empty if its version is unsupported
+    pub fn scramble(&self) -> String {
+        self.scramble_moves()
+            .map(|moves| utils::format_moves(&moves))
+            .unwrap_or_default()
+    }
+
+    // Get scramble moves for this block, None if its version is unsupported
+    pub fn scramble_moves(&self) -> Option<Vec<Move>> {
+        match self.version {
+            1 => Some(utils::scramble_from_hash_v1(&self.hash)),
+            2 | 3 => Some(utils::scramble_from_hash(&self.hash)),
+            _ => None,
+        }
+    }
+
+    // Get the cube state after every move of the solution, marking annotated steps.
+    // None if the version of the block is unsupported.
+    pub fn replay(&self) -> Option<BlockReplay> {
+        let scramble = self.scramble_moves()?;
+        let solution = utils::parse_moves(&self.solution);
+        let annotations = utils::parse_step_annotations(&self.solution_description);
+        let step_ends = utils::step_ends(&solution, &annotations);
+
+        Some(BlockReplay {
+            hash: self.hash.clone(),
+            scramble: utils::format_moves(&scramble),
+            solution: self.solution.clone(),
+            steps: cube::replay(&scramble, &solution, &step_ends),
+        })
     }
 
     // Returns true if the user is allowed to create a child block
@@ -79,6 +177,7 @@ impl Block {
     }
 
     // Returns the number of recommended blocks
+    #[tracing::instrument(level = "debug", skip_all)]
     pub async fn get_recommended_count(db: &SqlitePool) -> Result<usize, sqlx::Error> {
         let blocks = Self::find_all(db, false, None, None)
             .await?
@@ -103,6 +202,7 @@ impl Block {
         time: Option<NaiveDateTime>,
         main_chain_hashes: &HashSet<String>,
         optimal_height: i64,
+        bindings: &Bindings,
     ) -> Vec<BlockTag> {
         let mut tags = vec![];
 
@@ -122,10 +222,15 @@ impl Block {
             tags.push(BlockTag::Genesis);
         }
 
+        if self.is_verified(bindings.public_key(&self.name)) {
+            tags.push(BlockTag::Verified);
+        }
+
         tags
     }
 
     // Return true if the solution moves for a given hash already exists
+    #[tracing::instrument(level = "debug", skip(db, solution))]
     pub async fn hash_and_solution_exists(
         db: &SqlitePool,
         hash: &str,
@@ -148,7 +253,7 @@ impl Block {
     // Fetch Block from database using its hash
     pub async fn from_hash(hash: &str) -> Self {
         sqlx::query_as::<_, Block>(
-            "SELECT hash, parent_hash, height, name, message, solution, solution_moves, solution_description, created_at
+            "SELECT hash, parent_hash, height, name, message, solution, solution_moves, solution_description, created_at, public_key, signature, user_id, mean_of
              FROM blocks
              WHERE hash = ?",
         )
@@ -162,26 +267,12 @@ impl Block {
     fn is_from_last_week(&self, time: Option<NaiveDateTime>) -> bool {
         let created_at = self.created_at.expect("Block should have a creation date");
         let now = time.unwrap_or_else(|| Utc::now().naive_utc());
-        let today = now.date();
-        let days_since_monday = match today.weekday() {
-            Weekday::Mon => 0,
-            Weekday::Tue => 1,
-            Weekday::Wed => 2,
-            Weekday::Thu => 3,
-            Weekday::Fri => 4,
-            Weekday::Sat => 5,
-            Weekday::Sun => 6,
-        };
 
-        let start_of_week_date = today - chrono::Duration::days(days_since_monday);
-        let start_of_week = start_of_week_date
-            .and_hms_opt(0, 0, 0)
-            .expect("Failed to get start of week");
-
-        created_at < start_of_week
+        created_at < utils::start_of_week(now)
     }
 
     // Get a list of hashes of blocks in the main chain
+    #[tracing::instrument(level = "debug", skip_all)]
     pub async fn get_main_chain_hashes(db: &SqlitePool) -> Result<HashSet<String>, sqlx::Error> {
         Ok(sqlx::query_scalar!(
             r#"
@@ -212,6 +303,7 @@ impl Block {
     }
 
     // Fetch all blocks
+    #[tracing::instrument(level = "debug", skip(db))]
     pub async fn find_all(
         db: &SqlitePool,
         main_chain_only: bool,
@@ -219,7 +311,7 @@ impl Block {
         page_offset: Option<u32>,
     ) -> Result<Vec<Block>, sqlx::Error> {
         let mut query_str = String::from(
-            "SELECT version, hash, parent_hash, height, name, message, solution, solution_moves, solution_description, created_at
+            "SELECT version, hash, parent_hash, height, name, message, solution, solution_moves, solution_description, created_at, public_key, signature, user_id, mean_of
              FROM blocks"
         );
 
@@ -261,10 +353,129 @@ impl Block {
         query.fetch_all(db).await
     }
 
+    // Fetch the most recently created blocks, optionally limited to the main chain or a name
+    #[tracing::instrument(level = "debug", skip(db))]
+    pub async fn find_recent(
+        db: &SqlitePool,
+        main_chain_only: bool,
+        name: Option<&str>,
+        limit: u32,
+    ) -> Result<Vec<Block>, sqlx::Error> {
+        sqlx::query_as::<_, Block>(
+            "WITH RECURSIVE main_chain AS (
+                SELECT hash, parent_hash
+                FROM blocks
+                WHERE ?2 AND hash = (
+                    SELECT hash
+                    FROM blocks
+                    ORDER BY height DESC, solution_moves ASC
+                    LIMIT 1
+                )
+
+                UNION ALL
+
+                SELECT b.hash, b.parent_hash
+                FROM blocks b
+                INNER JOIN main_chain mc ON b.hash = mc.parent_hash
+            )
+            SELECT version, hash, parent_hash, height, name, message, solution, solution_moves, solution_description, created_at, public_key, signature, user_id, mean_of
+            FROM blocks
+            WHERE (?1 IS NULL OR name = ?1)
+              AND (NOT ?2 OR hash IN (SELECT hash FROM main_chain))
+            ORDER BY created_at DESC, height DESC
+            LIMIT ?3",
+        )
+        .bind(name)
+        .bind(main_chain_only)
+        .bind(limit)
+        .fetch_all(db)
+        .await
+    }
+
+    // Fetch the blocks created between two dates, both included
+    #[tracing::instrument(level = "debug", skip(db))]
+    pub async fn find_created_between(
+        db: &SqlitePool,
+        from: NaiveDate,
+        to: NaiveDate,
+    ) -> Result<Vec<Block>, sqlx::Error> {
+        sqlx::query_as::<_, Block>(
+            "SELECT version, hash, parent_hash, height, name, message, solution, solution_moves, solution_description, created_at, public_key, signature, user_id, mean_of
+             FROM blocks
+             WHERE date(created_at) BETWEEN ? AND ?
+             ORDER BY created_at ASC, height ASC",
+        )
+        .bind(from)
+        .bind(to)
+        .fetch_all(db)
+        .await
+    }
+
+    // Whether any block was posted under the given name
+    #[tracing::instrument(level = "debug", skip(db))]
+    pub async fn name_has_blocks(db: &SqlitePool, name: &str) -> Result<bool, sqlx::Error> {
+        sqlx::query_scalar::<_, bool>("SELECT EXISTS(SELECT 1 FROM blocks WHERE name = ?)")
+            .bind(name)
+            .fetch_one(db)
+            .await
+    }
+
+    // Fetch every block by the given name, oldest first
+    #[tracing::instrument(level = "debug", skip(db))]
+    pub async fn find_by_name(db: &SqlitePool, name: &str) -> Result<Vec<Block>, sqlx::Error> {
+        sqlx::query_as::<_, Block>(
+            "SELECT version, hash, parent_hash, height, name, message, solution, solution_moves, solution_description, created_at, public_key, signature, user_id, mean_of
+             FROM blocks
+             WHERE name = ?
+             ORDER BY created_at ASC, height ASC",
+        )
+        .bind(name)
+        .fetch_all(db)
+        .await
+    }
+
+    // Fetch the tip of the main chain
+    #[tracing::instrument(level = "debug", skip_all)]
+    pub async fn find_tip<'e>(db: impl SqliteExecutor<'e>) -> Result<Option<Block>, sqlx::Error> {
+        Self::find_tip_excluding(db, None).await
+    }
+
+    // Fetch what the tip of the main chain would be without the given block, i.e. the tip
+    // before it was created
+    #[tracing::instrument(level = "debug", skip(db))]
+    pub async fn find_tip_excluding<'e>(
+        db: impl SqliteExecutor<'e>,
+        hash: Option<&str>,
+    ) -> Result<Option<Block>, sqlx::Error> {
+        sqlx::query_as::<_, Block>(
+            "SELECT version, hash, parent_hash, height, name, message, solution, solution_moves, solution_description, created_at, public_key, signature, user_id, mean_of
+             FROM blocks
+             WHERE ?1 IS NULL OR hash != ?1
+             ORDER BY height DESC, solution_moves ASC
+             LIMIT 1",
+        )
+        .bind(hash)
+        .fetch_optional(db)
+        .await
+    }
+
+    // Count the blocks created from the given time on, e.g. in the current round
+    #[tracing::instrument(level = "debug", skip(db))]
+    pub async fn count_created_since(
+        db: &SqlitePool,
+        since: NaiveDateTime,
+    ) -> Result<i64, sqlx::Error> {
+        sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM blocks WHERE created_at >= ?")
+            .bind(since)
+            .fetch_one(db)
+            .await
+    }
+
     // Fetch a block by hash
+    #[tracing::instrument(level = "debug", skip(db))]
     pub async fn find_by_hash(db: &SqlitePool, hash: &str) -> Result<Block, sqlx::Error> {
         sqlx::query_as::<_, Block>(
-            "SELECT version, hash, parent_hash, height, name, message, solution, solution_moves, solution_description, created_at
+            "SELECT version, hash, parent_hash, height, name, message, solution, solution_moves, solution_description, created_at, public_key, signature, user_id, mean_of
              FROM blocks
              WHERE hash = ?",
         )
@@ -273,7 +484,64 @@ impl Block {
         .await
     }
 
+    // Fetch blocks whose hash starts with the given prefix
+    #[tracing::instrument(level = "debug", skip(db))]
+    pub async fn find_by_hash_prefix(
+        db: &SqlitePool,
+        prefix: &str,
+        limit: u32,
+    ) -> Result<Vec<Block>, sqlx::Error> {
+        sqlx::query_as::<_, Block>(
+            "SELECT version, hash, parent_hash, height, name, message, solution, solution_moves, solution_description, created_at, public_key, signature, user_id, mean_of
+             FROM blocks
+             WHERE substr(hash, 1, length(?1)) = ?1
+             ORDER BY height DESC, solution_moves ASC
+             LIMIT ?2",
+        )
+        .bind(prefix)
+        .bind(limit)
+        .fetch_all(db)
+        .await
+    }
+
+    // Fetch the direct children of this block
+    #[tracing::instrument(level = "debug", skip_all, fields(hash = %self.hash))]
+    pub async fn find_children(&self, db: &SqlitePool) -> Result<Vec<Block>, sqlx::Error> {
+        sqlx::query_as::<_, Block>(
+            "SELECT version, hash, parent_hash, height, name, message, solution, solution_moves, solution_description, created_at, public_key, signature, user_id, mean_of
+             FROM blocks
+             WHERE parent_hash = ?
+             ORDER BY solution_moves ASC, created_at ASC",
+        )
+        .bind(&self.hash)
+        .fetch_all(db)
+        .await
+    }
+
+    // Fetch the path from this block's parent back to the genesis block
+    #[tracing::instrument(level = "debug", skip_all, fields(hash = %self.hash))]
+    pub async fn find_ancestors(&self, db: &SqlitePool) -> Result<Vec<Block>, sqlx::Error> {
+        sqlx::query_as::<_, Block>(
+            "WITH RECURSIVE ancestors AS (
+                SELECT version, hash, parent_hash, height, name, message, solution, solution_moves, solution_description, created_at, public_key, signature, user_id, mean_of
+                FROM blocks
+                WHERE hash = ?
+
+                UNION ALL
+
+                SELECT b.version, b.hash, b.parent_hash, b.height, b.name, b.message, b.solution, b.solution_moves, b.solution_description, b.created_at, b.public_key, b.signature, b.user_id, b.mean_of
+                FROM blocks b
+                INNER JOIN ancestors a ON b.hash = a.parent_hash
+            )
+            SELECT * FROM ancestors ORDER BY height DESC",
+        )
+        .bind(self.parent_hash.as_deref().unwrap_or(""))
+        .fetch_all(db)
+        .await
+    }
+
     // Create a genesis block
+    #[tracing::instrument(level = "debug", skip_all, fields(hash = %hash))]
     pub async fn create_genesis(
         db: &SqlitePool,
         hash: &str,
@@ -283,12 +551,15 @@ impl Block {
         solution_moves: u8,
         solution_description: &str,
     ) -> Result<Self, sqlx::Error> {
-        sqlx::query_as::<_, Block>(
+        // Commit explicitly: a dropped `INSERT .. RETURNING` statement might not be finalized yet
+        let mut tx = db.begin().await?;
+        let block = sqlx::query_as::<_, Block>(
             "INSERT INTO blocks (
-                hash, height, name, message, solution, solution_moves, solution_description
-            ) VALUES (?, ?, ?, ?, ?, ?, ?)
-            RETURNING version, hash, parent_hash, height, name, message, solution, solution_moves, solution_description, created_at",
+                version, hash, height, name, message, solution, solution_moves, solution_description
+            ) VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+            RETURNING version, hash, parent_hash, height, name, message, solution, solution_moves, solution_description, created_at, public_key, signature, user_id, mean_of",
         )
+        .bind(Self::CURRENT_VERSION)
         .bind(hash)
         .bind(0)
         .bind(name)
@@ -296,28 +567,43 @@ impl Block {
         .bind(solution)
         .bind(solution_moves)
         .bind(solution_description)
-        .fetch_one(db)
-        .await
+        .fetch_one(&mut *tx)
+        .await?;
+        tx.commit().await?;
+
+        Ok(block)
     }
 
-    // Create a child block
+    // Create a child block, optionally signed as `(public_key, signature)` or posted by a user.
+    // Revealed blocks keep the time they were committed at, anything else is created now.
+    // Challenge blocks pass their attempts, which are stored along with the block.
+    // Inside a transaction, the block is only stored once the caller commits.
     #[allow(clippy::too_many_arguments)]
-    pub async fn create_child(
+    #[tracing::instrument(level = "debug", skip_all, fields(parent_hash = %self.hash, hash = %hash))]
+    pub async fn create_child<'c>(
         &self,
-        db: &SqlitePool,
+        db: impl Acquire<'c, Database = Sqlite>,
         hash: &str,
         name: &str,
         message: &str,
         solution: &str,
         solution_moves: u8,
         solution_description: &str,
+        signed_by: Option<(&str, &str)>,
+        user_id: Option<i64>,
+        created_at: Option<NaiveDateTime>,
+        attempts: &[ChallengeAttempt],
     ) -> Result<Self, sqlx::Error> {
-        sqlx::query_as::<_, Block>(
+        let (public_key, signature) = signed_by.unzip();
+        let mean_of = (!attempts.is_empty()).then_some(attempts.len() as u8);
+        let mut tx = db.begin().await?;
+        let block = sqlx::query_as::<_, Block>(
             "INSERT INTO blocks (
-                hash, parent_hash, height, name, message, solution, solution_moves, solution_description
-            ) VALUES (?, ?, ?, ?, ?, ?, ?, ?)
-            RETURNING version, hash, parent_hash, height, name, message, solution, solution_moves, solution_description, created_at",
+                version, hash, parent_hash, height, name, message, solution, solution_moves, solution_description, public_key, signature, user_id, created_at, mean_of
+            ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, COALESCE(?, CURRENT_TIMESTAMP), ?)
+            RETURNING version, hash, parent_hash, height, name, message, solution, solution_moves, solution_description, created_at, public_key, signature, user_id, mean_of",
         )
+        .bind(Self::CURRENT_VERSION)
         .bind(hash)
         .bind(&self.hash)
         .bind(self.height + 1)
@@ -326,8 +612,36 @@ impl Block {
         .bind(solution)
         .bind(solution_moves)
         .bind(solution_description)
-        .fetch_one(db)
-        .await
+        .bind(public_key)
+        .bind(signature)
+        .bind(user_id)
+        .bind(created_at)
+        .bind(mean_of)
+        .fetch_one(&mut *tx)
+        .await?;
+        for attempt in attempts {
+            sqlx::query(
+                "INSERT INTO challenge_attempts (block_hash, attempt_index, hash, solution, solution_moves)
+                VALUES (?, ?, ?, ?, ?)",
+            )
+            .bind(hash)
+            .bind(attempt.attempt_index)
+            .bind(&attempt.hash)
+            .bind(&attempt.solution)
+            .bind(attempt.solution_moves)
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await?;
+
+        Ok(block)
+    }
+
+    // Creation time in RFC 3339 format, as used in feeds
+    pub fn created_at_rfc3339(&self) -> String {
+        self.created_at
+            .map(|t| t.format("%Y-%m-%dT%H:%M:%SZ").to_string())
+            .unwrap_or_default()
     }
 
     pub fn short_hash(&self) -> String {
@@ -335,6 +649,102 @@ impl Block {
     }
 }
 
+// A block committed to in a commit-reveal round. Only the commitment to its solution is known
+// until the round closes, after which revealing the solution turns it into a regular block.
+#[derive(Debug, Clone, FromRow, Serialize, ToSchema)]
+pub struct PendingBlock {
+    pub hash: String,
+    pub parent_hash: String,
+    pub name: String,
+    pub message: String,
+    // Hex encoded sha256(solution || salt)
+    pub commitment: String,
+    #[serde(skip)]
+    pub user_id: Option<i64>,
+    pub created_at: Option<NaiveDateTime>,
+}
+
+impl PendingBlock {
+    pub async fn create(
+        db: &SqlitePool,
+        hash: &str,
+        parent_hash: &str,
+        name: &str,
+        message: &str,
+        commitment: &str,
+        user_id: Option<i64>,
+    ) -> Result<Self, sqlx::Error> {
+        let mut tx = db.begin().await?;
+        let pending = sqlx::query_as::<_, PendingBlock>(
+            "INSERT INTO pending_blocks (hash, parent_hash, name, message, commitment, user_id)
+            VALUES (?, ?, ?, ?, ?, ?)
+            RETURNING hash, parent_hash, name, message, commitment, user_id, created_at",
+        )
+        .bind(hash)
+        .bind(parent_hash)
+        .bind(name)
+        .bind(message)
+        .bind(commitment)
+        .bind(user_id)
+        .fetch_one(&mut *tx)
+        .await?;
+        tx.commit().await?;
+
+        Ok(pending)
+    }
+
+    pub async fn find_by_hash(db: &SqlitePool, hash: &str) -> Result<Option<Self>, sqlx::Error> {
+        sqlx::query_as::<_, PendingBlock>(
+            "SELECT hash, parent_hash, name, message, commitment, user_id, created_at
+            FROM pending_blocks
+            WHERE hash = ?",
+        )
+        .bind(hash)
+        .fetch_optional(db)
+        .await
+    }
+
+    // All unrevealed blocks, oldest first
+    pub async fn find_all(db: &SqlitePool) -> Result<Vec<Self>, sqlx::Error> {
+        sqlx::query_as::<_, PendingBlock>(
+            "SELECT hash, parent_hash, name, message, commitment, user_id, created_at
+            FROM pending_blocks
+            ORDER BY created_at ASC, hash ASC",
+        )
+        .fetch_all(db)
+        .await
+    }
+
+    pub async fn delete<'e>(&self, db: impl SqliteExecutor<'e>) -> Result<(), sqlx::Error> {
+        sqlx::query("DELETE FROM pending_blocks WHERE hash = ?")
+            .bind(&self.hash)
+            .execute(db)
+            .await?;
+        Ok(())
+    }
+}
+
+// Block with placeholder values for the unit tests, override fields with `..test_block(hash)`
+#[cfg(test)]
+pub fn test_block(hash: &str) -> Block {
+    Block {
+        version: 2,
+        hash: hash.to_string(),
+        parent_hash: None,
+        height: 0,
+        name: "test".to_string(),
+        message: "message".to_string(),
+        solution: "".to_string(),
+        solution_moves: 0,
+        solution_description: "".to_string(),
+        created_at: None,
+        public_key: None,
+        signature: None,
+        user_id: None,
+        mean_of: None,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -411,6 +821,10 @@ mod tests {
                 child_solution,
                 child_solution_moves,
                 child_solution_description,
+                None,
+                None,
+                None,
+                &[],
             )
             .await
             .expect("Failed to create child block");
@@ -476,6 +890,99 @@ mod tests {
         assert_eq!(paginated_blocks_page4[0].hash, "genesis_block_hash_001");
     }
 
+    #[sqlx::test(fixtures("../fixtures/blocks.sql"))]
+    async fn test_find_by_hash_prefix(pool: SqlitePool) {
+        let blocks = Block::find_by_hash_prefix(&pool, "main_chain_block_00", 10)
+            .await
+            .expect("Failed to find blocks by prefix");
+        assert_eq!(blocks.len(), 3);
+        assert_eq!(blocks[0].hash, "main_chain_block_004");
+
+        let blocks = Block::find_by_hash_prefix(&pool, "genesis", 10)
+            .await
+            .expect("Failed to find blocks by prefix");
+        assert_eq!(blocks.len(), 1);
+
+        let blocks = Block::find_by_hash_prefix(&pool, "%", 10)
+            .await
+            .expect("Failed to find blocks by prefix");
+        assert!(blocks.is_empty());
+    }
+
+    #[sqlx::test(fixtures("../fixtures/blocks.sql"))]
+    async fn test_find_children_and_ancestors(pool: SqlitePool) {
+        let genesis = Block::find_by_hash(&pool, "genesis_block_hash_001")
+            .await
+            .unwrap();
+        let children = genesis.find_children(&pool).await.unwrap();
+        let child_hashes = children.iter().map(|b| b.hash.as_str()).collect::<Vec<_>>();
+        assert_eq!(child_hashes.len(), 2);
+        assert!(child_hashes.contains(&"main_chain_block_002"));
+        assert!(child_hashes.contains(&"fork_chain_block_B_001"));
+        assert!(genesis.find_ancestors(&pool).await.unwrap().is_empty());
+
+        let block = Block::find_by_hash(&pool, "fork_chain_block_A_002")
+            .await
+            .unwrap();
+        let ancestors = block.find_ancestors(&pool).await.unwrap();
+        let ancestor_hashes = ancestors
+            .iter()
+            .map(|b| b.hash.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            ancestor_hashes,
+            vec![
+                "fork_chain_block_A_001",
+                "main_chain_block_002",
+                "genesis_block_hash_001"
+            ]
+        );
+    }
+
+    #[sqlx::test(fixtures("../fixtures/blocks.sql"))]
+    async fn test_find_recent(pool: SqlitePool) {
+        let blocks = Block::find_recent(&pool, false, None, 3).await.unwrap();
+        assert_eq!(blocks.len(), 3);
+        assert!(
+            blocks
+                .windows(2)
+                .all(|w| w[0].created_at >= w[1].created_at)
+        );
+
+        let blocks = Block::find_recent(&pool, true, None, 10).await.unwrap();
+        assert_eq!(blocks.len(), 4);
+        assert_eq!(blocks[0].hash, "main_chain_block_004");
+        let main_chain_hashes = Block::get_main_chain_hashes(&pool).await.unwrap();
+        assert!(blocks.iter().all(|b| main_chain_hashes.contains(&b.hash)));
+
+        // The limit applies to the filtered blocks
+        let blocks = Block::find_recent(&pool, true, None, 2).await.unwrap();
+        assert_eq!(blocks.len(), 2);
+        assert!(blocks.iter().all(|b| main_chain_hashes.contains(&b.hash)));
+
+        let blocks = Block::find_recent(&pool, false, Some("Alice"), 10)
+            .await
+            .unwrap();
+        assert!(!blocks.is_empty());
+        assert!(blocks.iter().all(|b| b.name == "Alice"));
+        assert_eq!(blocks[0].created_at_rfc3339().len(), 20);
+    }
+
+    #[sqlx::test(fixtures("../fixtures/blocks.sql"))]
+    async fn test_find_by_name(pool: SqlitePool) {
+        let blocks = Block::find_by_name(&pool, "Alice").await.unwrap();
+        assert!(!blocks.is_empty());
+        assert!(blocks.iter().all(|b| b.name == "Alice"));
+        assert!(
+            blocks
+                .windows(2)
+                .all(|w| w[0].created_at <= w[1].created_at)
+        );
+
+        let blocks = Block::find_by_name(&pool, "nobody").await.unwrap();
+        assert!(blocks.is_empty());
+    }
+
     #[sqlx::test(fixtures("../fixtures/blocks.sql"))]
     async fn test_get_main_chain_hashes(pool: SqlitePool) {
         let hashes = Block::get_main_chain_hashes(&pool).await.unwrap();
@@ -536,6 +1043,10 @@ mod tests {
                 "U D L R F B",
                 6,
                 "desc_a",
+                None,
+                None,
+                None,
+                &[],
             )
             .await;
         let _ = root
@@ -547,6 +1058,10 @@ mod tests {
                 "U D L R F B",
                 6,
                 "desc_b",
+                None,
+                None,
+                None,
+                &[],
             )
             .await;
         let main_chain_hashes = Block::get_main_chain_hashes(&pool)
@@ -565,72 +1080,49 @@ mod tests {
                 .expect("Failed to parse test time");
 
         let genesis_block = Block {
-            version: 2,
-            hash: "genesis_hash".to_string(),
-            parent_hash: None,
-            height: 0,
             name: "Genesis Block".to_string(),
-            message: "Initial block".to_string(),
-            solution: "".to_string(),
-            solution_moves: 0,
-            solution_description: "".to_string(),
             created_at: Some(current_test_time),
+            ..test_block("genesis_hash")
         };
 
         let block_a = Block {
-            version: 2,
-            hash: "block_a_hash".to_string(),
             parent_hash: Some("genesis_hash".to_string()),
             height: 1,
             name: "Block A".to_string(),
-            message: "Child of Genesis".to_string(),
-            solution: "A".to_string(),
             solution_moves: 5,
-            solution_description: "Solution A".to_string(),
             created_at: Some(current_test_time - Duration::minutes(1)),
+            ..test_block("block_a_hash")
         };
 
         let block_b = Block {
-            version: 2,
-            hash: "block_b_hash".to_string(),
             parent_hash: Some("genesis_hash".to_string()),
             height: 1,
             name: "Block B".to_string(),
-            message: "Another Child of Genesis".to_string(),
-            solution: "B".to_string(),
             solution_moves: 8,
-            solution_description: "Solution B".to_string(),
             created_at: Some(current_test_time - Duration::hours(1)),
+            ..test_block("block_b_hash")
         };
 
         let block_c = Block {
-            version: 2,
-            hash: "block_c_hash".to_string(),
             parent_hash: Some("genesis_hash".to_string()),
             height: 1,
             name: "Block C".to_string(),
-            message: "Old Child of Genesis".to_string(),
-            solution: "C".to_string(),
             solution_moves: 5,
-            solution_description: "Solution C".to_string(),
             created_at: Some(current_test_time - Duration::weeks(2)),
+            ..test_block("block_c_hash")
         };
 
         let block_d = Block {
-            version: 2,
-            hash: "block_d_hash".to_string(),
             parent_hash: Some("block_a_hash".to_string()),
             height: 2,
             name: "Block D".to_string(),
-            message: "Child of Block A".to_string(),
-            solution: "D".to_string(),
             solution_moves: 3,
-            solution_description: "Solution D".to_string(),
             created_at: Some(current_test_time - Duration::minutes(30)),
+            ..test_block("block_d_hash")
         };
         let optimal_height = block_a.height;
 
-        let mut blocks = vec![
+        let mut blocks = [
             block_a.clone(),
             block_d.clone(),
             genesis_block.clone(),
@@ -656,7 +1148,12 @@ mod tests {
             .iter()
             .find(|b| b.hash == block_a.hash)
             .expect("Block A should be present")
-            .tags(Some(current_test_time), &main_chain_hashes, optimal_height);
+            .tags(
+                Some(current_test_time),
+                &main_chain_hashes,
+                optimal_height,
+                &Bindings::default(),
+            );
         let expected_tags_a = vec![BlockTag::New, BlockTag::MainChain];
         assert_eq!(actual_tags_a, expected_tags_a, "Tags mismatch for Block A");
 
@@ -664,7 +1161,12 @@ mod tests {
             .iter()
             .find(|b| b.hash == block_d.hash)
             .expect("Block D should be present")
-            .tags(Some(current_test_time), &main_chain_hashes, optimal_height);
+            .tags(
+                Some(current_test_time),
+                &main_chain_hashes,
+                optimal_height,
+                &Bindings::default(),
+            );
         let expected_tags_d = vec![BlockTag::New, BlockTag::MainChain];
         assert_eq!(actual_tags_d, expected_tags_d, "Tags mismatch for Block D");
 
@@ -672,7 +1174,12 @@ mod tests {
             .iter()
             .find(|b| b.hash == genesis_block.hash)
             .expect("Genesis block should be present")
-            .tags(Some(current_test_time), &main_chain_hashes, optimal_height);
+            .tags(
+                Some(current_test_time),
+                &main_chain_hashes,
+                optimal_height,
+                &Bindings::default(),
+            );
         let expected_tags_genesis = vec![BlockTag::MainChain, BlockTag::Genesis];
         assert_eq!(
             actual_tags_genesis, expected_tags_genesis,
@@ -683,7 +1190,12 @@ mod tests {
             .iter()
             .find(|b| b.hash == block_b.hash)
             .expect("Block B should be present")
-            .tags(Some(current_test_time), &main_chain_hashes, optimal_height);
+            .tags(
+                Some(current_test_time),
+                &main_chain_hashes,
+                optimal_height,
+                &Bindings::default(),
+            );
         let expected_tags_b = vec![BlockTag::New];
         assert_eq!(actual_tags_b, expected_tags_b, "Tags mismatch for Block B");
 
@@ -691,7 +1203,12 @@ mod tests {
             .iter()
             .find(|b| b.hash == block_c.hash)
             .expect("Block C should be present")
-            .tags(Some(current_test_time), &main_chain_hashes, optimal_height);
+            .tags(
+                Some(current_test_time),
+                &main_chain_hashes,
+                optimal_height,
+                &Bindings::default(),
+            );
         let expected_tags_c = vec![BlockTag::Recommended];
         assert_eq!(actual_tags_c, expected_tags_c, "Tags mismatch for Block C");
     }
@@ -701,7 +1218,7 @@ mod tests {
         let hash = "duplicate_solution_hash";
         let solution = "U D L R F B";
 
-        let exists = Block::hash_and_solution_exists(&pool, &hash, &solution)
+        let exists = Block::hash_and_solution_exists(&pool, hash, solution)
             .await
             .expect("Failed to check if hash and solution exist");
 
@@ -722,7 +1239,7 @@ mod tests {
         .await
         .expect("Failed to create genesis block for duplicate solution test");
 
-        let exists = Block::hash_and_solution_exists(&pool, &hash, &solution)
+        let exists = Block::hash_and_solution_exists(&pool, hash, solution)
             .await
             .expect("Failed to check if hash and solution exist");
 
@@ -742,17 +1259,7 @@ mod tests {
     fn test_scramble_v1_v2() {
         let mut block = Block {
             version: 1,
-            hash: "A0C1E2G3".to_string(),
-            parent_hash: None,
-            height: 0,
-            name: "Test Block".to_string(),
-            message: "Test Message".to_string(),
-            solution: "U D L R F B".to_string(),
-            solution_moves: 6,
-            solution_description: "Test Description".to_string(),
-            created_at: Some(
-                NaiveDateTime::parse_from_str("2024-09-19 18:45:00", "%Y-%m-%d %H:%M:%S").unwrap(),
-            ),
+            ..test_block("A0C1E2G3")
         };
         let scramble = block.scramble();
 
@@ -777,10 +1284,17 @@ mod tests {
             scramble.ends_with("R' U' F"),
             "Scramble should end with R' U' F"
         );
+
+        // Unknown versions have no scramble instead of panicking
+        block.version = 9;
+        assert!(block.scramble_moves().is_none());
+        assert!(block.scramble().is_empty());
+        assert!(block.replay().is_none());
+        assert!(!block.is_valid());
     }
 
     #[sqlx::test]
-    async fn test_new_block_is_v2(pool: SqlitePool) {
+    async fn test_new_block_is_current_version(pool: SqlitePool) {
         let new_block = Block::create_genesis(
             &pool,
             "new_block_hash",
@@ -793,6 +1307,6 @@ mod tests {
         .await
         .expect("Failed to create new block");
 
-        assert_eq!(new_block.version, 2, "New block should be version 2");
+        assert_eq!(new_block.version, 3, "New block should be version 3");
     }
 }
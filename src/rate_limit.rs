@@ -0,0 +1,391 @@
+use actix_web::HttpRequest;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::cache::{Cache, CacheError, MemoryCache};
+use crate::config::Config;
+
+const KEY_PREFIX: &str = "rate_limit";
+
+// What a request spends its tokens on. Each scope has its own buckets, so looking at a
+// scramble doesn't use up the token needed to submit its solution.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scope {
+    Scramble,
+    Submission,
+    Login,
+    Image,
+}
+
+impl Scope {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Scope::Scramble => "scramble",
+            Scope::Submission => "submission",
+            Scope::Login => "login",
+            Scope::Image => "image",
+        }
+    }
+}
+
+// Who a request acts as besides its IP: logged in users by their account, so names taken
+// from them in a form can't spend their tokens, anyone else by the name they use
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Subject<'a> {
+    User(i64),
+    Name(&'a str),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RateLimitConfig {
+    // Requests allowed in a burst, 0 to disable rate limiting
+    pub burst: u32,
+    // Tokens refilled per minute once the burst is used up
+    pub per_minute: f64,
+    // Take the client IP from X-Forwarded-For, when running behind a proxy that sets it
+    pub trust_proxy_headers: bool,
+}
+
+impl RateLimitConfig {
+    pub fn from_config(config: &Config) -> Self {
+        RateLimitConfig {
+            burst: config.rate_limit_burst,
+            per_minute: config.rate_limit_per_minute,
+            trust_proxy_headers: config.trust_proxy_headers,
+        }
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.burst > 0 && self.per_minute > 0.0
+    }
+
+    fn tokens_per_ms(&self) -> f64 {
+        self.per_minute / 60_000.0
+    }
+
+    // Time for an empty bucket to fill up, after which it is the same as no bucket at all
+    fn refill_time(&self) -> Duration {
+        Duration::from_millis((self.burst as f64 / self.tokens_per_ms()).ceil() as u64)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RateLimited {
+    pub retry_after: Duration,
+}
+
+impl RateLimited {
+    // Whole seconds for the Retry-After header, rounded up
+    pub fn retry_after_secs(&self) -> u64 {
+        self.retry_after.as_millis().div_ceil(1000).max(1) as u64
+    }
+}
+
+// A token bucket, stored in the cache as "<tokens>:<updated at in ms>"
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Bucket {
+    tokens: f64,
+    updated_ms: u64,
+}
+
+impl Bucket {
+    fn parse(value: &str) -> Result<Self, CacheError> {
+        let (tokens, updated_ms) = value.split_once(':').ok_or(CacheError::Serialization)?;
+        Ok(Bucket {
+            tokens: tokens.parse().map_err(|_| CacheError::Serialization)?,
+            updated_ms: updated_ms.parse().map_err(|_| CacheError::Serialization)?,
+        })
+    }
+
+    fn serialize(&self) -> String {
+        format!("{}:{}", self.tokens, self.updated_ms)
+    }
+
+    // The bucket as of `now_ms`, with the tokens refilled since its last update
+    fn refilled(self, config: &RateLimitConfig, now_ms: u64) -> Self {
+        let elapsed = now_ms.saturating_sub(self.updated_ms) as f64;
+        Bucket {
+            tokens: (self.tokens + elapsed * config.tokens_per_ms()).min(config.burst as f64),
+            updated_ms: now_ms,
+        }
+    }
+}
+
+// Token bucket rate limiter keeping its buckets in a cache, so they can live in a shared
+// store when the app runs on several instances
+#[derive(Debug, Clone)]
+pub struct RateLimiter<C = MemoryCache<String, String>> {
+    cache: C,
+    config: RateLimitConfig,
+    // Buckets are read, updated and written back, so checks don't interleave
+    lock: Arc<Mutex<()>>,
+}
+
+impl<C> RateLimiter<C>
+where
+    C: Cache<Key = String, Value = String, Error = CacheError>,
+{
+    pub fn new(cache: C, config: RateLimitConfig) -> Self {
+        RateLimiter {
+            cache,
+            config,
+            lock: Arc::new(Mutex::new(())),
+        }
+    }
+
+    // Spend a token of the client's IP and of the subject, if both have one left
+    pub fn check_request(
+        &self,
+        request: &HttpRequest,
+        scope: Scope,
+        subject: Subject<'_>,
+    ) -> Result<(), RateLimited> {
+        let mut keys = Vec::new();
+        if let Some(ip) = self.client_ip(request) {
+            keys.push(format!("ip:{}", ip));
+        }
+        match subject {
+            Subject::User(id) => keys.push(format!("user:{}", id)),
+            Subject::Name("") => {}
+            Subject::Name(name) => keys.push(format!("name:{}", name)),
+        }
+        self.check(scope, &keys)
+    }
+
+    pub fn check(&self, scope: Scope, keys: &[String]) -> Result<(), RateLimited> {
+        let now_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64;
+        self.check_at(scope, keys, now_ms)
+    }
+
+    // A token is only spent if every bucket has one, so a client hitting the limit of its
+    // IP doesn't also drain the bucket of the name it uses. Cache failures let requests
+    // through rather than taking the site down.
+    fn check_at(&self, scope: Scope, keys: &[String], now_ms: u64) -> Result<(), RateLimited> {
+        if !self.config.is_enabled() || keys.is_empty() {
+            return Ok(());
+        }
+        let _guard = self
+            .lock
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+
+        match self.take_tokens(scope, keys, now_ms) {
+            Ok(outcome) => outcome,
+            Err(e) => {
+                tracing::warn!(error = ?e, "rate limiter cache failed");
+                Ok(())
+            }
+        }
+    }
+
+    fn take_tokens(
+        &self,
+        scope: Scope,
+        keys: &[String],
+        now_ms: u64,
+    ) -> Result<Result<(), RateLimited>, CacheError> {
+        let cache_keys = keys
+            .iter()
+            .map(|key| format!("{}:{}:{}", KEY_PREFIX, scope.as_str(), key))
+            .collect::<Vec<_>>();
+        let mut buckets = Vec::with_capacity(cache_keys.len());
+        for key in &cache_keys {
+            let bucket = match self.cache.get(key)? {
+                Some(value) => Bucket::parse(&value)?.refilled(&self.config, now_ms),
+                None => Bucket {
+                    tokens: self.config.burst as f64,
+                    updated_ms: now_ms,
+                },
+            };
+            buckets.push(bucket);
+        }
+
+        let missing = buckets.iter().map(|b| 1.0 - b.tokens).fold(0.0, f64::max);
+        if missing > 0.0 {
+            let retry_after = (missing / self.config.tokens_per_ms()).ceil() as u64;
+            return Ok(Err(RateLimited {
+                retry_after: Duration::from_millis(retry_after),
+            }));
+        }
+
+        let ttl = Some(self.config.refill_time());
+        for (key, bucket) in cache_keys.iter().zip(buckets) {
+            let spent = Bucket {
+                tokens: bucket.tokens - 1.0,
+                ..bucket
+            };
+            self.cache.set(key, spent.serialize(), ttl)?;
+        }
+        Ok(Ok(()))
+    }
+
+    // Behind a proxy, the last X-Forwarded-For entry is the address the proxy saw: earlier
+    // ones come from the client and can't be trusted
+    fn client_ip(&self, request: &HttpRequest) -> Option<String> {
+        let forwarded = self
+            .config
+            .trust_proxy_headers
+            .then(|| request.headers().get("x-forwarded-for"))
+            .flatten()
+            .and_then(|h| h.to_str().ok())
+            .and_then(|h| h.rsplit(',').next())
+            .map(str::trim)
+            .filter(|ip| !ip.is_empty());
+        match forwarded {
+            Some(ip) => Some(ip.to_string()),
+            None => request.peer_addr().map(|addr| addr.ip().to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::TestRequest;
+
+    fn test_limiter(burst: u32, per_minute: f64) -> RateLimiter {
+        RateLimiter::new(
+            MemoryCache::default(),
+            RateLimitConfig {
+                burst,
+                per_minute,
+                trust_proxy_headers: false,
+            },
+        )
+    }
+
+    #[test]
+    fn test_token_bucket() {
+        let limiter = test_limiter(2, 6.0);
+        let keys = vec!["ip:1.2.3.4".to_string()];
+
+        assert!(limiter.check_at(Scope::Submission, &keys, 0).is_ok());
+        assert!(limiter.check_at(Scope::Submission, &keys, 0).is_ok());
+        // Empty bucket: one token comes back every 10 seconds
+        let limited = limiter
+            .check_at(Scope::Submission, &keys, 4_000)
+            .unwrap_err();
+        assert_eq!(limited.retry_after, Duration::from_secs(6));
+        assert_eq!(limited.retry_after_secs(), 6);
+        assert!(limiter.check_at(Scope::Submission, &keys, 10_000).is_ok());
+        assert!(limiter.check_at(Scope::Submission, &keys, 10_000).is_err());
+
+        // Scopes have their own buckets
+        assert!(limiter.check_at(Scope::Scramble, &keys, 10_000).is_ok());
+
+        // Buckets don't fill past the burst
+        assert!(limiter.check_at(Scope::Submission, &keys, 600_000).is_ok());
+        assert!(limiter.check_at(Scope::Submission, &keys, 600_000).is_ok());
+        assert!(limiter.check_at(Scope::Submission, &keys, 600_000).is_err());
+    }
+
+    #[test]
+    fn test_every_key_needs_a_token() {
+        let limiter = test_limiter(1, 1.0);
+        let alice = vec!["ip:1.2.3.4".to_string(), "name:Alice".to_string()];
+        let bob = vec!["ip:1.2.3.4".to_string(), "name:Bob".to_string()];
+        let other_ip = vec!["ip:5.6.7.8".to_string(), "name:Bob".to_string()];
+
+        assert!(limiter.check_at(Scope::Submission, &alice, 0).is_ok());
+        // Same IP under another name
+        assert!(limiter.check_at(Scope::Submission, &bob, 0).is_err());
+        // The rejected request didn't spend Bob's token
+        assert!(limiter.check_at(Scope::Submission, &other_ip, 0).is_ok());
+    }
+
+    #[test]
+    fn test_check_request() {
+        let limiter = test_limiter(1, 1.0);
+        let request = TestRequest::default()
+            .peer_addr("10.0.0.1:4000".parse().unwrap())
+            .insert_header(("x-forwarded-for", "1.2.3.4"))
+            .to_http_request();
+        assert!(
+            limiter
+                .check_request(&request, Scope::Scramble, Subject::Name(""))
+                .is_ok()
+        );
+
+        // Forwarded addresses are ignored unless configured, so they can't be spoofed
+        let request = TestRequest::default()
+            .peer_addr("10.0.0.1:4000".parse().unwrap())
+            .insert_header(("x-forwarded-for", "5.6.7.8"))
+            .to_http_request();
+        assert!(
+            limiter
+                .check_request(&request, Scope::Scramble, Subject::Name(""))
+                .is_err()
+        );
+
+        // Behind a proxy, the address it appended is used
+        let limiter = RateLimiter::new(
+            MemoryCache::default(),
+            RateLimitConfig {
+                burst: 1,
+                per_minute: 1.0,
+                trust_proxy_headers: true,
+            },
+        );
+        let forwarded = |chain: &str| {
+            TestRequest::default()
+                .peer_addr("10.0.0.1:4000".parse().unwrap())
+                .insert_header(("x-forwarded-for", chain))
+                .to_http_request()
+        };
+        assert!(
+            limiter
+                .check_request(&forwarded("1.2.3.4"), Scope::Scramble, Subject::Name(""))
+                .is_ok()
+        );
+        assert!(
+            limiter
+                .check_request(
+                    &forwarded("9.9.9.9, 1.2.3.4"),
+                    Scope::Scramble,
+                    Subject::Name("")
+                )
+                .is_err()
+        );
+        assert!(
+            limiter
+                .check_request(&forwarded("5.6.7.8"), Scope::Scramble, Subject::Name(""))
+                .is_ok()
+        );
+
+        // Logged in users have their own bucket, apart from the name they post under
+        let limiter = test_limiter(1, 1.0);
+        let peer = |ip: &str| {
+            TestRequest::default()
+                .peer_addr(format!("{}:4000", ip).parse().unwrap())
+                .to_http_request()
+        };
+        assert!(
+            limiter
+                .check_request(&peer("1.1.1.1"), Scope::Submission, Subject::Name("Alice"))
+                .is_ok()
+        );
+        assert!(
+            limiter
+                .check_request(&peer("2.2.2.2"), Scope::Submission, Subject::User(1))
+                .is_ok()
+        );
+        assert!(
+            limiter
+                .check_request(&peer("3.3.3.3"), Scope::Submission, Subject::User(1))
+                .is_err()
+        );
+
+        // Disabled limiters let everything through
+        let disabled = test_limiter(0, 1.0);
+        for _ in 0..5 {
+            assert!(
+                disabled
+                    .check_request(&request, Scope::Scramble, Subject::Name("Alice"))
+                    .is_ok()
+            );
+        }
+    }
+}
@@ -1,17 +1,37 @@
 use actix_files::NamedFile;
-use actix_web::{HttpResponse, Responder, get, post, web};
+use actix_web::{HttpResponse, Responder, get, post, rt, web, web::Bytes};
+use chrono::Utc;
 use serde::Deserialize;
 use std::time::Duration;
+use tokio::sync::broadcast::error::RecvError;
+use utoipa::{IntoParams, OpenApi, ToSchema};
 
+use crate::accounts::{self, User, current_user};
+use crate::api::{ApiError, ApiErrorBody};
+use crate::attempts::Attempt;
 use crate::cache::{Cache, MemoryCache};
+use crate::challenges::{self, ChallengeAttempt};
 use crate::config;
+use crate::cube::{CubeState, Move};
+use crate::errors::AppError;
+use crate::events::{ChainEvent, EventBus, format_sse};
+use crate::health::{self, ChainVerifier};
+use crate::identity::{self, Bindings, Identity};
+use crate::leaderboard::{self, Leaderboard};
 use crate::messages::FlashMessage;
-use crate::models::Block;
+use crate::models::{Block, BlockReplay, PendingBlock};
+use crate::rate_limit::{RateLimiter, Scope, Subject};
+use crate::scoring::{self, Season, Standings};
+use crate::stats::{self, UserStats};
 use crate::utils::{
-    calculate_hash, format_data, format_moves, is_htmx_request, parse_moves, scramble_from_hash,
-    verify_solution,
+    base_url, calculate_hash, format_data, format_moves, is_htmx_request, parse_moves_strict,
+    scramble_from_hash, start_of_week, verify_solution,
 };
-use crate::views;
+use crate::validation::{self, BlockText, FieldLimits};
+use crate::{commit_reveal, csrf, metrics, security, tree, views, webhooks};
+
+const SSE_KEEP_ALIVE: Duration = Duration::from_secs(15);
+const FEED_SIZE: u32 = 50;
 
 #[get("/favicon.ico")]
 async fn favicon() -> impl Responder {
@@ -20,34 +40,29 @@ async fn favicon() -> impl Responder {
 
 #[get("/")]
 async fn get_index(
+    request: actix_web::HttpRequest,
     db: web::Data<sqlx::SqlitePool>,
     conf: web::Data<config::Config>,
     cache: web::Data<MemoryCache<String, String>>,
-) -> impl Responder {
-    let cache_key = "index_page".to_string();
-
-    if let Some(cached_page) = cache
-        .get(&cache_key)
-        .expect("Failed to get index from cache")
-    {
-        return HttpResponse::Ok().body(cached_page);
-    }
-
-    let cloudflare_code = conf.cloudflare_code.clone();
-    let recommended_block_count = Block::get_recommended_count(&db)
-        .await
-        .expect("Failed to get recommended block count");
-    let response = views::get_index(cloudflare_code, recommended_block_count);
-
-    cache
-        .set(
-            &cache_key,
-            response.clone(),
-            Some(Duration::from_secs(60 * 60)),
-        )
-        .expect("Failed to cache index page");
+) -> Result<HttpResponse, AppError> {
+    // The page holds the CSRF token and flash message of the visitor, so only the count is
+    // cached
+    let cache_key = "index_recommended_block_count".to_string();
+    let recommended_block_count = match cache.get(&cache_key)?.and_then(|c| c.parse().ok()) {
+        Some(count) => count,
+        None => {
+            let count = Block::get_recommended_count(&db).await?;
+            cache.set(
+                &cache_key,
+                count.to_string(),
+                Some(Duration::from_secs(60 * 60)),
+            )?;
+            count
+        }
+    };
 
-    HttpResponse::Ok().body(response)
+    let page = page_context(&request, &conf);
+    Ok(HttpResponse::Ok().body(views::get_index(page, recommended_block_count)?))
 }
 
 #[get("/health")]
@@ -55,36 +70,69 @@ async fn get_health() -> impl Responder {
     HttpResponse::Ok().body("OK")
 }
 
+// The process is up and serving requests, without checking any dependency
+#[get("/health/live")]
+async fn get_health_live() -> impl Responder {
+    HttpResponse::Ok().json(serde_json::json!({ "status": health::Status::Ok }))
+}
+
+// Ready to serve traffic: the database, migrations and cache are fine, invalid blocks only
+// degrade the status
+#[get("/health/ready")]
+async fn get_health_ready(
+    db: web::Data<sqlx::SqlitePool>,
+    verifier: web::Data<ChainVerifier>,
+    cache: web::Data<MemoryCache<String, String>>,
+) -> impl Responder {
+    let readiness = health::readiness(&db, &verifier, &cache).await;
+    if readiness.is_ready() {
+        HttpResponse::Ok().json(readiness)
+    } else {
+        HttpResponse::ServiceUnavailable().json(readiness)
+    }
+}
+
+// Served on the internal metrics port only, see `Config::metrics_port`
+#[get("/metrics")]
+async fn get_metrics() -> impl Responder {
+    HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4; charset=utf-8")
+        .body(metrics::render())
+}
+
 #[get("/parent")]
 async fn get_parent(
     request: actix_web::HttpRequest,
     conf: web::Data<config::Config>,
     db: web::Data<sqlx::SqlitePool>,
-) -> impl Responder {
-    let blocks = Block::find_all(&db, false, None, None)
-        .await
-        .expect("Failed to fetch blocks");
+) -> Result<HttpResponse, AppError> {
+    let blocks = Block::find_all(&db, false, None, None).await?;
     let optimal_height = blocks
         .iter()
         .find(|b| b.can_create_child(None))
-        .expect("There should be at least one block that can create a child")
+        .ok_or_else(|| AppError::not_found("No block can be used as a parent yet."))?
         .height;
 
+    let bindings = Bindings::for_names(&db, blocks.iter().map(|b| b.name.as_str())).await?;
+
     if is_htmx_request(&request) {
-        return HttpResponse::Ok().body(views::get_partial_parent(blocks, optimal_height));
+        return Ok(HttpResponse::Ok().body(views::get_partial_parent(
+            blocks,
+            optimal_height,
+            bindings,
+        )?));
     }
 
-    let cloudflare_code = conf.cloudflare_code.clone();
-    let recommended_block_count = Block::get_recommended_count(&db)
-        .await
-        .expect("Failed to get recommended block count");
+    let page = page_context(&request, &conf);
+    let recommended_block_count = Block::get_recommended_count(&db).await?;
 
-    HttpResponse::Ok().body(views::get_parent(
-        cloudflare_code,
+    Ok(HttpResponse::Ok().body(views::get_parent(
+        page,
         recommended_block_count,
         blocks,
         optimal_height,
-    ))
+        bindings,
+    )?))
 }
 
 #[derive(Deserialize)]
@@ -92,6 +140,10 @@ struct InitialBlockInfo {
     parent_hash: String,
     name: Option<String>,
     message: Option<String>,
+    // Checkbox to start a timed attempt when the scramble is revealed, logged in users only
+    timed: Option<String>,
+    // Set to 3 to get the scrambles of a mean of 3 challenge
+    mean_of: Option<u8>,
 }
 
 #[get("/block")]
@@ -100,31 +152,38 @@ async fn get_block(
     conf: web::Data<config::Config>,
     db: web::Data<sqlx::SqlitePool>,
     block_info: web::Query<InitialBlockInfo>,
-) -> impl Responder {
-    match Block::find_by_hash(&db, &block_info.parent_hash).await {
-        Ok(block) => {
-            if !block.can_create_child(None) {
-                return HttpResponse::BadRequest()
-                    .body("This block cannot be used as a parent for a new block.");
-            }
-        }
-        Err(_) => {
-            return HttpResponse::NotFound().body("Parent block not found");
-        }
-    };
+) -> Result<HttpResponse, AppError> {
+    find_eligible_parent(&db, &block_info.parent_hash).await?;
+    let user = current_user(&request, &db).await;
+    let username = user.as_ref().map(|u| u.username.as_str());
     if is_htmx_request(&request) {
-        return HttpResponse::Ok().body(views::get_partial_block(&block_info.parent_hash));
+        let html = views::get_partial_block(&block_info.parent_hash, username)?;
+        return Ok(HttpResponse::Ok().body(html));
     }
 
-    let cloudflare_code = conf.cloudflare_code.clone();
-    let recommended_block_count = Block::get_recommended_count(&db)
-        .await
-        .expect("Failed to get recommended block count");
-    HttpResponse::Ok().body(views::get_block(
-        cloudflare_code,
+    let page = page_context(&request, &conf);
+    let recommended_block_count = Block::get_recommended_count(&db).await?;
+    Ok(HttpResponse::Ok().body(views::get_block(
+        page,
         &block_info.parent_hash,
         recommended_block_count,
-    ))
+        username,
+    )?))
+}
+
+// The parent of a new block must exist and be from a previous round
+async fn find_eligible_parent(db: &sqlx::SqlitePool, hash: &str) -> Result<Block, AppError> {
+    let block = match Block::find_by_hash(db, hash).await {
+        Ok(block) => block,
+        Err(sqlx::Error::RowNotFound) => {
+            return Err(AppError::NotFound(ApiError::ParentNotFound.message()));
+        }
+        Err(e) => return Err(e.into()),
+    };
+    if !block.can_create_child(None) {
+        return Err(AppError::BadRequest(ApiError::ParentNotEligible.message()));
+    }
+    Ok(block)
 }
 
 #[get("/solution")]
@@ -132,183 +191,3356 @@ async fn get_solution(
     conf: web::Data<config::Config>,
     request: actix_web::HttpRequest,
     db: web::Data<sqlx::SqlitePool>,
+    limiter: web::Data<RateLimiter>,
     block_info: web::Query<InitialBlockInfo>,
-) -> impl Responder {
+) -> Result<HttpResponse, AppError> {
     if block_info.parent_hash.is_empty() {
-        return HttpResponse::BadRequest().body("Parent hash is required.");
+        return Err(AppError::bad_request("Parent hash is required."));
     }
 
-    if block_info.name.clone().unwrap_or_default().is_empty()
-        || block_info.message.clone().unwrap_or_default().is_empty()
-    {
+    // Logged in users always post under the name of their account
+    let user = current_user(&request, &db).await;
+    let name = match &user {
+        Some(user) => user.username.clone(),
+        None => block_info.name.clone().unwrap_or_default(),
+    };
+
+    if name.is_empty() || block_info.message.clone().unwrap_or_default().is_empty() {
         // Only render block if fields are missing
-        return HttpResponse::Ok().body("<div id=\"solution-form\" hidden></div>");
+        return Ok(HttpResponse::Ok().body("<div id=\"solution-form\" hidden></div>"));
     }
 
-    match Block::find_by_hash(&db, &block_info.parent_hash).await {
-        Ok(block) => {
-            if !block.can_create_child(None) {
-                return HttpResponse::BadRequest()
-                    .body("This block cannot be used as a parent for a new block.");
-            }
-        }
-        Err(_) => {
-            return HttpResponse::NotFound().body("Parent block not found");
+    // The scramble is derived from the text as it will be hashed once submitted
+    let limits = FieldLimits::from_config(&conf);
+    let message = block_info.message.clone().unwrap_or_default();
+    let checked = validation::check_field("name", &name, limits.name, false).and_then(|name| {
+        validation::check_field("message", &message, limits.message, true)
+            .map(|message| (name, message))
+    });
+    let (name, message) = match checked {
+        Ok(checked) => checked,
+        Err(e) => {
+            let resp = HttpResponse::BadRequest().body(e.message());
+            return Ok(FlashMessage::error(&e.message()).set(resp));
         }
     };
-    let name = block_info.name.clone().unwrap_or_default();
-    let message = block_info.message.clone().unwrap_or_default();
+
+    // Checked before any token is spent, so asking for scrambles under someone else's name
+    // doesn't use up their tokens
+    if user.is_none() && User::find_by_username(&db, &name).await?.is_some() {
+        let resp = HttpResponse::Forbidden().body(ApiError::NameReserved.message());
+        return Ok(FlashMessage::error(
+            "This name belongs to an account. Please log in to use it.",
+        )
+        .set(resp));
+    }
+
+    // Every scramble shown is a block that can be submitted, so they are rate limited too
+    let subject = match &user {
+        Some(user) => Subject::User(user.id),
+        None => Subject::Name(&name),
+    };
+    if let Err(limited) = limiter.check_request(&request, Scope::Scramble, subject) {
+        let error = AppError::from(limited);
+        return Ok(FlashMessage::error(&error.message()).set(error.html_response()));
+    }
+
+    find_eligible_parent(&db, &block_info.parent_hash).await?;
     let data = format_data(&block_info.parent_hash, &name, &message);
     let hash = calculate_hash(&data);
     let raw_scramble = scramble_from_hash(&hash);
     let scramble = format_moves(&raw_scramble);
 
+    // Every reveal to a logged in user is recorded, and the clock of a timed attempt starts
+    // the first time the scramble is shown. A scramble already seen can't be timed anymore.
+    let now = Utc::now().naive_utc();
+    let attempt = match &user {
+        Some(user) => {
+            Some(Attempt::start(&db, &hash, user.id, now, block_info.timed.is_some()).await?)
+        }
+        None => None,
+    };
+    if block_info.timed.is_some() && attempt.as_ref().is_some_and(|a| !a.timed) {
+        let error = AppError::bad_request(
+            "This scramble was already shown without the timer, so it can't be a timed attempt.",
+        );
+        return Ok(FlashMessage::error(&error.message()).set(error.html_response()));
+    }
+    let remaining_seconds = attempt
+        .filter(|a| a.timed && a.submitted_at.is_none())
+        .map(|a| a.remaining_seconds(now));
+    let challenge_scrambles = match block_info.mean_of {
+        Some(challenges::MEAN_OF) => (1..=challenges::MEAN_OF)
+            .map(|i| {
+                let attempt_hash =
+                    challenges::attempt_hash(&block_info.parent_hash, &name, &message, i);
+                let scramble = format_moves(&scramble_from_hash(&attempt_hash));
+                (i, attempt_hash, scramble)
+            })
+            .collect(),
+        _ => Vec::new(),
+    };
+
     if is_htmx_request(&request) {
-        return HttpResponse::Ok().body(views::get_partial_solution(
+        return Ok(HttpResponse::Ok().body(views::get_partial_solution(
             &block_info.parent_hash,
             &name,
             &message,
             &scramble,
             &hash,
-        ));
+            remaining_seconds,
+            &challenge_scrambles,
+        )?));
     }
 
-    let cloudflare_code = conf.cloudflare_code.clone();
-    let recommended_block_count = Block::get_recommended_count(&db)
-        .await
-        .expect("Failed to get recommended block count");
-    HttpResponse::Ok().body(views::get_solution(
-        cloudflare_code,
+    let page = page_context(&request, &conf);
+    let recommended_block_count = Block::get_recommended_count(&db).await?;
+    Ok(HttpResponse::Ok().body(views::get_solution(
+        page,
         &block_info.parent_hash,
         &name,
         &message,
         &scramble,
         &hash,
         recommended_block_count,
+        user.as_ref().map(|u| u.username.as_str()),
+        remaining_seconds,
+        &challenge_scrambles,
+    )?))
+}
+
+#[derive(Deserialize)]
+struct Credentials {
+    username: String,
+    password: String,
+}
+
+#[get("/account")]
+async fn get_account(
+    request: actix_web::HttpRequest,
+    db: web::Data<sqlx::SqlitePool>,
+) -> Result<HttpResponse, AppError> {
+    let user = current_user(&request, &db).await;
+    Ok(HttpResponse::Ok().body(views::get_partial_account_nav(
+        user.as_ref().map(|u| u.username.as_str()),
+    )?))
+}
+
+#[get("/login")]
+async fn get_login(
+    request: actix_web::HttpRequest,
+    conf: web::Data<config::Config>,
+    db: web::Data<sqlx::SqlitePool>,
+) -> Result<HttpResponse, AppError> {
+    if is_htmx_request(&request) {
+        return Ok(HttpResponse::Ok().body(views::get_partial_login()?));
+    }
+
+    let page = page_context(&request, &conf);
+    let recommended_block_count = Block::get_recommended_count(&db).await?;
+    Ok(HttpResponse::Ok().body(views::get_login(page, recommended_block_count)?))
+}
+
+#[get("/register")]
+async fn get_register(
+    request: actix_web::HttpRequest,
+    conf: web::Data<config::Config>,
+    db: web::Data<sqlx::SqlitePool>,
+) -> Result<HttpResponse, AppError> {
+    if is_htmx_request(&request) {
+        return Ok(HttpResponse::Ok().body(views::get_partial_register()?));
+    }
+
+    let page = page_context(&request, &conf);
+    let recommended_block_count = Block::get_recommended_count(&db).await?;
+    Ok(HttpResponse::Ok().body(views::get_register(page, recommended_block_count)?))
+}
+
+// Per-request parts of a full page: the CSRF token of its forms, the nonce of its scripts and
+// the flash message left by the previous response
+fn page_context(request: &actix_web::HttpRequest, conf: &config::Config) -> views::Page {
+    views::Page {
+        cloudflare_code: conf.cloudflare_code.clone(),
+        csrf_token: csrf::token(request),
+        csp_nonce: security::nonce(request),
+        flash: FlashMessage::take(request),
+    }
+}
+
+// Start a session for the user and send them back to the overview
+fn logged_in_response(token: &str, message: &str) -> HttpResponse {
+    let response = HttpResponse::TemporaryRedirect()
+        .append_header(("HX-Redirect", "/"))
+        .cookie(accounts::session_cookie(token))
+        .finish();
+    FlashMessage::info(message).set(response)
+}
+
+#[post("/login")]
+async fn post_login(
+    request: actix_web::HttpRequest,
+    db: web::Data<sqlx::SqlitePool>,
+    limiter: web::Data<RateLimiter>,
+    credentials: web::Form<Credentials>,
+) -> Result<HttpResponse, AppError> {
+    // Attempts are limited per IP and per username, so passwords can't be guessed quickly
+    let subject = Subject::Name(&credentials.username);
+    if let Err(limited) = limiter.check_request(&request, Scope::Login, subject) {
+        let error = AppError::from(limited);
+        return Ok(FlashMessage::error(&error.message()).set(error.html_response()));
+    }
+
+    let user = User::authenticate(&db, &credentials.username, &credentials.password).await?;
+    let Some(user) = user else {
+        let resp = HttpResponse::Unauthorized().body("Invalid username or password");
+        return Ok(FlashMessage::error("Invalid username or password.").set(resp));
+    };
+
+    let token = user.create_session(&db).await?;
+    Ok(logged_in_response(
+        &token,
+        &format!("Welcome back, {}!", user.username),
     ))
 }
 
-#[derive(Debug, Deserialize)]
+#[post("/register")]
+async fn post_register(
+    conf: web::Data<config::Config>,
+    db: web::Data<sqlx::SqlitePool>,
+    credentials: web::Form<Credentials>,
+) -> Result<HttpResponse, AppError> {
+    let limits = FieldLimits::from_config(&conf);
+    let username = match accounts::validate_registration(
+        &credentials.username,
+        &credentials.password,
+        limits.name,
+    ) {
+        Ok(username) => username,
+        Err(error) => {
+            let resp = HttpResponse::BadRequest().body(error.clone());
+            return Ok(FlashMessage::error(&error).set(resp));
+        }
+    };
+
+    // Names bound to a key can't be claimed by an account, nor names with blocks that anyone
+    // could have posted
+    if Identity::find_by_name(&db, &username).await?.is_some()
+        || Block::name_has_blocks(&db, &username).await?
+    {
+        let resp = HttpResponse::Conflict().body(ApiError::NameTaken.message());
+        return Ok(FlashMessage::error(&ApiError::NameTaken.message()).set(resp));
+    }
+
+    let user = match User::create(&db, &username, &credentials.password).await {
+        Ok(user) => user,
+        Err(sqlx::Error::Database(e)) if e.is_unique_violation() => {
+            let resp = HttpResponse::Conflict().body(ApiError::NameTaken.message());
+            return Ok(FlashMessage::error(&ApiError::NameTaken.message()).set(resp));
+        }
+        Err(e) => return Err(e.into()),
+    };
+
+    let token = user.create_session(&db).await?;
+    Ok(logged_in_response(
+        &token,
+        "Your account has been created. Welcome!",
+    ))
+}
+
+#[post("/logout")]
+async fn post_logout(
+    request: actix_web::HttpRequest,
+    db: web::Data<sqlx::SqlitePool>,
+) -> Result<HttpResponse, AppError> {
+    if let Some(token) = request.cookie(accounts::SESSION_COOKIE_NAME) {
+        accounts::delete_session(&db, token.value()).await?;
+    }
+
+    let response = HttpResponse::TemporaryRedirect()
+        .append_header(("HX-Redirect", "/"))
+        .cookie(accounts::removal_cookie())
+        .finish();
+    Ok(FlashMessage::info("You have been logged out.").set(response))
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
 struct CompleteBlockInfo {
     parent_hash: String,
     name: String,
     message: String,
     solution: String,
     solution_description: String,
+    // Hex encoded ed25519 signature over "<hash>|<solution>", required for names bound to a key
+    #[serde(default)]
+    signature: Option<String>,
+    // Set to 3 for a mean of 3 challenge, with the solutions of the attempts separated by "/"
+    #[serde(default)]
+    mean_of: Option<u8>,
 }
 
-#[post("/solution")]
-async fn post_solution(
-    db: web::Data<sqlx::SqlitePool>,
-    block_info: web::Form<CompleteBlockInfo>,
-) -> impl Responder {
-    if block_info.parent_hash.is_empty()
-        || block_info.name.is_empty()
-        || block_info.message.is_empty()
-        || block_info.solution.is_empty()
-        || block_info.solution_description.is_empty()
+impl CompleteBlockInfo {
+    fn is_signed(&self) -> bool {
+        self.signature.as_deref().is_some_and(|s| !s.is_empty())
+    }
+}
+
+// Validate a submitted solution and create the block it describes. Revealed solutions pass
+// the pending block they were committed to, and are checked as of the time of the commitment.
+async fn submit_block(
+    db: &sqlx::SqlitePool,
+    events: &EventBus,
+    limits: &FieldLimits,
+    block_info: &CompleteBlockInfo,
+    user: Option<&User>,
+    pending: Option<&PendingBlock>,
+) -> Result<Block, ApiError> {
+    let result = create_submitted_block(db, events, limits, block_info, user, pending).await;
+    metrics::record_submission(&result);
+    match &result {
+        Ok(block) => tracing::info!(
+            hash = %block.hash,
+            height = block.height,
+            name = %block.name,
+            solution_moves = block.solution_moves,
+            "block accepted"
+        ),
+        Err(e) => tracing::info!(
+            parent_hash = %block_info.parent_hash,
+            name = %block_info.name,
+            reason = metrics::submission_outcome(&result),
+            error = %e,
+            "block rejected"
+        ),
+    }
+    result
+}
+
+// Spend a submission token of the client and of the account or name, counting rejected
+// submissions. Names reserved by an account or a key are refused first when the submission
+// can't use them, so nobody can drain the bucket of a name that isn't theirs.
+async fn limit_submission(
+    db: &sqlx::SqlitePool,
+    limiter: &RateLimiter,
+    request: &actix_web::HttpRequest,
+    parent_hash: &str,
+    name: &str,
+    signed: bool,
+    user: Option<&User>,
+) -> Result<(), ApiError> {
+    let name = validation::normalize(name);
+    let result = match user {
+        Some(user) => Ok(Subject::User(user.id)),
+        None => check_name_reservation(db, &name, signed)
+            .await
+            .map(|()| Subject::Name(&name)),
+    }
+    .and_then(|subject| {
+        limiter
+            .check_request(request, Scope::Submission, subject)
+            .map_err(|limited| ApiError::RateLimited {
+                retry_after: limited.retry_after_secs(),
+            })
+    });
+    if let Err(e) = &result {
+        let rejected = Err(e.clone());
+        metrics::record_submission(&rejected);
+        tracing::info!(
+            parent_hash = %parent_hash,
+            name = %name,
+            reason = metrics::submission_outcome(&rejected),
+            error = %e,
+            "block rejected"
+        );
+    }
+    result
+}
+
+// Anonymous submissions can't use the name of an account, nor a name bound to a key unless
+// they are signed
+async fn check_name_reservation(
+    db: &sqlx::SqlitePool,
+    name: &str,
+    signed: bool,
+) -> Result<(), ApiError> {
+    let account = User::find_by_username(db, name)
+        .await
+        .map_err(|_| ApiError::Internal)?;
+    let identity = Identity::find_by_name(db, name)
+        .await
+        .map_err(|_| ApiError::Internal)?;
+    if account.is_some() || (identity.is_some() && !signed) {
+        return Err(ApiError::NameReserved);
+    }
+    Ok(())
+}
+
+async fn create_submitted_block(
+    db: &sqlx::SqlitePool,
+    events: &EventBus,
+    limits: &FieldLimits,
+    block_info: &CompleteBlockInfo,
+    user: Option<&User>,
+    pending: Option<&PendingBlock>,
+) -> Result<Block, ApiError> {
+    let missing_fields = [
+        ("parent_hash", &block_info.parent_hash),
+        ("name", &block_info.name),
+        ("message", &block_info.message),
+        ("solution", &block_info.solution),
+        ("solution_description", &block_info.solution_description),
+    ]
+    .iter()
+    .filter(|(_, value)| value.is_empty())
+    .map(|(field, _)| field.to_string())
+    .collect::<Vec<_>>();
+    if !missing_fields.is_empty() {
+        return Err(ApiError::MissingFields {
+            fields: missing_fields,
+        });
+    }
+    // Blocks are hashed and stored with their normalised text
+    let text = BlockText::parse(
+        &block_info.name,
+        &block_info.message,
+        &block_info.solution_description,
+        limits,
+    )?;
+
+    let created_at = pending.and_then(|p| p.created_at);
+    if pending.is_none()
+        && commit_reveal::is_enabled(db, Utc::now().date_naive())
+            .await
+            .map_err(|_| ApiError::Internal)?
     {
-        return HttpResponse::BadRequest().body("All fields are required.");
+        return Err(ApiError::CommitmentRequired);
     }
 
-    let parent_block = match Block::find_by_hash(&db, &block_info.parent_hash).await {
+    let parent_block = match Block::find_by_hash(db, &block_info.parent_hash).await {
         Ok(block) => {
-            if !block.can_create_child(None) {
-                return HttpResponse::BadRequest()
-                    .body("This block cannot be used as a parent for a new block.");
+            if !block.can_create_child(created_at) {
+                return Err(ApiError::ParentNotEligible);
             }
             block
         }
-        Err(_) => {
-            return HttpResponse::NotFound().body("Parent block not found");
-        }
+        Err(sqlx::Error::RowNotFound) => return Err(ApiError::ParentNotFound),
+        Err(_) => return Err(ApiError::Internal),
     };
 
-    let data = format_data(&parent_block.hash, &block_info.name, &block_info.message);
+    let data = format_data(&parent_block.hash, &text.name, &text.message);
     let hash = calculate_hash(&data);
-    let raw_scramble = scramble_from_hash(&hash);
-    let parsed_solution = parse_moves(&block_info.solution);
 
-    if !verify_solution(&raw_scramble, &parsed_solution) {
-        let resp = HttpResponse::BadRequest().body("Incorrect solution");
-        return FlashMessage::error(
-            "I'm sorry, but your solution doesn't seem to be correct. Please double-check it!",
-        )
-        .set(resp);
-    }
+    let (solution, solution_moves, attempts) = match block_info.mean_of {
+        None => {
+            let parsed_solution = parse_solution(&block_info.solution)?;
+            if !verify_solution(&scramble_from_hash(&hash), &parsed_solution) {
+                return Err(ApiError::WrongSolution);
+            }
+            (
+                format_moves(&parsed_solution),
+                parsed_solution.len() as u8,
+                Vec::new(),
+            )
+        }
+        // Every attempt has its own scramble, derived from the block data and its index
+        Some(challenges::MEAN_OF) => {
+            let solutions = challenges::split_solutions(&block_info.solution);
+            if solutions.len() != challenges::MEAN_OF as usize {
+                return Err(ApiError::InvalidRequest {
+                    detail: format!(
+                        "A mean of {0} challenge needs {0} solutions separated by \"/\".",
+                        challenges::MEAN_OF
+                    ),
+                });
+            }
+
+            let mut attempts = Vec::new();
+            for (attempt_index, solution) in (1..=challenges::MEAN_OF).zip(solutions) {
+                let parsed_solution = parse_solution(solution)?;
+                let attempt_hash = challenges::attempt_hash(
+                    &parent_block.hash,
+                    &text.name,
+                    &text.message,
+                    attempt_index,
+                );
+                if !verify_solution(&scramble_from_hash(&attempt_hash), &parsed_solution) {
+                    return Err(ApiError::WrongSolution);
+                }
+                attempts.push(ChallengeAttempt::new(
+                    attempt_index,
+                    attempt_hash,
+                    &parsed_solution,
+                ));
+            }
+            let moves = attempts
+                .iter()
+                .map(|a| a.solution_moves)
+                .collect::<Vec<_>>();
+            (
+                challenges::join_solutions(&attempts),
+                challenges::rounded_mean(&moves),
+                attempts,
+            )
+        }
+        Some(_) => {
+            return Err(ApiError::InvalidRequest {
+                detail: format!(
+                    "Only mean of {} challenges are supported.",
+                    challenges::MEAN_OF
+                ),
+            });
+        }
+    };
 
-    if Block::hash_and_solution_exists(&db, &hash, &format_moves(&parsed_solution))
+    if Block::hash_and_solution_exists(db, &hash, &solution)
         .await
-        .expect("Failed to check for existing block")
+        .map_err(|_| ApiError::Internal)?
     {
-        let resp = HttpResponse::BadRequest().body("This solution already exists");
-        return FlashMessage::error("This solution already exists").set(resp);
+        return Err(ApiError::DuplicateSolution);
+    }
+
+    if pending.is_none() {
+        if PendingBlock::find_by_hash(db, &hash)
+            .await
+            .map_err(|_| ApiError::Internal)?
+            .is_some()
+        {
+            return Err(ApiError::DuplicateBlock);
+        }
+        check_account_name(db, &text.name, user).await?;
     }
 
-    if parent_block
+    // Names bound to a key can only be used by blocks signed with that key
+    let identity = Identity::find_by_name(db, &text.name)
+        .await
+        .map_err(|_| ApiError::Internal)?;
+    let signature = block_info.signature.as_deref().filter(|s| !s.is_empty());
+    let signed_by = match (&identity, signature) {
+        (None, None) => None,
+        (Some(_), None) => return Err(ApiError::NameReserved),
+        (None, Some(_)) => return Err(ApiError::IdentityNotFound),
+        (Some(identity), Some(signature)) => {
+            let data = identity::block_data(&hash, &solution);
+            if !identity::verify_signature(&identity.public_key, &data, signature) {
+                return Err(ApiError::InvalidSignature);
+            }
+            Some((identity.public_key.as_str(), signature))
+        }
+    };
+
+    // Everything the block changes is stored in one transaction, so no tip or event is lost
+    let mut tx = db.begin().await.map_err(|_| ApiError::Internal)?;
+    let block = parent_block
         .create_child(
-            &db,
+            &mut *tx,
             &hash,
-            &block_info.name,
-            &block_info.message,
-            &format_moves(&parsed_solution),
-            parsed_solution.len() as u8,
-            &block_info.solution_description,
+            &text.name,
+            &text.message,
+            &solution,
+            solution_moves,
+            &text.solution_description,
+            signed_by,
+            pending.map_or(user.map(|u| u.id), |p| p.user_id),
+            created_at,
+            &attempts,
         )
         .await
-        .is_err()
+        .map_err(|e| match e {
+            sqlx::Error::Database(e) if e.is_unique_violation() => ApiError::DuplicateBlock,
+            _ => ApiError::Internal,
+        })?;
+    let previous_tip = Block::find_tip_excluding(&mut *tx, Some(&block.hash))
+        .await
+        .map_err(|_| ApiError::Internal)?;
+    let tip = Block::find_tip(&mut *tx)
+        .await
+        .map_err(|_| ApiError::Internal)?;
+
+    leaderboard::record_block(
+        &mut *tx,
+        &block.hash,
+        previous_tip.as_ref().map(|b| b.hash.as_str()),
+        tip.as_ref().map(|b| b.hash.as_str()),
+    )
+    .await
+    .map_err(|_| ApiError::Internal)?;
+
+    let mut chain_events = vec![ChainEvent::block_created(&block)];
+    if let Some(tip) = tip
+        && previous_tip.as_ref().map(|b| &b.hash) != Some(&tip.hash)
+    {
+        // The new tip doesn't extend the previous one, so the main chain switched branches
+        let previous_hash = previous_tip.as_ref().map(|b| b.hash.as_str());
+        if tip.parent_hash.as_deref() != previous_hash {
+            tracing::info!(
+                hash = %tip.hash,
+                previous_hash = previous_hash.unwrap_or_default(),
+                height = tip.height,
+                "reorg"
+            );
+        }
+        chain_events.push(ChainEvent::TipChanged {
+            hash: tip.hash,
+            previous_hash: previous_tip.map(|b| b.hash),
+            height: tip.height,
+        });
+    }
+    for event in &chain_events {
+        webhooks::enqueue(&mut *tx, event)
+            .await
+            .map_err(|_| ApiError::Internal)?;
+    }
+    if let Some(pending) = pending {
+        pending
+            .delete(&mut *tx)
+            .await
+            .map_err(|_| ApiError::Internal)?;
+    }
+    tx.commit().await.map_err(|_| ApiError::Internal)?;
+
+    for event in chain_events {
+        events.publish(event);
+    }
+
+    Ok(block)
+}
+
+fn parse_solution(solution: &str) -> Result<Vec<Move>, ApiError> {
+    parse_moves_strict(solution).map_err(|e| ApiError::InvalidNotation {
+        position: e.position,
+        found: e.found,
+    })
+}
+
+// Account names can only be used by their owner, who can't use any other name
+async fn check_account_name(
+    db: &sqlx::SqlitePool,
+    name: &str,
+    user: Option<&User>,
+) -> Result<(), ApiError> {
+    let account = User::find_by_username(db, name)
+        .await
+        .map_err(|_| ApiError::Internal)?;
+    if let Some(user) = user
+        && user.username != name
     {
-        return HttpResponse::InternalServerError()
-            .body("Failed to create block. Please try again later.");
+        return Err(ApiError::InvalidRequest {
+            detail: "Logged in users can only post blocks under their own name.".to_string(),
+        });
+    }
+    if account.is_some() && user.is_none() {
+        return Err(ApiError::NameReserved);
+    }
+    Ok(())
+}
+
+#[post("/solution")]
+async fn post_solution(
+    conf: web::Data<config::Config>,
+    request: actix_web::HttpRequest,
+    db: web::Data<sqlx::SqlitePool>,
+    events: web::Data<EventBus>,
+    limiter: web::Data<RateLimiter>,
+    block_info: web::Form<CompleteBlockInfo>,
+) -> Result<HttpResponse, AppError> {
+    let user = current_user(&request, &db).await;
+    let limited = limit_submission(
+        &db,
+        &limiter,
+        &request,
+        &block_info.parent_hash,
+        &block_info.name,
+        block_info.is_signed(),
+        user.as_ref(),
+    );
+    let result = match limited.await {
+        Ok(()) => {
+            let limits = FieldLimits::from_config(&conf);
+            submit_block(&db, &events, &limits, &block_info, user.as_ref(), None).await
+        }
+        Err(e) => Err(e),
+    };
+    let response = match result {
+        Ok(block) => {
+            let attempt = match &user {
+                Some(user) => Attempt::finish(&db, &block.hash, user.id, Utc::now().naive_utc())
+                    .await?
+                    .filter(|a| a.timed),
+                None => None,
+            };
+            let message = match attempt {
+                Some(a) if a.official => format!(
+                    "Block created successfully in {}, an official solve. Thank you!",
+                    a.elapsed_display()
+                ),
+                Some(a) => format!(
+                    "Block created successfully in {}, over the time limit for an official solve. Thank you!",
+                    a.elapsed_display()
+                ),
+                None => "Block created successfully. Thank you!".to_string(),
+            };
+            let response = HttpResponse::TemporaryRedirect()
+                .append_header(("HX-Redirect", "/?all=true"))
+                .finish();
+            FlashMessage::info(&message).set(response)
+        }
+        Err(ApiError::WrongSolution) => {
+            let resp = HttpResponse::BadRequest().body("Incorrect solution");
+            FlashMessage::error(
+                "I'm sorry, but your solution doesn't seem to be correct. Please double-check it!",
+            )
+            .set(resp)
+        }
+        Err(
+            e @ (ApiError::DuplicateSolution
+            | ApiError::InvalidNotation { .. }
+            | ApiError::NameReserved
+            | ApiError::InvalidSignature
+            | ApiError::IdentityNotFound
+            | ApiError::DuplicateBlock
+            | ApiError::CommitmentRequired
+            | ApiError::InvalidRequest { .. }
+            | ApiError::InvalidField { .. }),
+        ) => {
+            let resp = HttpResponse::BadRequest().body(e.message());
+            FlashMessage::error(&e.message()).set(resp)
+        }
+        Err(e @ ApiError::RateLimited { retry_after }) => {
+            let resp = HttpResponse::TooManyRequests()
+                .insert_header((actix_web::http::header::RETRY_AFTER, retry_after))
+                .body(e.message());
+            FlashMessage::error(&e.message()).set(resp)
+        }
+        Err(ApiError::ParentNotFound) => {
+            return Err(AppError::NotFound(ApiError::ParentNotFound.message()));
+        }
+        Err(ApiError::Internal) => {
+            HttpResponse::InternalServerError().body(ApiError::Internal.message())
+        }
+        Err(e) => return Err(AppError::BadRequest(e.message())),
     };
+    Ok(response)
+}
 
-    let response = HttpResponse::TemporaryRedirect()
-        .append_header(("HX-Redirect", "/?all=true"))
-        .finish();
-    FlashMessage::info("Block created successfully. Thank you!").set(response)
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "Fewest Moves Chain API", version = "1"),
+    paths(
+        post_api_block,
+        post_api_commitment,
+        get_api_commitments,
+        post_api_reveal,
+        get_api_block_replay,
+        post_api_identity,
+        get_api_identity,
+        get_api_leaderboard,
+        get_api_seasons,
+        get_api_season_standings
+    )
+)]
+pub struct ApiDoc;
+
+// Define `api_config` registering the given handlers, and keep their names for the
+// specification tests, which check every registered handler is documented
+macro_rules! api_services {
+    ($($handler:ident),* $(,)?) => {
+        // Register the JSON API, shared by the server and the specification tests
+        pub fn api_config(cfg: &mut web::ServiceConfig) {
+            cfg.app_data(web::JsonConfig::default().error_handler(json_error_handler))
+                .service(get_openapi)
+                $(.service($handler))*;
+        }
+
+        #[cfg(test)]
+        const API_HANDLERS: &[&str] = &[$(stringify!($handler)),*];
+    };
 }
 
-#[derive(Debug, Deserialize)]
-pub struct BlockQueryParams {
-    pub all: Option<bool>,
-    pub page_size: Option<u32>,
-    pub page_offset: Option<u32>,
+api_services!(
+    post_api_block,
+    post_api_commitment,
+    get_api_commitments,
+    post_api_reveal,
+    get_api_block_replay,
+    post_api_identity,
+    get_api_identity,
+    get_api_leaderboard,
+    get_api_seasons,
+    get_api_season_standings,
+);
+
+#[get("/api/v1/openapi.json")]
+async fn get_openapi() -> impl Responder {
+    HttpResponse::Ok().json(ApiDoc::openapi())
 }
 
-#[get("/blocks")]
-async fn get_blocks(
+#[utoipa::path(
+    post,
+    path = "/api/v1/blocks",
+    request_body = CompleteBlockInfo,
+    responses(
+        (status = 201, description = "Block created", body = Block),
+        (status = 400, description = "Malformed request, missing fields, or a name, message or description too long or with forbidden characters", body = ApiErrorBody),
+        (status = 403, description = "Name reserved by a key or an account", body = ApiErrorBody),
+        (status = 404, description = "Parent block or signing identity not found", body = ApiErrorBody),
+        (status = 409, description = "Solution or block already exists, or the round requires a commitment", body = ApiErrorBody),
+        (status = 422, description = "Invalid notation, wrong solution, invalid signature or ineligible parent", body = ApiErrorBody),
+        (status = 429, description = "Too many submissions from this client or name, retry after the Retry-After header", body = ApiErrorBody),
+        (status = 500, description = "Internal error", body = ApiErrorBody),
+    )
+)]
+#[post("/api/v1/blocks")]
+async fn post_api_block(
+    conf: web::Data<config::Config>,
     request: actix_web::HttpRequest,
     db: web::Data<sqlx::SqlitePool>,
-    query_params: web::Query<BlockQueryParams>,
-) -> impl Responder {
-    if !is_htmx_request(&request) {
-        return HttpResponse::NotFound().body("This endpoint is only available for HTMX requests.");
-    }
+    events: web::Data<EventBus>,
+    limiter: web::Data<RateLimiter>,
+    block_info: web::Json<CompleteBlockInfo>,
+) -> Result<HttpResponse, ApiError> {
+    limit_submission(
+        &db,
+        &limiter,
+        &request,
+        &block_info.parent_hash,
+        &block_info.name,
+        block_info.is_signed(),
+        None,
+    )
+    .await?;
+    let limits = FieldLimits::from_config(&conf);
+    let block = submit_block(&db, &events, &limits, &block_info, None, None).await?;
+    Ok(HttpResponse::Created().json(block))
+}
 
-    let show_all = query_params.all.unwrap_or(false);
-    let page_size = query_params.page_size.unwrap_or(10);
-    let page_offset = query_params.page_offset.unwrap_or(0);
-    let next_offset = page_offset + page_size;
+#[derive(Debug, Deserialize, ToSchema)]
+struct Commitment {
+    parent_hash: String,
+    name: String,
+    message: String,
+    // Hex encoded sha256(solution || salt)
+    commitment: String,
+}
 
-    let main_chain_hashes = Block::get_main_chain_hashes(&db)
-        .await
-        .expect("Unable to fetch main chain hashes");
-    let blocks = Block::find_all(&db, !show_all, Some(page_size), Some(page_offset))
-        .await
-        .expect("Unable to fetch all blocks");
-    let optimal_height = Block::find_all(&db, false, None, None)
+#[utoipa::path(
+    post,
+    path = "/api/v1/commitments",
+    request_body = Commitment,
+    responses(
+        (status = 201, description = "Block pending until its solution is revealed", body = PendingBlock),
+        (status = 400, description = "Malformed request, missing fields, or a name or message too long or with forbidden characters, or a logged in user committing under another name", body = ApiErrorBody),
+        (status = 403, description = "Name reserved by an account the request isn't logged in to", body = ApiErrorBody),
+        (status = 404, description = "Parent block not found", body = ApiErrorBody),
+        (status = 409, description = "Round not in commit-reveal mode, or block already exists", body = ApiErrorBody),
+        (status = 422, description = "Invalid commitment or ineligible parent", body = ApiErrorBody),
+        (status = 429, description = "Too many submissions from this client, name or account, retry after the Retry-After header", body = ApiErrorBody),
+        (status = 500, description = "Internal error", body = ApiErrorBody),
+    )
+)]
+#[post("/api/v1/commitments")]
+async fn post_api_commitment(
+    conf: web::Data<config::Config>,
+    request: actix_web::HttpRequest,
+    db: web::Data<sqlx::SqlitePool>,
+    limiter: web::Data<RateLimiter>,
+    commitment: web::Json<Commitment>,
+) -> Result<HttpResponse, ApiError> {
+    let user = current_user(&request, &db).await;
+    let missing_fields = [
+        ("parent_hash", &commitment.parent_hash),
+        ("name", &commitment.name),
+        ("message", &commitment.message),
+        ("commitment", &commitment.commitment),
+    ]
+    .iter()
+    .filter(|(_, value)| value.is_empty())
+    .map(|(field, _)| field.to_string())
+    .collect::<Vec<_>>();
+    if !missing_fields.is_empty() {
+        return Err(ApiError::MissingFields {
+            fields: missing_fields,
+        });
+    }
+    // Committed to as they will be hashed when the solution is revealed
+    let limits = FieldLimits::from_config(&conf);
+    let name = validation::check_field("name", &commitment.name, limits.name, false)?;
+    let message = validation::check_field("message", &commitment.message, limits.message, true)?;
+
+    if !commit_reveal::is_enabled(&db, Utc::now().date_naive())
         .await
-        .expect("Failed to fetch blocks")
-        .iter()
-        .find(|b| b.can_create_child(None))
-        .map_or(0, |b| b.height);
+        .map_err(|_| ApiError::Internal)?
+    {
+        return Err(ApiError::CommitRevealDisabled);
+    }
+    if !commit_reveal::is_valid_commitment(&commitment.commitment) {
+        return Err(ApiError::InvalidCommitment);
+    }
 
-    HttpResponse::Ok().body(views::get_partial_blocks(
-        blocks,
-        main_chain_hashes,
-        next_offset,
-        page_size,
-        show_all,
-        optimal_height,
-    ))
+    match Block::find_by_hash(&db, &commitment.parent_hash).await {
+        Ok(block) if block.can_create_child(None) => {}
+        Ok(_) => return Err(ApiError::ParentNotEligible),
+        Err(sqlx::Error::RowNotFound) => return Err(ApiError::ParentNotFound),
+        Err(_) => return Err(ApiError::Internal),
+    }
+    check_account_name(&db, &name, user.as_ref()).await?;
+    // Every commitment stores a pending block, so they spend submission tokens. Names bound to
+    // a key are only signed for when the solution is revealed.
+    limit_submission(
+        &db,
+        &limiter,
+        &request,
+        &commitment.parent_hash,
+        &name,
+        true,
+        user.as_ref(),
+    )
+    .await?;
+
+    let hash = calculate_hash(&format_data(&commitment.parent_hash, &name, &message));
+    match Block::find_by_hash(&db, &hash).await {
+        Ok(_) => return Err(ApiError::DuplicateBlock),
+        Err(sqlx::Error::RowNotFound) => {}
+        Err(_) => return Err(ApiError::Internal),
+    }
+
+    match PendingBlock::create(
+        &db,
+        &hash,
+        &commitment.parent_hash,
+        &name,
+        &message,
+        &commitment.commitment.to_lowercase(),
+        user.map(|u| u.id),
+    )
+    .await
+    {
+        Ok(pending) => Ok(HttpResponse::Created().json(pending)),
+        Err(sqlx::Error::Database(e)) if e.is_unique_violation() => Err(ApiError::DuplicateBlock),
+        Err(_) => Err(ApiError::Internal),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/commitments",
+    responses(
+        (status = 200, description = "Blocks waiting for their solution to be revealed, oldest first", body = Vec<PendingBlock>),
+        (status = 500, description = "Internal error", body = ApiErrorBody),
+    )
+)]
+#[get("/api/v1/commitments")]
+async fn get_api_commitments(db: web::Data<sqlx::SqlitePool>) -> Result<HttpResponse, ApiError> {
+    let pending = PendingBlock::find_all(&db)
+        .await
+        .map_err(|_| ApiError::Internal)?;
+    Ok(HttpResponse::Ok().json(pending))
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+struct Reveal {
+    // The solution exactly as committed to
+    solution: String,
+    salt: String,
+    solution_description: String,
+    // Hex encoded ed25519 signature over "<hash>|<solution>", required for names bound to a key
+    #[serde(default)]
+    signature: Option<String>,
+    // Set to 3 if the commitment was to the solutions of a mean of 3 challenge
+    #[serde(default)]
+    mean_of: Option<u8>,
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/commitments/{hash}/reveal",
+    params(("hash" = String, Path, description = "Hash of the pending block")),
+    request_body = Reveal,
+    responses(
+        (status = 201, description = "Solution revealed and block added to the chain", body = Block),
+        (status = 400, description = "Malformed request, missing fields, or a description too long or with forbidden characters", body = ApiErrorBody),
+        (status = 403, description = "Name reserved by a key", body = ApiErrorBody),
+        (status = 404, description = "Pending block, parent block or signing identity not found", body = ApiErrorBody),
+        (status = 409, description = "Round not closed yet or solution already exists", body = ApiErrorBody),
+        (status = 422, description = "Solution doesn't match the commitment, invalid notation, wrong solution, invalid signature or ineligible parent", body = ApiErrorBody),
+        (status = 500, description = "Internal error", body = ApiErrorBody),
+    )
+)]
+#[post("/api/v1/commitments/{hash}/reveal")]
+async fn post_api_reveal(
+    conf: web::Data<config::Config>,
+    db: web::Data<sqlx::SqlitePool>,
+    events: web::Data<EventBus>,
+    path: web::Path<String>,
+    reveal: web::Json<Reveal>,
+) -> Result<HttpResponse, ApiError> {
+    let pending = PendingBlock::find_by_hash(&db, &path)
+        .await
+        .map_err(|_| ApiError::Internal)?
+        .ok_or(ApiError::CommitmentNotFound)?;
+
+    let committed_at = pending.created_at.ok_or(ApiError::Internal)?;
+    if !commit_reveal::is_round_closed(committed_at, Utc::now().naive_utc()) {
+        return Err(ApiError::RoundNotClosed);
+    }
+    if commit_reveal::commitment(&reveal.solution, &reveal.salt) != pending.commitment {
+        return Err(ApiError::CommitmentMismatch);
+    }
+
+    let block_info = CompleteBlockInfo {
+        parent_hash: pending.parent_hash.clone(),
+        name: pending.name.clone(),
+        message: pending.message.clone(),
+        solution: reveal.solution.clone(),
+        solution_description: reveal.solution_description.clone(),
+        signature: reveal.signature.clone(),
+        mean_of: reveal.mean_of,
+    };
+    let limits = FieldLimits::from_config(&conf);
+    let block = submit_block(&db, &events, &limits, &block_info, None, Some(&pending)).await?;
+    Ok(HttpResponse::Created().json(block))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/blocks/{hash}/replay",
+    params(("hash" = String, Path, description = "Full block hash")),
+    responses(
+        (status = 200, description = "Cube states after every solution move", body = BlockReplay),
+        (status = 404, description = "Block not found, or a mean of 3 challenge without a replay", body = ApiErrorBody),
+        (status = 500, description = "Internal error", body = ApiErrorBody),
+    )
+)]
+#[get("/api/v1/blocks/{hash}/replay")]
+async fn get_api_block_replay(
+    db: web::Data<sqlx::SqlitePool>,
+    path: web::Path<String>,
+) -> Result<HttpResponse, ApiError> {
+    let block = match Block::find_by_hash(&db, &path).await {
+        Ok(block) => block,
+        Err(sqlx::Error::RowNotFound) => return Err(ApiError::BlockNotFound),
+        Err(_) => return Err(ApiError::Internal),
+    };
+    match block.replay().filter(|_| block.mean_of.is_none()) {
+        Some(replay) => Ok(HttpResponse::Ok().json(replay)),
+        None => Err(ApiError::ReplayNotFound),
+    }
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+struct IdentityRegistration {
+    name: String,
+    // Hex encoded ed25519 public key
+    public_key: String,
+    // Hex encoded signature over "register|<name>", proving possession of the private key
+    signature: String,
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/identities",
+    request_body = IdentityRegistration,
+    responses(
+        (status = 201, description = "Name bound to the public key", body = Identity),
+        (status = 400, description = "Malformed request, missing fields, or a name too long or with forbidden characters", body = ApiErrorBody),
+        (status = 409, description = "Name already bound to a key or an account, or used by existing blocks", body = ApiErrorBody),
+        (status = 422, description = "Invalid public key or signature", body = ApiErrorBody),
+        (status = 500, description = "Internal error", body = ApiErrorBody),
+    )
+)]
+#[post("/api/v1/identities")]
+async fn post_api_identity(
+    conf: web::Data<config::Config>,
+    db: web::Data<sqlx::SqlitePool>,
+    registration: web::Json<IdentityRegistration>,
+) -> Result<HttpResponse, ApiError> {
+    let missing_fields = [
+        ("name", &registration.name),
+        ("public_key", &registration.public_key),
+        ("signature", &registration.signature),
+    ]
+    .iter()
+    .filter(|(_, value)| value.is_empty())
+    .map(|(field, _)| field.to_string())
+    .collect::<Vec<_>>();
+    if !missing_fields.is_empty() {
+        return Err(ApiError::MissingFields {
+            fields: missing_fields,
+        });
+    }
+
+    // Bound names are block names, so they follow the same rules and are stored the same way
+    let limits = FieldLimits::from_config(&conf);
+    let name = validation::check_field("name", &registration.name, limits.name, false)?;
+
+    let public_key = registration.public_key.to_lowercase();
+    if identity::parse_public_key(&public_key).is_none() {
+        return Err(ApiError::InvalidPublicKey);
+    }
+    if !identity::verify_signature(
+        &public_key,
+        &identity::registration_data(&registration.name),
+        &registration.signature,
+    ) {
+        return Err(ApiError::InvalidSignature);
+    }
+
+    match User::find_by_username(&db, &name).await {
+        Ok(None) => {}
+        Ok(Some(_)) => return Err(ApiError::NameTaken),
+        Err(_) => return Err(ApiError::Internal),
+    }
+    // Anyone could have posted the unsigned blocks already using the name, so binding it
+    // would pass off their history as the key holder's
+    match Block::name_has_blocks(&db, &name).await {
+        Ok(false) => {}
+        Ok(true) => return Err(ApiError::NameTaken),
+        Err(_) => return Err(ApiError::Internal),
+    }
+
+    match Identity::create(&db, &name, &public_key).await {
+        Ok(identity) => Ok(HttpResponse::Created().json(identity)),
+        Err(sqlx::Error::Database(e)) if e.is_unique_violation() => Err(ApiError::NameTaken),
+        Err(_) => Err(ApiError::Internal),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/identities/{name}",
+    params(("name" = String, Path, description = "Name bound to the key")),
+    responses(
+        (status = 200, description = "Public key bound to the name", body = Identity),
+        (status = 404, description = "No key registered for this name", body = ApiErrorBody),
+        (status = 500, description = "Internal error", body = ApiErrorBody),
+    )
+)]
+#[get("/api/v1/identities/{name}")]
+async fn get_api_identity(
+    db: web::Data<sqlx::SqlitePool>,
+    path: web::Path<String>,
+) -> Result<HttpResponse, ApiError> {
+    match Identity::find_by_name(&db, &path).await {
+        Ok(Some(identity)) => Ok(HttpResponse::Ok().json(identity)),
+        Ok(None) => Err(ApiError::IdentityNotFound),
+        Err(_) => Err(ApiError::Internal),
+    }
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+struct LeaderboardQueryParams {
+    // Any date within the round, all-time rankings if omitted
+    round: Option<chrono::NaiveDate>,
+    // Number of best solutions in the best mean ranking
+    n: Option<u32>,
+    // Number of ranks per ranking, ties at the last rank are included
+    limit: Option<usize>,
+}
+
+impl LeaderboardQueryParams {
+    fn round(&self) -> Option<chrono::NaiveDate> {
+        self.round.map(leaderboard::round_of)
+    }
+
+    fn mean_of(&self) -> u32 {
+        self.n.unwrap_or(leaderboard::DEFAULT_MEAN_OF).clamp(1, 100)
+    }
+
+    fn limit(&self) -> usize {
+        self.limit
+            .unwrap_or(leaderboard::DEFAULT_LIMIT)
+            .clamp(1, 100)
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/leaderboard",
+    params(LeaderboardQueryParams),
+    responses(
+        (status = 200, description = "All rankings for the round or all-time", body = Leaderboard),
+        (status = 400, description = "Malformed query", body = ApiErrorBody),
+        (status = 500, description = "Internal error", body = ApiErrorBody),
+    )
+)]
+#[get("/api/v1/leaderboard")]
+async fn get_api_leaderboard(
+    db: web::Data<sqlx::SqlitePool>,
+    query_params: web::Query<LeaderboardQueryParams>,
+) -> Result<HttpResponse, ApiError> {
+    let board = leaderboard::leaderboard(
+        &db,
+        query_params.round(),
+        query_params.mean_of(),
+        query_params.limit(),
+    )
+    .await
+    .map_err(|_| ApiError::Internal)?;
+    Ok(HttpResponse::Ok().json(board))
+}
+
+#[get("/leaderboard")]
+async fn get_leaderboard(
+    request: actix_web::HttpRequest,
+    conf: web::Data<config::Config>,
+    db: web::Data<sqlx::SqlitePool>,
+    query_params: web::Query<LeaderboardQueryParams>,
+) -> Result<HttpResponse, AppError> {
+    let board = leaderboard::leaderboard(
+        &db,
+        query_params.round(),
+        query_params.mean_of(),
+        query_params.limit(),
+    )
+    .await?;
+    let rounds = leaderboard::rounds(&db).await?;
+
+    if is_htmx_request(&request) {
+        return Ok(HttpResponse::Ok().body(views::get_partial_leaderboard(&board, &rounds)?));
+    }
+
+    let page = page_context(&request, &conf);
+    let recommended_block_count = Block::get_recommended_count(&db).await?;
+    Ok(HttpResponse::Ok().body(views::get_leaderboard(
+        page,
+        recommended_block_count,
+        &board,
+        &rounds,
+    )?))
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+struct SeasonQueryParams {
+    // Season to show, the most recent one if omitted
+    season: Option<i64>,
+    // Number of ranks, ties at the last rank are included
+    limit: Option<usize>,
+}
+
+impl SeasonQueryParams {
+    fn limit(&self) -> usize {
+        self.limit
+            .unwrap_or(leaderboard::DEFAULT_LIMIT)
+            .clamp(1, 100)
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/seasons",
+    responses(
+        (status = 200, description = "All seasons, most recent first", body = Vec<Season>),
+        (status = 500, description = "Internal error", body = ApiErrorBody),
+    )
+)]
+#[get("/api/v1/seasons")]
+async fn get_api_seasons(db: web::Data<sqlx::SqlitePool>) -> Result<HttpResponse, ApiError> {
+    let seasons = Season::find_all(&db)
+        .await
+        .map_err(|_| ApiError::Internal)?;
+    Ok(HttpResponse::Ok().json(seasons))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/seasons/{id}/standings",
+    params(
+        ("id" = i64, Path, description = "Season id"),
+        SeasonQueryParams,
+    ),
+    responses(
+        (status = 200, description = "Points standings of the season", body = Standings),
+        (status = 400, description = "Malformed query", body = ApiErrorBody),
+        (status = 404, description = "Season not found", body = ApiErrorBody),
+        (status = 500, description = "Internal error", body = ApiErrorBody),
+    )
+)]
+#[get("/api/v1/seasons/{id}/standings")]
+async fn get_api_season_standings(
+    db: web::Data<sqlx::SqlitePool>,
+    path: web::Path<i64>,
+    query_params: web::Query<SeasonQueryParams>,
+) -> Result<HttpResponse, ApiError> {
+    let season = Season::find_by_id(&db, *path)
+        .await
+        .map_err(|_| ApiError::Internal)?
+        .ok_or(ApiError::SeasonNotFound)?;
+    let standings = scoring::standings(&db, season, query_params.limit())
+        .await
+        .map_err(|_| ApiError::Internal)?;
+    Ok(HttpResponse::Ok().json(standings))
+}
+
+#[get("/seasons")]
+async fn get_seasons(
+    request: actix_web::HttpRequest,
+    conf: web::Data<config::Config>,
+    db: web::Data<sqlx::SqlitePool>,
+    query_params: web::Query<SeasonQueryParams>,
+) -> Result<HttpResponse, AppError> {
+    let seasons = Season::find_all(&db).await?;
+    let season = query_params
+        .season
+        .and_then(|id| seasons.iter().find(|s| s.id == id))
+        .or(seasons.first())
+        .cloned();
+    let standings = match season {
+        Some(season) => Some(scoring::standings(&db, season, query_params.limit()).await?),
+        None => None,
+    };
+
+    if is_htmx_request(&request) {
+        return Ok(
+            HttpResponse::Ok().body(views::get_partial_seasons(standings.as_ref(), &seasons)?)
+        );
+    }
+
+    let page = page_context(&request, &conf);
+    let recommended_block_count = Block::get_recommended_count(&db).await?;
+    Ok(HttpResponse::Ok().body(views::get_seasons(
+        page,
+        recommended_block_count,
+        standings.as_ref(),
+        &seasons,
+    )?))
+}
+
+#[get("/b/{hash}/replay")]
+async fn get_block_replay(
+    request: actix_web::HttpRequest,
+    conf: web::Data<config::Config>,
+    db: web::Data<sqlx::SqlitePool>,
+    path: web::Path<String>,
+) -> Result<HttpResponse, AppError> {
+    let block = match Block::find_by_hash(&db, &path).await {
+        Ok(block) => block,
+        Err(sqlx::Error::RowNotFound) => return Err(AppError::not_found("Block not found")),
+        Err(e) => return Err(e.into()),
+    };
+    let Some(replay) = block.replay().filter(|_| block.mean_of.is_none()) else {
+        return Err(AppError::not_found(&ApiError::ReplayNotFound.message()));
+    };
+
+    if is_htmx_request(&request) {
+        return Ok(HttpResponse::Ok().body(views::get_partial_replay(&block, &replay)?));
+    }
+
+    let page = page_context(&request, &conf);
+    let recommended_block_count = Block::get_recommended_count(&db).await?;
+    Ok(HttpResponse::Ok().body(views::get_replay(
+        page,
+        recommended_block_count,
+        &block,
+        &replay,
+    )?))
+}
+
+// Render malformed JSON bodies as structured API errors
+fn json_error_handler(
+    err: actix_web::error::JsonPayloadError,
+    _request: &actix_web::HttpRequest,
+) -> actix_web::Error {
+    ApiError::InvalidRequest {
+        detail: err.to_string(),
+    }
+    .into()
+}
+
+#[get("/b/{hash}")]
+async fn get_block_details(
+    request: actix_web::HttpRequest,
+    conf: web::Data<config::Config>,
+    db: web::Data<sqlx::SqlitePool>,
+    path: web::Path<String>,
+) -> Result<HttpResponse, AppError> {
+    let prefix = path.into_inner();
+    let htmx = is_htmx_request(&request);
+    let page = page_context(&request, &conf);
+
+    let block = match Block::find_by_hash(&db, &prefix).await {
+        Ok(block) => block,
+        Err(sqlx::Error::RowNotFound) => {
+            let mut candidates = Block::find_by_hash_prefix(&db, &prefix, 50).await?;
+            match candidates.len() {
+                0 => return Err(AppError::not_found("Block not found")),
+                1 => candidates.remove(0),
+                _ if htmx => {
+                    let html = views::get_partial_block_disambiguation(&prefix, candidates)?;
+                    return Ok(HttpResponse::Ok().body(html));
+                }
+                _ => {
+                    let recommended_block_count = Block::get_recommended_count(&db).await?;
+                    return Ok(HttpResponse::Ok().body(views::get_block_disambiguation(
+                        page,
+                        recommended_block_count,
+                        &prefix,
+                        candidates,
+                    )?));
+                }
+            }
+        }
+        Err(e) => return Err(e.into()),
+    };
+
+    let children = block.find_children(&db).await?;
+    let ancestors = block.find_ancestors(&db).await?;
+    let main_chain_hashes = Block::get_main_chain_hashes(&db).await?;
+    let fork_height = ancestors
+        .iter()
+        .find(|b| main_chain_hashes.contains(&b.hash))
+        .map(|b| b.height);
+    let optimal_height = Block::find_all(&db, false, None, None)
+        .await?
+        .iter()
+        .find(|b| b.can_create_child(None))
+        .map_or(0, |b| b.height);
+    let bindings = Bindings::for_names(&db, [block.name.as_str()]).await?;
+    let attempt = Attempt::find_submitted(&db, &block.hash).await?;
+    let challenge_attempts = ChallengeAttempt::find_by_block(&db, &block.hash).await?;
+
+    if htmx {
+        return Ok(HttpResponse::Ok().body(views::get_partial_block_details(
+            &block,
+            &children,
+            &ancestors,
+            &main_chain_hashes,
+            fork_height,
+            optimal_height,
+            &bindings,
+            attempt.as_ref(),
+            &challenge_attempts,
+        )?));
+    }
+
+    let recommended_block_count = Block::get_recommended_count(&db).await?;
+    Ok(HttpResponse::Ok().body(views::get_block_details(
+        page,
+        recommended_block_count,
+        &block,
+        &children,
+        &ancestors,
+        &main_chain_hashes,
+        fork_height,
+        optimal_height,
+        &bindings,
+        attempt.as_ref(),
+        &challenge_attempts,
+    )?))
+}
+
+#[get("/scramble/{hash}.svg")]
+async fn get_scramble_image(
+    request: actix_web::HttpRequest,
+    db: web::Data<sqlx::SqlitePool>,
+    limiter: web::Data<RateLimiter>,
+    path: web::Path<String>,
+) -> Result<HttpResponse, AppError> {
+    let hash = path.into_inner();
+    if hash.is_empty() || hash.len() > 64 {
+        return Err(AppError::bad_request("Invalid hash"));
+    }
+
+    // Existing blocks keep the scramble of their version, anything else is a new block. Those
+    // are rendered for any hash, so they are rate limited unless they are stored attempts.
+    let scramble = match Block::find_by_hash(&db, &hash).await {
+        Ok(block) => block
+            .scramble_moves()
+            .ok_or_else(|| AppError::not_found("Unsupported block version"))?,
+        Err(sqlx::Error::RowNotFound) => {
+            if !ChallengeAttempt::exists(&db, &hash).await? {
+                limiter.check_request(&request, Scope::Image, Subject::Name(""))?;
+            }
+            scramble_from_hash(&hash)
+        }
+        Err(e) => return Err(e.into()),
+    };
+
+    Ok(HttpResponse::Ok()
+        .content_type("image/svg+xml")
+        .append_header(("Cache-Control", "public, max-age=86400"))
+        .body(CubeState::from_moves(&scramble).to_svg(16)))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TreeQueryParams {
+    pub depth: Option<i64>,
+    pub rounds: Option<i64>,
+}
+
+#[get("/tree")]
+async fn get_tree(
+    db: web::Data<sqlx::SqlitePool>,
+    query_params: web::Query<TreeQueryParams>,
+) -> Result<HttpResponse, AppError> {
+    let depth = query_params.depth.unwrap_or(20).clamp(1, 500);
+    let blocks = Block::find_all(&db, false, None, None).await?;
+    let tip_height = blocks.first().map_or(0, |b| b.height);
+
+    let mut since = None;
+    if let Some(rounds) = query_params.rounds {
+        let now = Utc::now().naive_utc();
+        since = Some(start_of_week(now) - chrono::Duration::weeks(rounds.clamp(1, 520) - 1));
+    }
+
+    let window = blocks
+        .into_iter()
+        .filter(|b| b.height > tip_height - depth)
+        .filter(|b| since.is_none_or(|since| b.created_at.is_some_and(|t| t >= since)))
+        .collect::<Vec<_>>();
+    let main_chain_hashes = Block::get_main_chain_hashes(&db).await?;
+
+    Ok(HttpResponse::Ok()
+        .content_type("image/svg+xml")
+        .body(tree::render_svg(&tree::layout(&window, &main_chain_hashes))))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct FeedQueryParams {
+    pub main_chain: Option<bool>,
+}
+
+fn atom_response(body: String) -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type("application/atom+xml; charset=utf-8")
+        .body(body)
+}
+
+#[get("/feed.atom")]
+async fn get_feed(
+    request: actix_web::HttpRequest,
+    db: web::Data<sqlx::SqlitePool>,
+    query_params: web::Query<FeedQueryParams>,
+) -> Result<HttpResponse, AppError> {
+    let main_chain = query_params.main_chain.unwrap_or(false);
+    let blocks = Block::find_recent(&db, main_chain, None, FEED_SIZE).await?;
+    let base_url = base_url(&request);
+    let (title, self_url) = if main_chain {
+        (
+            "Fewest Moves Chain: main chain",
+            format!("{}/feed.atom?main_chain=true", base_url),
+        )
+    } else {
+        ("Fewest Moves Chain", format!("{}/feed.atom", base_url))
+    };
+
+    Ok(atom_response(views::get_feed(
+        title, &base_url, &self_url, blocks,
+    )?))
+}
+
+#[get("/u/{name}")]
+async fn get_profile(
+    request: actix_web::HttpRequest,
+    conf: web::Data<config::Config>,
+    db: web::Data<sqlx::SqlitePool>,
+    path: web::Path<String>,
+) -> Result<HttpResponse, AppError> {
+    let name = path.into_inner();
+    let blocks = Block::find_by_name(&db, &name).await?;
+    let main_chain_hashes = Block::get_main_chain_hashes(&db).await?;
+    let stats = UserStats::from_blocks(&blocks, &main_chain_hashes);
+    let chart_svg = stats::render_moves_chart(&blocks, &main_chain_hashes);
+
+    if is_htmx_request(&request) {
+        return Ok(HttpResponse::Ok().body(views::get_partial_profile(
+            &name,
+            &stats,
+            &chart_svg,
+            &blocks,
+            &main_chain_hashes,
+        )?));
+    }
+
+    let page = page_context(&request, &conf);
+    let recommended_block_count = Block::get_recommended_count(&db).await?;
+    Ok(HttpResponse::Ok().body(views::get_profile(
+        page,
+        recommended_block_count,
+        &name,
+        &stats,
+        &chart_svg,
+        &blocks,
+        &main_chain_hashes,
+    )?))
+}
+
+#[get("/u/{name}/feed.atom")]
+async fn get_user_feed(
+    request: actix_web::HttpRequest,
+    db: web::Data<sqlx::SqlitePool>,
+    path: web::Path<String>,
+) -> Result<HttpResponse, AppError> {
+    let name = path.into_inner();
+    let blocks = Block::find_recent(&db, false, Some(&name), FEED_SIZE).await?;
+    let base_url = base_url(&request);
+    let title = format!("Fewest Moves Chain: blocks by {}", name);
+    let self_url = format!("{}{}", base_url, request.path());
+
+    Ok(atom_response(views::get_feed(
+        &title, &base_url, &self_url, blocks,
+    )?))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct EventsQueryParams {
+    pub format: Option<String>,
+}
+
+// Render event data for the HTMX SSE extension: new blocks as list items, other events only
+// act as triggers
+async fn render_event_html(db: &sqlx::SqlitePool, event: &ChainEvent) -> String {
+    let ChainEvent::BlockCreated { hash, .. } = event else {
+        return String::new();
+    };
+    let (Ok(block), Ok(main_chain_hashes), Ok(blocks)) = (
+        Block::find_by_hash(db, hash).await,
+        Block::get_main_chain_hashes(db).await,
+        Block::find_all(db, false, None, None).await,
+    ) else {
+        return String::new();
+    };
+    let Ok(bindings) = Bindings::for_names(db, [block.name.as_str()]).await else {
+        return String::new();
+    };
+    let optimal_height = blocks
+        .iter()
+        .find(|b| b.can_create_child(None))
+        .map_or(0, |b| b.height);
+
+    views::get_partial_blocks(
+        vec![block],
+        main_chain_hashes,
+        0,
+        0,
+        true,
+        optimal_height,
+        bindings,
+        false,
+    )
+    .unwrap_or_default()
+}
+
+#[get("/events")]
+async fn get_events(
+    db: web::Data<sqlx::SqlitePool>,
+    events: web::Data<EventBus>,
+    query_params: web::Query<EventsQueryParams>,
+) -> impl Responder {
+    let html = query_params.format.as_deref() == Some("html");
+    let state = (events.subscribe(), db.into_inner());
+
+    let stream = futures_util::stream::unfold(state, move |(mut receiver, db)| async move {
+        loop {
+            let message = match rt::time::timeout(SSE_KEEP_ALIVE, receiver.recv()).await {
+                Err(_) => Bytes::from_static(b": keep-alive\n\n"),
+                Ok(Ok(event)) => {
+                    let data = if html {
+                        render_event_html(&db, &event).await
+                    } else {
+                        serde_json::to_string(&event).unwrap_or_default()
+                    };
+                    format_sse(event.name(), &data)
+                }
+                Ok(Err(RecvError::Lagged(_))) => continue,
+                Ok(Err(RecvError::Closed)) => return None,
+            };
+            return Some((Ok::<_, actix_web::Error>(message), (receiver, db)));
+        }
+    });
+
+    HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .append_header(("Cache-Control", "no-cache"))
+        .streaming(stream)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BlockQueryParams {
+    pub all: Option<bool>,
+    pub page_size: Option<u32>,
+    pub page_offset: Option<u32>,
+}
+
+#[get("/blocks")]
+async fn get_blocks(
+    request: actix_web::HttpRequest,
+    db: web::Data<sqlx::SqlitePool>,
+    query_params: web::Query<BlockQueryParams>,
+) -> Result<HttpResponse, AppError> {
+    if !is_htmx_request(&request) {
+        return Err(AppError::not_found(
+            "This endpoint is only available for HTMX requests.",
+        ));
+    }
+
+    let show_all = query_params.all.unwrap_or(false);
+    let page_size = query_params.page_size.unwrap_or(10);
+    let page_offset = query_params.page_offset.unwrap_or(0);
+    let next_offset = page_offset + page_size;
+
+    let main_chain_hashes = Block::get_main_chain_hashes(&db).await?;
+    let blocks = Block::find_all(&db, !show_all, Some(page_size), Some(page_offset)).await?;
+    let bindings = Bindings::for_names(&db, blocks.iter().map(|b| b.name.as_str())).await?;
+    let optimal_height = Block::find_all(&db, false, None, None)
+        .await?
+        .iter()
+        .find(|b| b.can_create_child(None))
+        .map_or(0, |b| b.height);
+
+    Ok(HttpResponse::Ok().body(views::get_partial_blocks(
+        blocks,
+        main_chain_hashes,
+        next_offset,
+        page_size,
+        show_all,
+        optimal_height,
+        bindings,
+        page_offset == 0,
+    )?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rate_limit::RateLimitConfig;
+    use actix_web::{App, http::StatusCode, test};
+    use serde_json::{Value, json};
+    use sqlx::SqlitePool;
+    use utoipa::openapi::path::Operation;
+
+    fn test_limiter() -> RateLimiter {
+        RateLimiter::new(
+            MemoryCache::default(),
+            RateLimitConfig::from_config(&config::test_config()),
+        )
+    }
+
+    fn documented_statuses(operation: &Operation) -> Vec<String> {
+        operation.responses.responses.keys().cloned().collect()
+    }
+
+    fn schema_properties(spec: &Value, name: &str) -> (Vec<String>, Vec<String>) {
+        let schema = &spec["components"]["schemas"][name];
+        let properties = schema["properties"]
+            .as_object()
+            .expect("Schema should have properties")
+            .keys()
+            .cloned()
+            .collect();
+        let required = schema["required"]
+            .as_array()
+            .expect("Schema should have required fields")
+            .iter()
+            .map(|v| v.as_str().unwrap().to_string())
+            .collect();
+        (properties, required)
+    }
+
+    #[sqlx::test(fixtures("../fixtures/blocks.sql"))]
+    async fn test_openapi_matches_handlers(pool: SqlitePool) {
+        let events = EventBus::default();
+        let mut receiver = events.subscribe();
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(pool))
+                .app_data(web::Data::new(events))
+                .app_data(web::Data::new(test_limiter()))
+                .app_data(web::Data::new(config::test_config()))
+                .configure(api_config),
+        )
+        .await;
+        let spec = ApiDoc::openapi();
+
+        // The served document is the generated one
+        let served: Value = test::call_and_read_body_json(
+            &app,
+            test::TestRequest::get()
+                .uri("/api/v1/openapi.json")
+                .to_request(),
+        )
+        .await;
+        let spec_json = serde_json::to_value(&spec).unwrap();
+        assert_eq!(served, spec_json);
+
+        // Every registered handler is documented, under its name as the operation id
+        let operation_ids = spec
+            .paths
+            .paths
+            .values()
+            .flat_map(|item| [item.get.as_ref(), item.post.as_ref()])
+            .flatten()
+            .filter_map(|operation| operation.operation_id.clone())
+            .collect::<Vec<_>>();
+        for handler in API_HANDLERS {
+            assert!(
+                operation_ids.iter().any(|id| id == handler),
+                "Handler {} is registered but not documented",
+                handler
+            );
+        }
+        assert_eq!(operation_ids.len(), API_HANDLERS.len());
+
+        // Every documented operation is routed and answers with a documented status
+        for (path, item) in spec.paths.paths.iter() {
+            let uri = path
+                .replace("{hash}", "unknown")
+                .replace("{name}", "unknown")
+                .replace("{id}", "0");
+            let operations = [
+                (item.get.as_ref(), test::TestRequest::get()),
+                (item.post.as_ref(), test::TestRequest::post()),
+            ];
+            for (operation, request) in operations {
+                let Some(operation) = operation else {
+                    continue;
+                };
+                let resp =
+                    test::call_service(&app, request.uri(&uri).set_json(json!({})).to_request())
+                        .await;
+                assert!(
+                    documented_statuses(operation).contains(&resp.status().as_str().to_string()),
+                    "Undocumented status {} for {}",
+                    resp.status(),
+                    path
+                );
+            }
+        }
+
+        // Successful and failed submissions match their schemas
+        let parent_hash = "genesis_block_hash_001";
+        let hash = calculate_hash(&format_data(parent_hash, "api", "Hello"));
+        let solution = scramble_from_hash(&hash)
+            .iter()
+            .rev()
+            .map(|m| m.inverse())
+            .collect::<Vec<_>>();
+        let request = json!({
+            "parent_hash": parent_hash,
+            "name": "api",
+            "message": "Hello",
+            "solution": format_moves(&solution),
+            "solution_description": "Inverse scramble",
+        });
+
+        let resp = test::call_service(
+            &app,
+            test::TestRequest::post()
+                .uri("/api/v1/blocks")
+                .set_json(&request)
+                .to_request(),
+        )
+        .await;
+        assert_eq!(resp.status(), StatusCode::CREATED);
+        let body: Value = test::read_body_json(resp).await;
+        assert_eq!(
+            receiver.try_recv().unwrap(),
+            ChainEvent::BlockCreated {
+                hash: hash.clone(),
+                parent_hash: Some(parent_hash.to_string()),
+                height: 1,
+                name: "api".to_string(),
+                solution_moves: solution.len() as u8,
+            }
+        );
+        assert!(receiver.try_recv().is_err(), "The tip should not change");
+        let (properties, required) = schema_properties(&spec_json, "Block");
+        for key in body.as_object().unwrap().keys() {
+            assert!(properties.contains(key), "Undocumented field {}", key);
+        }
+        for key in required {
+            assert!(body.get(&key).is_some(), "Missing field {}", key);
+        }
+
+        let resp = test::call_service(
+            &app,
+            test::TestRequest::post()
+                .uri("/api/v1/blocks")
+                .set_json(&request)
+                .to_request(),
+        )
+        .await;
+        assert_eq!(resp.status(), StatusCode::CONFLICT);
+        let body: Value = test::read_body_json(resp).await;
+        assert_eq!(body["error"], "duplicate_solution");
+
+        let mut invalid_request = request.clone();
+        invalid_request["solution"] = json!("R Uw");
+        let resp = test::call_service(
+            &app,
+            test::TestRequest::post()
+                .uri("/api/v1/blocks")
+                .set_json(&invalid_request)
+                .to_request(),
+        )
+        .await;
+        assert_eq!(resp.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        let body: Value = test::read_body_json(resp).await;
+        assert_eq!(body["error"], "invalid_notation");
+        assert_eq!(body["position"], 3);
+
+        let resp = test::call_service(
+            &app,
+            test::TestRequest::get()
+                .uri("/api/v1/blocks/genesis_block_hash_001/replay")
+                .to_request(),
+        )
+        .await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let body: Value = test::read_body_json(resp).await;
+        let (properties, _) = schema_properties(&spec_json, "BlockReplay");
+        for key in body.as_object().unwrap().keys() {
+            assert!(properties.contains(key), "Undocumented field {}", key);
+        }
+        assert_eq!(body["steps"].as_array().unwrap().len(), 2);
+    }
+
+    #[sqlx::test(fixtures("../fixtures/blocks.sql"))]
+    async fn test_events(pool: SqlitePool) {
+        use actix_web::body::MessageBody;
+
+        let events = EventBus::default();
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(pool.clone()))
+                .app_data(web::Data::new(events.clone()))
+                .service(get_events)
+                .service(get_blocks),
+        )
+        .await;
+
+        let resp = test::call_service(
+            &app,
+            test::TestRequest::get()
+                .uri("/events?format=html")
+                .to_request(),
+        )
+        .await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(
+            resp.headers().get("Content-Type").unwrap(),
+            "text/event-stream"
+        );
+
+        // New blocks are streamed rendered, without subscribing again
+        let hash = "genesis_block_hash_001";
+        events.publish(ChainEvent::block_created(
+            &Block::find_by_hash(&pool, hash).await.unwrap(),
+        ));
+        let mut body = std::pin::pin!(resp.into_body());
+        let chunk = futures_util::future::poll_fn(|cx| body.as_mut().poll_next(cx))
+            .await
+            .unwrap()
+            .unwrap();
+        let chunk = String::from_utf8_lossy(&chunk);
+        assert!(
+            chunk.starts_with("event: block_created\ndata: "),
+            "{}",
+            chunk
+        );
+        assert!(chunk.contains(&format!("/b/{}", hash)));
+        assert!(!chunk.contains("sse-connect"));
+
+        // Only the first page of the overview subscribes to the stream
+        let blocks = |offset: u32| {
+            test::TestRequest::get()
+                .uri(&format!("/blocks?all=true&page_offset={}", offset))
+                .insert_header(("HX-Request", "true"))
+                .to_request()
+        };
+        let body = test::call_and_read_body(&app, blocks(0)).await;
+        assert!(String::from_utf8_lossy(&body).contains("sse-connect=\"/events?format=html\""));
+        let body = test::call_and_read_body(&app, blocks(10)).await;
+        assert!(!String::from_utf8_lossy(&body).contains("sse-connect"));
+    }
+
+    #[sqlx::test]
+    async fn test_tip_changes(pool: SqlitePool) {
+        crate::setup::run_setup(&pool).await.unwrap();
+        let genesis = Block::find_tip(&pool).await.unwrap().unwrap();
+        let events = EventBus::default();
+        let mut receiver = events.subscribe();
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(pool.clone()))
+                .app_data(web::Data::new(events))
+                .app_data(web::Data::new(test_limiter()))
+                .app_data(web::Data::new(config::test_config()))
+                .configure(api_config),
+        )
+        .await;
+        let submit = |parent_hash: &str, name: &str| {
+            let hash = calculate_hash(&format_data(parent_hash, name, "Hello"));
+            let solution = scramble_from_hash(&hash)
+                .iter()
+                .rev()
+                .map(|m| m.inverse())
+                .collect::<Vec<_>>();
+            let request = test::TestRequest::post()
+                .uri("/api/v1/blocks")
+                .set_json(json!({
+                    "parent_hash": parent_hash,
+                    "name": name,
+                    "message": "Hello",
+                    "solution": format_moves(&solution),
+                    "solution_description": "Inverse scramble",
+                }))
+                .to_request();
+            (hash, request)
+        };
+
+        // The first block replaces the genesis block as the tip
+        let (first, request) = submit(&genesis.hash, "first");
+        let resp = test::call_service(&app, request).await;
+        assert_eq!(resp.status(), StatusCode::CREATED);
+        assert!(matches!(
+            receiver.try_recv().unwrap(),
+            ChainEvent::BlockCreated { .. }
+        ));
+        assert_eq!(
+            receiver.try_recv().unwrap(),
+            ChainEvent::TipChanged {
+                hash: first.clone(),
+                previous_hash: Some(genesis.hash.clone()),
+                height: 1,
+            }
+        );
+
+        // A sibling only takes over with a shorter solution, which switches branches
+        let (second, request) = submit(&genesis.hash, "second");
+        let resp = test::call_service(&app, request).await;
+        assert_eq!(resp.status(), StatusCode::CREATED);
+        assert!(matches!(
+            receiver.try_recv().unwrap(),
+            ChainEvent::BlockCreated { .. }
+        ));
+        let tip = Block::find_tip(&pool).await.unwrap().unwrap();
+        if tip.hash == second {
+            assert_eq!(
+                receiver.try_recv().unwrap(),
+                ChainEvent::TipChanged {
+                    hash: second,
+                    previous_hash: Some(first),
+                    height: 1,
+                }
+            );
+        } else {
+            assert_eq!(tip.hash, first);
+        }
+        assert!(receiver.try_recv().is_err());
+    }
+
+    #[sqlx::test(fixtures("../fixtures/blocks.sql"))]
+    async fn test_signed_blocks(pool: SqlitePool) {
+        use ed25519_dalek::{Signer, SigningKey};
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(pool.clone()))
+                .app_data(web::Data::new(EventBus::default()))
+                .app_data(web::Data::new(test_limiter()))
+                .app_data(web::Data::new(config::test_config()))
+                .configure(api_config),
+        )
+        .await;
+        let key = SigningKey::from_bytes(&[3; 32]);
+        let public_key = hex::encode(key.verifying_key().as_bytes());
+        let sign = |data: Vec<u8>| hex::encode(key.sign(&data).to_bytes());
+
+        let post = |uri: &str, body: &Value| {
+            test::TestRequest::post()
+                .uri(uri)
+                .set_json(body)
+                .to_request()
+        };
+        let registration = json!({
+            "name": "Nootr",
+            "public_key": public_key,
+            "signature": sign(identity::registration_data("Nootr")),
+        });
+        let resp = test::call_service(&app, post("/api/v1/identities", &registration)).await;
+        assert_eq!(resp.status(), StatusCode::CREATED);
+        let resp = test::call_service(&app, post("/api/v1/identities", &registration)).await;
+        assert_eq!(resp.status(), StatusCode::CONFLICT);
+
+        let mut forged = registration.clone();
+        forged["name"] = json!("Alice");
+        let resp = test::call_service(&app, post("/api/v1/identities", &forged)).await;
+        assert_eq!(resp.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+        // Names follow the rules of block names, and are bound in NFC
+        let register = |name: &str| {
+            json!({
+                "name": name,
+                "public_key": public_key,
+                "signature": sign(identity::registration_data(name)),
+            })
+        };
+        let resp =
+            test::call_service(&app, post("/api/v1/identities", &register("Ze\u{301}lie"))).await;
+        assert_eq!(resp.status(), StatusCode::CREATED);
+        let body: Value = test::read_body_json(resp).await;
+        assert_eq!(body["name"], "Z\u{e9}lie");
+        let resp =
+            test::call_service(&app, post("/api/v1/identities", &register("Z\u{e9}lie"))).await;
+        assert_eq!(resp.status(), StatusCode::CONFLICT);
+        for name in ["Mal\u{202E}lory", "Mal\nlory", &"a".repeat(33)] {
+            let resp = test::call_service(&app, post("/api/v1/identities", &register(name))).await;
+            assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+            let body: Value = test::read_body_json(resp).await;
+            assert_eq!(body["error"], "invalid_field");
+        }
+
+        let parent_hash = "genesis_block_hash_001";
+        let hash = calculate_hash(&format_data(parent_hash, "Nootr", "Signed"));
+        let solution = format_moves(
+            &scramble_from_hash(&hash)
+                .iter()
+                .rev()
+                .map(|m| m.inverse())
+                .collect::<Vec<_>>(),
+        );
+        let mut request = json!({
+            "parent_hash": parent_hash,
+            "name": "Nootr",
+            "message": "Signed",
+            "solution": solution,
+            "solution_description": "Inverse scramble",
+        });
+
+        // The bound name can't be used without a signature, or with someone else's
+        let resp = test::call_service(&app, post("/api/v1/blocks", &request)).await;
+        assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+        request["signature"] = json!(hex::encode(
+            SigningKey::from_bytes(&[4; 32])
+                .sign(&identity::block_data(&hash, &solution))
+                .to_bytes()
+        ));
+        let resp = test::call_service(&app, post("/api/v1/blocks", &request)).await;
+        assert_eq!(resp.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+        request["signature"] = json!(sign(identity::block_data(&hash, &solution)));
+        let resp = test::call_service(&app, post("/api/v1/blocks", &request)).await;
+        assert_eq!(resp.status(), StatusCode::CREATED);
+
+        let block = Block::find_by_hash(&pool, &hash).await.unwrap();
+        assert!(block.is_valid());
+        assert!(block.is_verified(Some(&public_key)));
+        assert_eq!(block.public_key, Some(public_key.clone()));
+        let bindings = Bindings::for_names(&pool, ["Nootr", "Alice"])
+            .await
+            .unwrap();
+        assert_eq!(bindings.public_key("Nootr"), Some(public_key.as_str()));
+        assert_eq!(bindings.public_key("Alice"), None);
+
+        // Only the key currently bound to the name verifies a block
+        let other_key = hex::encode(SigningKey::from_bytes(&[4; 32]).verifying_key().as_bytes());
+        assert!(!block.is_verified(Some(&other_key)));
+        assert!(!block.is_verified(None));
+
+        let mut tampered = block.clone();
+        tampered.signature = Some(sign(identity::block_data(&hash, "R")));
+        assert!(!tampered.is_valid());
+        assert!(!tampered.is_verified(Some(&public_key)));
+
+        // Names already used by blocks can't be claimed
+        let registration = json!({
+            "name": "Alice",
+            "public_key": public_key,
+            "signature": sign(identity::registration_data("Alice")),
+        });
+        let resp = test::call_service(&app, post("/api/v1/identities", &registration)).await;
+        assert_eq!(resp.status(), StatusCode::CONFLICT);
+        let body: Value = test::read_body_json(resp).await;
+        assert_eq!(body["error"], "name_taken");
+    }
+
+    #[sqlx::test(fixtures("../fixtures/blocks.sql"))]
+    async fn test_account_blocks(pool: SqlitePool) {
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(pool.clone()))
+                .app_data(web::Data::new(EventBus::default()))
+                .app_data(web::Data::new(test_limiter()))
+                .app_data(web::Data::new(config::test_config()))
+                .service(post_register)
+                .service(post_login)
+                .service(post_logout)
+                .service(post_solution),
+        )
+        .await;
+        let credentials = |username: &str, password: &str| {
+            test::TestRequest::post()
+                .uri("/register")
+                .set_form([("username", username), ("password", password)])
+        };
+
+        // Names used by existing blocks can't be claimed
+        let resp = test::call_service(&app, credentials("Alice", "password123").to_request()).await;
+        assert_eq!(resp.status(), StatusCode::CONFLICT);
+
+        // Usernames follow the rules of block names
+        let resp = test::call_service(
+            &app,
+            credentials("Ca\u{202E}rol", "password123").to_request(),
+        )
+        .await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+        let resp = test::call_service(&app, credentials("Carol", "password123").to_request()).await;
+        assert_eq!(resp.headers().get("HX-Redirect").unwrap(), "/");
+        let session = resp
+            .response()
+            .cookies()
+            .find(|c| c.name() == accounts::SESSION_COOKIE_NAME)
+            .expect("Registering should log in")
+            .into_owned();
+        assert_eq!(session.secure(), Some(true));
+
+        // Names are compared in NFC, however they are typed
+        let resp =
+            test::call_service(&app, credentials("Zoe\u{301}", "password123").to_request()).await;
+        assert_eq!(resp.headers().get("HX-Redirect").unwrap(), "/");
+        let resp =
+            test::call_service(&app, credentials("Zo\u{e9}", "password123").to_request()).await;
+        assert_eq!(resp.status(), StatusCode::CONFLICT);
+        assert!(
+            User::find_by_username(&pool, "Zo\u{e9}")
+                .await
+                .unwrap()
+                .is_some()
+        );
+
+        // Guessing the password is rate limited, after which even the right one is refused
+        let login = |password: &str| credentials("Carol", password).uri("/login").to_request();
+        for _ in 0..config::test_config().rate_limit_burst {
+            let resp = test::call_service(&app, login("wrong password")).await;
+            assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+        }
+        let resp = test::call_service(&app, login("password123")).await;
+        assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+
+        let parent_hash = "genesis_block_hash_001";
+        let form = |name: &str, message: &str| {
+            let hash = calculate_hash(&format_data(parent_hash, name, message));
+            let solution = format_moves(
+                &scramble_from_hash(&hash)
+                    .iter()
+                    .rev()
+                    .map(|m| m.inverse())
+                    .collect::<Vec<_>>(),
+            );
+            (
+                hash,
+                [
+                    ("parent_hash", parent_hash.to_string()),
+                    ("name", name.to_string()),
+                    ("message", message.to_string()),
+                    ("solution", solution),
+                    ("solution_description", "Inverse scramble".to_string()),
+                ],
+            )
+        };
+
+        // The account name is reserved for its owner
+        let (_, anonymous) = form("Carol", "Anonymous");
+        let resp = test::call_service(
+            &app,
+            test::TestRequest::post()
+                .uri("/solution")
+                .set_form(anonymous)
+                .to_request(),
+        )
+        .await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+        // ... who can't post under another name
+        let (_, other_name) = form("Dave", "Impostor");
+        let resp = test::call_service(
+            &app,
+            test::TestRequest::post()
+                .uri("/solution")
+                .cookie(session.clone())
+                .set_form(other_name)
+                .to_request(),
+        )
+        .await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+        let (hash, own) = form("Carol", "Logged in");
+        let resp = test::call_service(
+            &app,
+            test::TestRequest::post()
+                .uri("/solution")
+                .cookie(session.clone())
+                .set_form(own)
+                .to_request(),
+        )
+        .await;
+        assert_eq!(resp.status(), StatusCode::TEMPORARY_REDIRECT);
+
+        let block = Block::find_by_hash(&pool, &hash).await.unwrap();
+        let user = User::find_by_username(&pool, "Carol")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(block.user_id, Some(user.id));
+        assert!(block.is_valid());
+
+        // The leaderboard aggregates are refreshed on insert
+        let board = leaderboard::leaderboard(&pool, None, 1, 100).await.unwrap();
+        assert!(board.rankings[0].entries.iter().any(|e| e.name == "Carol"));
+
+        let resp = test::call_service(
+            &app,
+            test::TestRequest::post()
+                .uri("/logout")
+                .cookie(session.clone())
+                .to_request(),
+        )
+        .await;
+        assert_eq!(resp.status(), StatusCode::TEMPORARY_REDIRECT);
+        assert!(
+            User::find_by_session(&pool, session.value())
+                .await
+                .unwrap()
+                .is_none()
+        );
+    }
+
+    #[sqlx::test(fixtures("../fixtures/blocks.sql"))]
+    async fn test_commit_reveal(pool: SqlitePool) {
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(pool.clone()))
+                .app_data(web::Data::new(EventBus::default()))
+                .app_data(web::Data::new(test_limiter()))
+                .app_data(web::Data::new(config::test_config()))
+                .configure(api_config),
+        )
+        .await;
+        commit_reveal::enable(&pool, Utc::now().date_naive())
+            .await
+            .unwrap();
+
+        let parent_hash = "genesis_block_hash_001";
+        let hash = calculate_hash(&format_data(parent_hash, "Mallory", "Hidden"));
+        let solution = format_moves(
+            &scramble_from_hash(&hash)
+                .iter()
+                .rev()
+                .map(|m| m.inverse())
+                .collect::<Vec<_>>(),
+        );
+
+        // Plain submissions are refused while the round is in commit-reveal mode
+        let resp = test::call_service(
+            &app,
+            test::TestRequest::post()
+                .uri("/api/v1/blocks")
+                .set_json(json!({
+                    "parent_hash": parent_hash,
+                    "name": "Mallory",
+                    "message": "Hidden",
+                    "solution": solution,
+                    "solution_description": "Inverse scramble",
+                }))
+                .to_request(),
+        )
+        .await;
+        assert_eq!(resp.status(), StatusCode::CONFLICT);
+        let body: Value = test::read_body_json(resp).await;
+        assert_eq!(body["error"], "commitment_required");
+
+        let commit = |commitment: String| {
+            test::TestRequest::post()
+                .uri("/api/v1/commitments")
+                .set_json(json!({
+                    "parent_hash": parent_hash,
+                    "name": "Mallory",
+                    "message": "Hidden",
+                    "commitment": commitment,
+                }))
+                .to_request()
+        };
+        let resp = test::call_service(&app, commit("not a hash".to_string())).await;
+        assert_eq!(resp.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+        let commitment = commit_reveal::commitment(&solution, "pepper");
+        let resp = test::call_service(&app, commit(commitment.clone())).await;
+        assert_eq!(resp.status(), StatusCode::CREATED);
+        let body: Value = test::read_body_json(resp).await;
+        assert_eq!(body["hash"], hash);
+        assert_eq!(body["commitment"], commitment);
+
+        let resp = test::call_service(&app, commit(commitment)).await;
+        assert_eq!(resp.status(), StatusCode::CONFLICT);
+
+        // Revealing has to wait for the round to close
+        let reveal = |salt: &str| {
+            test::TestRequest::post()
+                .uri(&format!("/api/v1/commitments/{}/reveal", hash))
+                .set_json(json!({
+                    "solution": solution,
+                    "salt": salt,
+                    "solution_description": "Inverse scramble",
+                }))
+                .to_request()
+        };
+        let resp = test::call_service(&app, reveal("pepper")).await;
+        assert_eq!(resp.status(), StatusCode::CONFLICT);
+        let body: Value = test::read_body_json(resp).await;
+        assert_eq!(body["error"], "round_not_closed");
+
+        sqlx::query("UPDATE pending_blocks SET created_at = datetime('now', '-7 days')")
+            .execute(&pool)
+            .await
+            .unwrap();
+        let committed_at = PendingBlock::find_by_hash(&pool, &hash)
+            .await
+            .unwrap()
+            .unwrap()
+            .created_at;
+
+        let resp = test::call_service(&app, reveal("salt")).await;
+        assert_eq!(resp.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        let body: Value = test::read_body_json(resp).await;
+        assert_eq!(body["error"], "commitment_mismatch");
+
+        let resp = test::call_service(&app, reveal("pepper")).await;
+        assert_eq!(resp.status(), StatusCode::CREATED);
+
+        // The block belongs to the round it was committed in
+        let block = Block::find_by_hash(&pool, &hash).await.unwrap();
+        assert!(block.is_valid());
+        assert_eq!(block.created_at, committed_at);
+        assert!(
+            PendingBlock::find_by_hash(&pool, &hash)
+                .await
+                .unwrap()
+                .is_none()
+        );
+
+        let resp = test::call_service(&app, reveal("pepper")).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[sqlx::test(fixtures("../fixtures/blocks.sql"))]
+    async fn test_commit_reveal_account(pool: SqlitePool) {
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(pool.clone()))
+                .app_data(web::Data::new(EventBus::default()))
+                .app_data(web::Data::new(test_limiter()))
+                .app_data(web::Data::new(config::test_config()))
+                .configure(api_config),
+        )
+        .await;
+        commit_reveal::enable(&pool, Utc::now().date_naive())
+            .await
+            .unwrap();
+        let user = User::create(&pool, "Carol", "password123").await.unwrap();
+        let session = accounts::session_cookie(&user.create_session(&pool).await.unwrap());
+
+        let parent_hash = "genesis_block_hash_001";
+        let hash = calculate_hash(&format_data(parent_hash, "Carol", "Hidden"));
+        let solution = format_moves(
+            &scramble_from_hash(&hash)
+                .iter()
+                .rev()
+                .map(|m| m.inverse())
+                .collect::<Vec<_>>(),
+        );
+        let commit = |name: &str| {
+            test::TestRequest::post()
+                .uri("/api/v1/commitments")
+                .set_json(json!({
+                    "parent_hash": parent_hash,
+                    "name": name,
+                    "message": "Hidden",
+                    "commitment": commit_reveal::commitment(&solution, "pepper"),
+                }))
+        };
+
+        // Account names are reserved for their owner, who can't commit under another name
+        let resp = test::call_service(&app, commit("Carol").to_request()).await;
+        assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+        let resp =
+            test::call_service(&app, commit("Mallory").cookie(session.clone()).to_request()).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+        let resp = test::call_service(&app, commit("Carol").cookie(session).to_request()).await;
+        assert_eq!(resp.status(), StatusCode::CREATED);
+
+        // The revealed block belongs to the account that committed to it
+        sqlx::query("UPDATE pending_blocks SET created_at = datetime('now', '-7 days')")
+            .execute(&pool)
+            .await
+            .unwrap();
+        let resp = test::call_service(
+            &app,
+            test::TestRequest::post()
+                .uri(&format!("/api/v1/commitments/{}/reveal", hash))
+                .set_json(json!({
+                    "solution": solution,
+                    "salt": "pepper",
+                    "solution_description": "Inverse scramble",
+                }))
+                .to_request(),
+        )
+        .await;
+        assert_eq!(resp.status(), StatusCode::CREATED);
+        let block = Block::find_by_hash(&pool, &hash).await.unwrap();
+        assert_eq!(block.user_id, Some(user.id));
+    }
+
+    #[sqlx::test(fixtures("../fixtures/blocks.sql"))]
+    async fn test_timed_attempt(pool: SqlitePool) {
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(pool.clone()))
+                .app_data(web::Data::new(EventBus::default()))
+                .app_data(web::Data::new(test_limiter()))
+                .app_data(web::Data::new(config::test_config()))
+                .service(get_solution)
+                .service(post_solution)
+                .service(get_block_details),
+        )
+        .await;
+        let user = User::create(&pool, "Carol", "password123").await.unwrap();
+        let session = accounts::session_cookie(&user.create_session(&pool).await.unwrap());
+
+        let parent_hash = "genesis_block_hash_001";
+        let hash = calculate_hash(&format_data(parent_hash, "Carol", "Against the clock"));
+        let reveal = |timed: bool| {
+            test::TestRequest::get()
+                .uri(&format!(
+                    "/solution?parent_hash={}&message=Against+the+clock{}",
+                    parent_hash,
+                    if timed { "&timed=on" } else { "" }
+                ))
+                .insert_header(("HX-Request", "true"))
+                .cookie(session.clone())
+                .to_request()
+        };
+
+        // Untimed reveals are recorded without starting the clock, and the scramble can't be
+        // attempted timed afterwards
+        let seen_hash = calculate_hash(&format_data(parent_hash, "Carol", "Seen"));
+        let seen = |timed: bool| {
+            test::TestRequest::get()
+                .uri(&format!(
+                    "/solution?parent_hash={}&message=Seen{}",
+                    parent_hash,
+                    if timed { "&timed=on" } else { "" }
+                ))
+                .insert_header(("HX-Request", "true"))
+                .cookie(session.clone())
+                .to_request()
+        };
+        let body = test::call_and_read_body(&app, seen(false)).await;
+        assert!(!String::from_utf8_lossy(&body).contains("countdown"));
+        let untimed = Attempt::find(&pool, &seen_hash, user.id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(!untimed.timed);
+        let resp = test::call_service(&app, seen(true)).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+        let body = test::call_and_read_body(&app, reveal(true)).await;
+        assert!(String::from_utf8_lossy(&body).contains("countdown"));
+        let attempt = Attempt::find(&pool, &hash, user.id).await.unwrap().unwrap();
+
+        // Showing the scramble again keeps counting from the first reveal
+        let body = test::call_and_read_body(&app, reveal(false)).await;
+        assert!(String::from_utf8_lossy(&body).contains("countdown"));
+        assert_eq!(
+            Attempt::find(&pool, &hash, user.id)
+                .await
+                .unwrap()
+                .unwrap()
+                .revealed_at,
+            attempt.revealed_at
+        );
+
+        let solution = format_moves(
+            &scramble_from_hash(&hash)
+                .iter()
+                .rev()
+                .map(|m| m.inverse())
+                .collect::<Vec<_>>(),
+        );
+        let resp = test::call_service(
+            &app,
+            test::TestRequest::post()
+                .uri("/solution")
+                .cookie(session.clone())
+                .set_form([
+                    ("parent_hash", parent_hash),
+                    ("name", "Carol"),
+                    ("message", "Against the clock"),
+                    ("solution", &solution),
+                    ("solution_description", "Inverse scramble"),
+                ])
+                .to_request(),
+        )
+        .await;
+        assert_eq!(resp.status(), StatusCode::TEMPORARY_REDIRECT);
+
+        let attempt = Attempt::find_submitted(&pool, &hash)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(attempt.official);
+        assert!(attempt.elapsed_seconds.unwrap() < crate::attempts::TIME_LIMIT_SECONDS);
+
+        let body = test::call_and_read_body(
+            &app,
+            test::TestRequest::get()
+                .uri(&format!("/b/{}", hash))
+                .insert_header(("HX-Request", "true"))
+                .to_request(),
+        )
+        .await;
+        assert!(String::from_utf8_lossy(&body).contains("Official, solved in"));
+    }
+
+    #[sqlx::test(fixtures("../fixtures/blocks.sql"))]
+    async fn test_mean_of_3_challenge(pool: SqlitePool) {
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(pool.clone()))
+                .app_data(web::Data::new(EventBus::default()))
+                .app_data(web::Data::new(test_limiter()))
+                .app_data(web::Data::new(config::test_config()))
+                .configure(api_config)
+                .service(get_block_details)
+                .service(get_block_replay),
+        )
+        .await;
+
+        let parent_hash = "genesis_block_hash_001";
+        let solutions = (1..=challenges::MEAN_OF)
+            .map(|i| {
+                let attempt_hash = challenges::attempt_hash(parent_hash, "Dave", "Three times", i);
+                format_moves(
+                    &scramble_from_hash(&attempt_hash)
+                        .iter()
+                        .rev()
+                        .map(|m| m.inverse())
+                        .collect::<Vec<_>>(),
+                )
+            })
+            .collect::<Vec<_>>();
+        let submit = |solution: String, mean_of: u8| {
+            test::TestRequest::post()
+                .uri("/api/v1/blocks")
+                .set_json(json!({
+                    "parent_hash": parent_hash,
+                    "name": "Dave",
+                    "message": "Three times",
+                    "solution": solution,
+                    "solution_description": "Inverse scrambles",
+                    "mean_of": mean_of,
+                }))
+                .to_request()
+        };
+
+        // Every attempt needs a solution
+        let resp = test::call_service(&app, submit(solutions[..2].join(" / "), 3)).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+        let resp = test::call_service(&app, submit(solutions.join(" / "), 5)).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+        let resp = test::call_service(&app, submit(solutions.join(" / "), 3)).await;
+        assert_eq!(resp.status(), StatusCode::CREATED);
+
+        let hash = calculate_hash(&format_data(parent_hash, "Dave", "Three times"));
+        let block = Block::find_by_hash(&pool, &hash).await.unwrap();
+        assert_eq!(block.mean_of, Some(3));
+        assert!(block.is_valid());
+
+        let attempts = ChallengeAttempt::find_by_block(&pool, &hash).await.unwrap();
+        assert_eq!(attempts.len(), 3);
+        let moves = attempts
+            .iter()
+            .map(|a| a.solution_moves)
+            .collect::<Vec<_>>();
+        assert_eq!(block.solution_moves, challenges::rounded_mean(&moves));
+
+        let body = test::call_and_read_body(
+            &app,
+            test::TestRequest::get()
+                .uri(&format!("/b/{}", hash))
+                .insert_header(("HX-Request", "true"))
+                .to_request(),
+        )
+        .await;
+        let body = String::from_utf8_lossy(&body);
+        assert!(body.contains("Mean of 3"));
+        assert!(body.contains("Attempt 3"));
+
+        // Challenges have no replay, in the API or on the site
+        for uri in [
+            format!("/api/v1/blocks/{}/replay", hash),
+            format!("/b/{}/replay", hash),
+        ] {
+            let resp =
+                test::call_service(&app, test::TestRequest::get().uri(&uri).to_request()).await;
+            assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+        }
+    }
+
+    #[sqlx::test(fixtures("../fixtures/blocks.sql"))]
+    async fn test_rate_limiting(pool: SqlitePool) {
+        let limiter = RateLimiter::new(
+            MemoryCache::default(),
+            RateLimitConfig {
+                burst: 2,
+                per_minute: 1.0,
+                trust_proxy_headers: false,
+            },
+        );
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(pool.clone()))
+                .app_data(web::Data::new(EventBus::default()))
+                .app_data(web::Data::new(limiter))
+                .app_data(web::Data::new(config::test_config()))
+                .service(get_solution)
+                .service(post_solution)
+                .configure(api_config),
+        )
+        .await;
+        let submit = |name: &str, ip: &str| {
+            test::TestRequest::post()
+                .uri("/api/v1/blocks")
+                .peer_addr(format!("{}:4000", ip).parse().unwrap())
+                .set_json(json!({
+                    "parent_hash": "genesis_block_hash_001",
+                    "name": name,
+                    "message": "Spam",
+                    "solution": "R U",
+                    "solution_description": "Guess",
+                }))
+                .to_request()
+        };
+
+        // Wrong solutions spend tokens too
+        for _ in 0..2 {
+            let resp = test::call_service(&app, submit("Mallory", "10.0.0.1")).await;
+            assert_eq!(resp.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        }
+        let resp = test::call_service(&app, submit("Mallory", "10.0.0.1")).await;
+        assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(
+            resp.headers()
+                .get(actix_web::http::header::RETRY_AFTER)
+                .unwrap(),
+            "60"
+        );
+        let body: Value = test::read_body_json(resp).await;
+        assert_eq!(body["error"], "rate_limited");
+        assert_eq!(body["retry_after"], 60);
+
+        // The name is limited from other addresses, and the address under other names
+        let resp = test::call_service(&app, submit("Mallory", "10.0.0.2")).await;
+        assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+        let resp = test::call_service(&app, submit("Trudy", "10.0.0.1")).await;
+        assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+        let resp = test::call_service(&app, submit("Trudy", "10.0.0.2")).await;
+        assert_eq!(resp.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+        // The form shows the error, and revealing scrambles has its own limit
+        let resp = test::call_service(
+            &app,
+            test::TestRequest::post()
+                .uri("/solution")
+                .peer_addr("10.0.0.1:4000".parse().unwrap())
+                .set_form([
+                    ("parent_hash", "genesis_block_hash_001"),
+                    ("name", "Mallory"),
+                    ("message", "Spam"),
+                    ("solution", "R U"),
+                    ("solution_description", "Guess"),
+                ])
+                .to_request(),
+        )
+        .await;
+        assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert!(resp.response().extensions().contains::<FlashMessage>());
+
+        let reveal = |message: &str| {
+            test::TestRequest::get()
+                .uri(&format!(
+                    "/solution?parent_hash=genesis_block_hash_001&name=Eve&message={}",
+                    message
+                ))
+                .peer_addr("10.0.0.3:4000".parse().unwrap())
+                .insert_header(("HX-Request", "true"))
+                .to_request()
+        };
+        for message in ["First", "Second"] {
+            let resp = test::call_service(&app, reveal(message)).await;
+            assert_eq!(resp.status(), StatusCode::OK);
+        }
+        let resp = test::call_service(&app, reveal("Third")).await;
+        assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert!(
+            resp.headers()
+                .contains_key(actix_web::http::header::RETRY_AFTER)
+        );
+    }
+
+    #[sqlx::test(fixtures("../fixtures/blocks.sql"))]
+    async fn test_rate_limiting_reserved_names(pool: SqlitePool) {
+        let limiter = RateLimiter::new(
+            MemoryCache::default(),
+            RateLimitConfig {
+                burst: 1,
+                per_minute: 1.0,
+                trust_proxy_headers: false,
+            },
+        );
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(pool.clone()))
+                .app_data(web::Data::new(EventBus::default()))
+                .app_data(web::Data::new(limiter))
+                .app_data(web::Data::new(config::test_config()))
+                .service(get_solution)
+                .service(post_solution)
+                .configure(api_config),
+        )
+        .await;
+        let user = User::create(&pool, "Carol", "password123").await.unwrap();
+        let session = accounts::session_cookie(&user.create_session(&pool).await.unwrap());
+        Identity::create(&pool, "Dave", &"ab".repeat(32))
+            .await
+            .unwrap();
+        let submit = |name: &str, ip: &str| {
+            test::TestRequest::post()
+                .uri("/api/v1/blocks")
+                .peer_addr(format!("{}:4000", ip).parse().unwrap())
+                .set_json(json!({
+                    "parent_hash": "genesis_block_hash_001",
+                    "name": name,
+                    "message": "Spam",
+                    "solution": "R U",
+                    "solution_description": "Guess",
+                }))
+                .to_request()
+        };
+
+        // Reserved names are refused before any token is spent
+        for ip in ["10.0.0.1", "10.0.0.2", "10.0.0.3"] {
+            let resp = test::call_service(&app, submit("Carol", ip)).await;
+            assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+            let resp = test::call_service(&app, submit("Dave", ip)).await;
+            assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+        }
+        let resp = test::call_service(&app, submit("Someone", "10.0.0.1")).await;
+        assert_eq!(resp.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+        // The owner still has their tokens, which are kept by account
+        let reveal = test::TestRequest::get()
+            .uri("/solution?parent_hash=genesis_block_hash_001&name=Carol&message=Mine")
+            .peer_addr("10.0.0.4:4000".parse().unwrap())
+            .insert_header(("HX-Request", "true"))
+            .cookie(session.clone())
+            .to_request();
+        let resp = test::call_service(&app, reveal).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let post = |ip: &str| {
+            test::TestRequest::post()
+                .uri("/solution")
+                .peer_addr(format!("{}:4000", ip).parse().unwrap())
+                .cookie(session.clone())
+                .set_form([
+                    ("parent_hash", "genesis_block_hash_001"),
+                    ("name", "Carol"),
+                    ("message", "Mine"),
+                    ("solution", "R U"),
+                    ("solution_description", "Guess"),
+                ])
+                .to_request()
+        };
+        let resp = test::call_service(&app, post("10.0.0.4")).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        let resp = test::call_service(&app, post("10.0.0.5")).await;
+        assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+    }
+
+    #[sqlx::test(fixtures("../fixtures/blocks.sql"))]
+    async fn test_rate_limiting_commitments_and_images(pool: SqlitePool) {
+        let limiter = RateLimiter::new(
+            MemoryCache::default(),
+            RateLimitConfig {
+                burst: 2,
+                per_minute: 1.0,
+                trust_proxy_headers: false,
+            },
+        );
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(pool.clone()))
+                .app_data(web::Data::new(EventBus::default()))
+                .app_data(web::Data::new(limiter))
+                .app_data(web::Data::new(config::test_config()))
+                .service(get_scramble_image)
+                .configure(api_config),
+        )
+        .await;
+        commit_reveal::enable(&pool, Utc::now().date_naive())
+            .await
+            .unwrap();
+
+        // Every commitment stores a pending block, so they are limited like submissions
+        let commit = |message: &str| {
+            test::TestRequest::post()
+                .uri("/api/v1/commitments")
+                .peer_addr("10.0.0.1:4000".parse().unwrap())
+                .set_json(json!({
+                    "parent_hash": "genesis_block_hash_001",
+                    "name": "Mallory",
+                    "message": message,
+                    "commitment": "ab".repeat(32),
+                }))
+                .to_request()
+        };
+        for message in ["First", "Second"] {
+            let resp = test::call_service(&app, commit(message)).await;
+            assert_eq!(resp.status(), StatusCode::CREATED);
+        }
+        let resp = test::call_service(&app, commit("Third")).await;
+        assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+        let body: Value = test::read_body_json(resp).await;
+        assert_eq!(body["error"], "rate_limited");
+        assert_eq!(PendingBlock::find_all(&pool).await.unwrap().len(), 2);
+
+        // Scrambles of existing blocks can be drawn freely, those of any other hash are limited
+        let image = |hash: &str| {
+            test::TestRequest::get()
+                .uri(&format!("/scramble/{}.svg", hash))
+                .peer_addr("10.0.0.2:4000".parse().unwrap())
+                .to_request()
+        };
+        for _ in 0..3 {
+            let resp = test::call_service(&app, image("genesis_block_hash_001")).await;
+            assert_eq!(resp.status(), StatusCode::OK);
+        }
+        for hash in ["aa", "bb"] {
+            let resp = test::call_service(&app, image(hash)).await;
+            assert_eq!(resp.status(), StatusCode::OK);
+        }
+        let resp = test::call_service(&app, image("cc")).await;
+        assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+    }
+
+    #[sqlx::test(fixtures("../fixtures/blocks.sql"))]
+    async fn test_block_text_validation(pool: SqlitePool) {
+        let mut config = config::test_config();
+        config.max_message_length = 10;
+        config.max_name_length = 8;
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(pool.clone()))
+                .app_data(web::Data::new(EventBus::default()))
+                .app_data(web::Data::new(test_limiter()))
+                .app_data(web::Data::new(config))
+                .service(get_solution)
+                .service(post_register)
+                .configure(api_config),
+        )
+        .await;
+
+        // Usernames are limited like the names of blocks, so accounts can post under theirs
+        let register = |username: &str| {
+            test::TestRequest::post()
+                .uri("/register")
+                .set_form([("username", username), ("password", "password123")])
+                .to_request()
+        };
+        let resp = test::call_service(&app, register("Bartholomew")).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        let resp = test::call_service(&app, register("Bart")).await;
+        assert_eq!(resp.headers().get("HX-Redirect").unwrap(), "/");
+        let parent_hash = "genesis_block_hash_001";
+        let submit = |name: &str, message: &str, solution: &str| {
+            test::TestRequest::post()
+                .uri("/api/v1/blocks")
+                .set_json(json!({
+                    "parent_hash": parent_hash,
+                    "name": name,
+                    "message": message,
+                    "solution": solution,
+                    "solution_description": "Inverse scramble",
+                }))
+                .to_request()
+        };
+
+        for (name, message, field) in [
+            ("Alice", "Too long a message", "message"),
+            ("Alice\u{202E}", "Spoofed", "name"),
+            ("Alice", "Beep\u{7}", "message"),
+        ] {
+            let resp = test::call_service(&app, submit(name, message, "R")).await;
+            assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+            let body: Value = test::read_body_json(resp).await;
+            assert_eq!(body["error"], "invalid_field");
+            assert_eq!(body["field"], field);
+        }
+
+        // The block is hashed and stored with the normalised text, and the scramble shown
+        // for the decomposed text is the one it is hashed with
+        let hash = calculate_hash(&format_data(parent_hash, "José", "Hi\nthere"));
+        let solution = format_moves(
+            &scramble_from_hash(&hash)
+                .iter()
+                .rev()
+                .map(|m| m.inverse())
+                .collect::<Vec<_>>(),
+        );
+        let resp = test::call_service(
+            &app,
+            test::TestRequest::get()
+                .uri("/solution?parent_hash=genesis_block_hash_001&name=Jose%CC%81&message=Hi%0D%0Athere")
+                .insert_header(("HX-Request", "true"))
+                .to_request(),
+        )
+        .await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let body = String::from_utf8(test::read_body(resp).await.to_vec()).unwrap();
+        assert!(body.contains(&hash));
+
+        let resp = test::call_service(&app, submit("Jose\u{301}", "Hi\r\nthere", &solution)).await;
+        assert_eq!(resp.status(), StatusCode::CREATED);
+        let block = Block::find_by_hash(&pool, &hash).await.unwrap();
+        assert_eq!(block.version, Block::CURRENT_VERSION);
+        assert_eq!(block.name, "José");
+        assert_eq!(block.message, "Hi\nthere");
+        assert!(block.is_valid());
+
+        // Text that doesn't follow the rules only verifies in blocks from before them
+        let mut decomposed = block.clone();
+        decomposed.name = "Jose\u{301}".to_string();
+        decomposed.hash = calculate_hash(&format_data(parent_hash, "Jose\u{301}", "Hi\nthere"));
+        decomposed.solution = format_moves(
+            &scramble_from_hash(&decomposed.hash)
+                .iter()
+                .rev()
+                .map(|m| m.inverse())
+                .collect::<Vec<_>>(),
+        );
+        decomposed.solution_moves = decomposed.solution.split(' ').count() as u8;
+        assert!(!decomposed.is_valid());
+        decomposed.version = 2;
+        assert!(decomposed.is_valid());
+    }
+
+    #[sqlx::test(fixtures("../fixtures/blocks.sql"))]
+    async fn test_form_notation(pool: SqlitePool) {
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(pool.clone()))
+                .app_data(web::Data::new(EventBus::default()))
+                .app_data(web::Data::new(test_limiter()))
+                .app_data(web::Data::new(config::test_config()))
+                .service(post_solution),
+        )
+        .await;
+
+        // The form is as strict as the API: skipping the brackets would change the solution
+        for (solution, message) in [
+            ("(R U) R'", "unexpected '(' at position 0"),
+            ("R U // Block", "unexpected '/' at position 4"),
+            ("Rw U", "unexpected 'w' at position 1"),
+        ] {
+            let resp = test::call_service(
+                &app,
+                test::TestRequest::post()
+                    .uri("/solution")
+                    .set_form([
+                        ("parent_hash", "genesis_block_hash_001"),
+                        ("name", "Alice"),
+                        ("message", "Hello"),
+                        ("solution", solution),
+                        ("solution_description", "Notes"),
+                    ])
+                    .to_request(),
+            )
+            .await;
+            assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+            let flash = resp
+                .response()
+                .extensions()
+                .get::<FlashMessage>()
+                .cloned()
+                .unwrap();
+            assert!(flash.message().contains(message), "{}", flash.message());
+        }
+    }
+
+    #[sqlx::test(fixtures("../fixtures/blocks.sql"))]
+    async fn test_forms_and_flash_messages(pool: SqlitePool) {
+        let app = test::init_service(
+            App::new()
+                .wrap(actix_web::middleware::from_fn(csrf::protect))
+                .wrap(actix_web::middleware::from_fn(
+                    crate::messages::flash_messages,
+                ))
+                .app_data(web::Data::new(pool.clone()))
+                .app_data(web::Data::new(config::test_config()))
+                .app_data(web::Data::new(MemoryCache::<String, String>::default()))
+                .service(get_index)
+                .service(post_logout),
+        )
+        .await;
+
+        // The page carries the token for HTMX to send back
+        let resp = test::call_service(&app, test::TestRequest::get().uri("/").to_request()).await;
+        let csrf_cookie = resp
+            .response()
+            .cookies()
+            .find(|c| c.name() == csrf::CSRF_COOKIE_NAME)
+            .unwrap()
+            .into_owned();
+        let body = String::from_utf8(test::read_body(resp).await.to_vec()).unwrap();
+        let token = body
+            .split("\"X-CSRF-Token\": \"")
+            .nth(1)
+            .and_then(|rest| rest.split('"').next())
+            .unwrap()
+            .to_string();
+        assert_eq!(token.len(), 64);
+
+        // Forms posted from other sites are rejected
+        let resp = test::call_service(
+            &app,
+            test::TestRequest::post()
+                .uri("/logout")
+                .cookie(csrf_cookie.clone())
+                .insert_header(("HX-Request", "true"))
+                .to_request(),
+        )
+        .await;
+        assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+        let body = String::from_utf8(test::read_body(resp).await.to_vec()).unwrap();
+        assert!(body.contains("hx-swap-oob"));
+        assert!(body.contains("This form has expired"));
+
+        let resp = test::call_service(
+            &app,
+            test::TestRequest::post()
+                .uri("/logout")
+                .cookie(csrf_cookie.clone())
+                .insert_header(("HX-Request", "true"))
+                .insert_header((csrf::CSRF_HEADER, token.as_str()))
+                .to_request(),
+        )
+        .await;
+        assert_eq!(resp.headers().get("HX-Redirect").unwrap(), "/");
+        let flash_cookie = resp
+            .response()
+            .cookies()
+            .find(|c| c.name() == "flash")
+            .unwrap()
+            .into_owned();
+
+        // The next page shows the message, once
+        let resp = test::call_service(
+            &app,
+            test::TestRequest::get()
+                .uri("/")
+                .cookie(csrf_cookie.clone())
+                .cookie(flash_cookie)
+                .to_request(),
+        )
+        .await;
+        let body = String::from_utf8(test::read_body(resp).await.to_vec()).unwrap();
+        assert!(body.contains("flash-message-info"));
+        assert!(body.contains("You have been logged out."));
+        let body = test::call_and_read_body(
+            &app,
+            test::TestRequest::get()
+                .uri("/")
+                .cookie(csrf_cookie)
+                .to_request(),
+        )
+        .await;
+        assert!(!String::from_utf8_lossy(&body).contains("flash-message-info"));
+    }
+
+    #[sqlx::test(fixtures("../fixtures/blocks.sql"))]
+    async fn test_page_script_nonces(pool: SqlitePool) {
+        let app = test::init_service(
+            App::new()
+                .wrap(actix_web::middleware::from_fn(security::security_headers))
+                .app_data(web::Data::new(pool.clone()))
+                .app_data(web::Data::new(config::test_config()))
+                .app_data(web::Data::new(MemoryCache::<String, String>::default()))
+                .service(get_index),
+        )
+        .await;
+
+        let resp = test::call_service(&app, test::TestRequest::get().uri("/").to_request()).await;
+        let csp = resp
+            .headers()
+            .get(actix_web::http::header::CONTENT_SECURITY_POLICY)
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .to_string();
+        assert!(
+            resp.headers()
+                .contains_key(actix_web::http::header::STRICT_TRANSPORT_SECURITY)
+        );
+        let body = String::from_utf8(test::read_body(resp).await.to_vec()).unwrap();
+
+        // Every script of the page carries the nonce of the policy
+        let nonce = csp
+            .split("'nonce-")
+            .nth(1)
+            .and_then(|rest| rest.split('\'').next())
+            .unwrap();
+        let scripts = body.matches("<script").count();
+        assert!(scripts > 0);
+        assert_eq!(
+            body.matches(&format!("<script nonce=\"{}\"", nonce))
+                .count(),
+            scripts
+        );
+        assert!(body.contains(&format!("\"inlineScriptNonce\": \"{}\"", nonce)));
+
+        // Every script the page loads is vendored
+        for script in body.split("src=\"/static/").skip(1) {
+            let file = script.split('"').next().unwrap();
+            assert!(
+                std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+                    .join("static")
+                    .join(file)
+                    .is_file(),
+                "static/{} is missing",
+                file
+            );
+        }
+    }
+
+    #[sqlx::test]
+    async fn test_errors_without_blocks(pool: SqlitePool) {
+        let app = test::init_service(
+            App::new()
+                .wrap(actix_web::middleware::from_fn(crate::errors::render_errors))
+                .app_data(web::Data::new(pool.clone()))
+                .app_data(web::Data::new(config::test_config()))
+                .app_data(web::Data::new(MemoryCache::<String, String>::default()))
+                .service(get_index)
+                .service(get_parent)
+                .service(get_block_details),
+        )
+        .await;
+
+        // The home page still renders, with nothing to recommend
+        let resp = test::call_service(&app, test::TestRequest::get().uri("/").to_request()).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        // No genesis block means no eligible parent, which used to crash the worker
+        for htmx in [true, false] {
+            let mut request = test::TestRequest::get().uri("/parent");
+            if htmx {
+                request = request.insert_header(("HX-Request", "true"));
+            }
+            let resp = test::call_service(&app, request.to_request()).await;
+            assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+        }
+
+        let resp = test::call_service(
+            &app,
+            test::TestRequest::get()
+                .uri("/parent")
+                .insert_header(("Accept", "application/json"))
+                .to_request(),
+        )
+        .await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+        let body: Value = test::read_body_json(resp).await;
+        assert_eq!(body["error"], "not_found");
+        assert_eq!(body["message"], "No block can be used as a parent yet.");
+
+        let resp = test::call_service(
+            &app,
+            test::TestRequest::get()
+                .uri("/b/missing")
+                .insert_header(("Accept", "application/json"))
+                .to_request(),
+        )
+        .await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+        let body: Value = test::read_body_json(resp).await;
+        assert_eq!(body["error"], "not_found");
+        assert_eq!(body["message"], "Block not found");
+    }
+
+    #[sqlx::test(fixtures("../fixtures/blocks.sql"))]
+    async fn test_metrics(pool: SqlitePool) {
+        let app = test::init_service(
+            App::new()
+                .wrap(actix_web::middleware::from_fn(metrics::track_requests))
+                .service(get_metrics),
+        )
+        .await;
+        let scrape = || test::TestRequest::get().uri("/metrics").to_request();
+        metrics::update_chain_gauges(&pool).await.unwrap();
+
+        let resp = test::call_service(&app, scrape()).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let body = test::read_body(resp).await;
+        let text = String::from_utf8_lossy(&body);
+        let main_chain_length = Block::get_main_chain_hashes(&pool).await.unwrap().len();
+        assert!(text.contains(&format!("main_chain_length {}", main_chain_length)));
+        assert!(text.contains("block_submissions_total{outcome=\"accepted\"}"));
+
+        // Requests are labelled by route pattern
+        let body = test::call_and_read_body(&app, scrape()).await;
+        assert!(
+            String::from_utf8_lossy(&body)
+                .contains("http_requests_total{method=\"GET\",route=\"/metrics\",status=\"200\"}")
+        );
+    }
+
+    #[sqlx::test]
+    async fn test_health_checks(pool: SqlitePool) {
+        crate::setup::run_setup(&pool).await.unwrap();
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(pool.clone()))
+                .app_data(web::Data::new(ChainVerifier::default()))
+                .app_data(web::Data::new(MemoryCache::<String, String>::default()))
+                .service(get_health_live)
+                .service(get_health_ready),
+        )
+        .await;
+        let ready = || test::TestRequest::get().uri("/health/ready").to_request();
+
+        let resp = test::call_service(&app, ready()).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let body: Value = test::read_body_json(resp).await;
+        assert_eq!(body["status"], "ok");
+        assert_eq!(body["components"]["migrations"]["status"], "ok");
+
+        // Losing the database makes the pod unready, but it is still alive
+        pool.close().await;
+        let resp = test::call_service(&app, ready()).await;
+        assert_eq!(resp.status(), StatusCode::SERVICE_UNAVAILABLE);
+        let body: Value = test::read_body_json(resp).await;
+        assert_eq!(body["status"], "error");
+        assert_eq!(body["components"]["database"]["status"], "error");
+        assert_eq!(body["components"]["cache"]["status"], "ok");
+
+        let resp = test::call_service(
+            &app,
+            test::TestRequest::get().uri("/health/live").to_request(),
+        )
+        .await;
+        assert_eq!(resp.status(), StatusCode::OK);
+    }
 }
@@ -0,0 +1,533 @@
+use actix_web::rt;
+use chrono::NaiveDate;
+use serde::Serialize;
+use sqlx::{FromRow, Sqlite, SqlitePool, Transaction};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::Arc;
+use tokio::sync::broadcast::error::RecvError;
+use utoipa::ToSchema;
+
+use crate::config::Config;
+use crate::events::{ChainEvent, EventBus};
+use crate::leaderboard::{assign_ranks, round_of};
+use crate::models::Block;
+
+// What a block is compared against when it is scored
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RoundContext {
+    // Fewest moves of any solution in the block's round
+    pub best_moves: u8,
+}
+
+pub trait ScoringFormula: Send + Sync {
+    fn points(&self, block: &Block, main_chain: bool, round: &RoundContext) -> f64;
+}
+
+// Points relative to the best solution of the round, so matching it earns the full points and
+// a solution twice as long earns half. Blocks on the main chain get a bonus, and solutions no
+// shorter than their scramble (like undoing it) a penalty. Scores never go below zero.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RelativeScoring {
+    pub points: f64,
+    pub main_chain_bonus: f64,
+    pub trivial_penalty: f64,
+}
+
+impl Default for RelativeScoring {
+    fn default() -> Self {
+        Self {
+            points: 100.0,
+            main_chain_bonus: 25.0,
+            trivial_penalty: 50.0,
+        }
+    }
+}
+
+impl RelativeScoring {
+    pub fn from_config(conf: &Config) -> Self {
+        Self {
+            points: conf.scoring_points,
+            main_chain_bonus: conf.scoring_main_chain_bonus,
+            trivial_penalty: conf.scoring_trivial_penalty,
+        }
+    }
+}
+
+impl ScoringFormula for RelativeScoring {
+    fn points(&self, block: &Block, main_chain: bool, round: &RoundContext) -> f64 {
+        let mut points = self.points * round.best_moves as f64 / block.solution_moves.max(1) as f64;
+        if main_chain {
+            points += self.main_chain_bonus;
+        }
+        if is_trivial(block) {
+            points -= self.trivial_penalty;
+        }
+        points.max(0.0)
+    }
+}
+
+// A solution that is no shorter than the scramble it solves
+pub fn is_trivial(block: &Block) -> bool {
+    block
+        .scramble_moves()
+        .is_some_and(|scramble| block.solution_moves as usize >= scramble.len())
+}
+
+// A season groups the rounds between two dates, both included
+#[derive(Debug, Clone, FromRow, Serialize, ToSchema)]
+pub struct Season {
+    pub id: i64,
+    pub name: String,
+    pub starts_on: NaiveDate,
+    pub ends_on: NaiveDate,
+}
+
+impl Season {
+    pub async fn create(
+        db: &SqlitePool,
+        name: &str,
+        starts_on: NaiveDate,
+        ends_on: NaiveDate,
+    ) -> Result<Self, sqlx::Error> {
+        let mut tx = db.begin().await?;
+        let season = sqlx::query_as::<_, Season>(
+            "INSERT INTO seasons (name, starts_on, ends_on) VALUES (?, ?, ?)
+            RETURNING id, name, starts_on, ends_on",
+        )
+        .bind(name)
+        .bind(starts_on)
+        .bind(ends_on)
+        .fetch_one(&mut *tx)
+        .await?;
+        tx.commit().await?;
+
+        Ok(season)
+    }
+
+    // All seasons, most recent first
+    pub async fn find_all(db: &SqlitePool) -> Result<Vec<Self>, sqlx::Error> {
+        sqlx::query_as::<_, Season>(
+            "SELECT id, name, starts_on, ends_on FROM seasons ORDER BY starts_on DESC, id DESC",
+        )
+        .fetch_all(db)
+        .await
+    }
+
+    pub async fn find_by_id(db: &SqlitePool, id: i64) -> Result<Option<Self>, sqlx::Error> {
+        sqlx::query_as::<_, Season>("SELECT id, name, starts_on, ends_on FROM seasons WHERE id = ?")
+            .bind(id)
+            .fetch_optional(db)
+            .await
+    }
+
+    // Seasons that include the given date
+    pub async fn find_containing(
+        db: &SqlitePool,
+        date: NaiveDate,
+    ) -> Result<Vec<Self>, sqlx::Error> {
+        sqlx::query_as::<_, Season>(
+            "SELECT id, name, starts_on, ends_on FROM seasons
+            WHERE starts_on <= ?1 AND ?1 <= ends_on
+            ORDER BY starts_on DESC, id DESC",
+        )
+        .bind(date)
+        .fetch_all(db)
+        .await
+    }
+
+    pub fn contains(&self, date: NaiveDate) -> bool {
+        self.starts_on <= date && date <= self.ends_on
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
+pub struct Standing {
+    pub rank: usize,
+    pub name: String,
+    pub points: f64,
+    // True if another name has the same points
+    pub tied: bool,
+    pub block_count: i64,
+    pub round_count: i64,
+}
+
+impl Standing {
+    pub fn points_display(&self) -> String {
+        format!("{:.1}", self.points)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct Standings {
+    pub season: Season,
+    pub standings: Vec<Standing>,
+}
+
+#[derive(Debug, Default)]
+struct Tally {
+    points: f64,
+    block_count: i64,
+    round_count: i64,
+}
+
+// Score the blocks of a season. Each name earns the points of its best block in every round,
+// so submitting more blocks in a round doesn't add up.
+fn tally(
+    season: &Season,
+    blocks: &[Block],
+    main_chain_hashes: &HashSet<String>,
+    formula: &dyn ScoringFormula,
+) -> HashMap<String, Tally> {
+    let mut rounds: BTreeMap<NaiveDate, Vec<&Block>> = BTreeMap::new();
+    for block in blocks.iter().filter(|b| b.height > 0) {
+        let Some(date) = block.created_at.map(|t| t.date()) else {
+            continue;
+        };
+        if season.contains(date) {
+            rounds.entry(round_of(date)).or_default().push(block);
+        }
+    }
+
+    let mut tallies: HashMap<String, Tally> = HashMap::new();
+    for round_blocks in rounds.values() {
+        // The best single solution sets the bar, means only when the round has no singles
+        let singles = round_blocks.iter().filter(|b| b.mean_of.is_none());
+        let Some(best_moves) = singles
+            .map(|b| b.solution_moves)
+            .min()
+            .or_else(|| round_blocks.iter().map(|b| b.solution_moves).min())
+        else {
+            continue;
+        };
+        let context = RoundContext { best_moves };
+
+        let mut best_per_name: HashMap<&str, (f64, i64)> = HashMap::new();
+        for block in round_blocks {
+            let points = formula.points(block, main_chain_hashes.contains(&block.hash), &context);
+            let entry = best_per_name.entry(&block.name).or_insert((0.0, 0));
+            entry.0 = entry.0.max(points);
+            entry.1 += 1;
+        }
+
+        for (name, (points, block_count)) in best_per_name {
+            let tally = tallies.entry(name.to_string()).or_default();
+            tally.points += points;
+            tally.block_count += block_count;
+            tally.round_count += 1;
+        }
+    }
+
+    tallies
+}
+
+// Rebuild the standings of every season from the blocks, for example after the formula changed
+pub async fn recompute_standings(
+    db: &SqlitePool,
+    formula: &dyn ScoringFormula,
+) -> Result<(), sqlx::Error> {
+    let seasons = Season::find_all(db).await?;
+    let blocks = Block::find_all(db, false, None, None).await?;
+    let main_chain_hashes = Block::get_main_chain_hashes(db).await?;
+
+    let mut tx = db.begin().await?;
+    sqlx::query("DELETE FROM season_standings")
+        .execute(&mut *tx)
+        .await?;
+    for season in &seasons {
+        insert_standings(
+            &mut tx,
+            season,
+            tally(season, &blocks, &main_chain_hashes, formula),
+        )
+        .await?;
+    }
+    tx.commit().await
+}
+
+// Rebuild the standings of the seasons that include the given date, for example after a block
+// was added on that day
+pub async fn recompute_standings_on(
+    db: &SqlitePool,
+    date: NaiveDate,
+    formula: &dyn ScoringFormula,
+) -> Result<(), sqlx::Error> {
+    let main_chain_hashes = Block::get_main_chain_hashes(db).await?;
+    for season in Season::find_containing(db, date).await? {
+        let blocks = Block::find_created_between(db, season.starts_on, season.ends_on).await?;
+
+        let mut tx = db.begin().await?;
+        sqlx::query("DELETE FROM season_standings WHERE season_id = ?")
+            .bind(season.id)
+            .execute(&mut *tx)
+            .await?;
+        insert_standings(
+            &mut tx,
+            &season,
+            tally(&season, &blocks, &main_chain_hashes, formula),
+        )
+        .await?;
+        tx.commit().await?;
+    }
+    Ok(())
+}
+
+async fn insert_standings(
+    tx: &mut Transaction<'_, Sqlite>,
+    season: &Season,
+    tallies: HashMap<String, Tally>,
+) -> Result<(), sqlx::Error> {
+    for (name, tally) in tallies {
+        sqlx::query(
+            "INSERT INTO season_standings (season_id, name, points, block_count, round_count)
+            VALUES (?, ?, ?, ?, ?)",
+        )
+        .bind(season.id)
+        .bind(name)
+        .bind(tally.points)
+        .bind(tally.block_count)
+        .bind(tally.round_count)
+        .execute(&mut **tx)
+        .await?;
+    }
+    Ok(())
+}
+
+pub async fn standings(
+    db: &SqlitePool,
+    season: Season,
+    limit: usize,
+) -> Result<Standings, sqlx::Error> {
+    let rows = sqlx::query_as::<_, (String, f64, i64, i64)>(
+        "SELECT name, points, block_count, round_count
+        FROM season_standings
+        WHERE season_id = ?
+        ORDER BY points DESC, name",
+    )
+    .bind(season.id)
+    .fetch_all(db)
+    .await?;
+
+    let counts = rows
+        .iter()
+        .map(|(name, _, blocks, rounds)| (name.clone(), (*blocks, *rounds)))
+        .collect::<HashMap<_, _>>();
+    let standings = assign_ranks(
+        rows.into_iter()
+            .map(|(name, points, _, _)| (name, points))
+            .collect(),
+        limit,
+    )
+    .into_iter()
+    .map(|entry| {
+        let (block_count, round_count) = counts[&entry.name];
+        Standing {
+            rank: entry.rank,
+            name: entry.name,
+            points: entry.value,
+            tied: entry.tied,
+            block_count,
+            round_count,
+        }
+    })
+    .collect();
+
+    Ok(Standings { season, standings })
+}
+
+// Keep the standings up to date as blocks are added. A new block changes the best solution of
+// its round, so the seasons of its day are recomputed. A reorg can move older blocks on or off
+// the main chain, so it recomputes every season, as does missing events.
+pub fn start_standings_task(db: SqlitePool, events: &EventBus, formula: Arc<dyn ScoringFormula>) {
+    let mut receiver = events.subscribe();
+
+    rt::spawn(async move {
+        // None recomputes every season
+        let mut date: Option<NaiveDate> = None;
+        let mut last_created: Option<(String, Option<String>)> = None;
+        loop {
+            let result = match date {
+                Some(date) => recompute_standings_on(&db, date, formula.as_ref()).await,
+                None => recompute_standings(&db, formula.as_ref()).await,
+            };
+            if let Err(e) = result {
+                tracing::error!(error = %e, "failed to recompute season standings");
+            }
+
+            date = loop {
+                match receiver.recv().await {
+                    Ok(ChainEvent::BlockCreated {
+                        hash, parent_hash, ..
+                    }) => {
+                        let created_at = Block::find_by_hash(&db, &hash)
+                            .await
+                            .ok()
+                            .and_then(|block| block.created_at);
+                        last_created = Some((hash, parent_hash));
+                        break created_at.map(|t| t.date());
+                    }
+                    // Extending the tip by the last block only changes the main chain status
+                    // of that block, which its recompute already included
+                    Ok(ChainEvent::TipChanged {
+                        hash,
+                        previous_hash,
+                        ..
+                    }) => {
+                        let extends_tip = last_created.as_ref().is_some_and(|(created, parent)| {
+                            *created == hash && *parent == previous_hash
+                        });
+                        if !extends_tip {
+                            break None;
+                        }
+                    }
+                    Ok(ChainEvent::RoundStarted { .. }) => continue,
+                    Err(RecvError::Lagged(_)) => break None,
+                    Err(RecvError::Closed) => return,
+                }
+            };
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::test_block;
+    use crate::utils::{calculate_hash, scramble_from_hash};
+
+    fn block(hash: &str, moves: u8) -> Block {
+        Block {
+            parent_hash: Some("parent".to_string()),
+            height: 1,
+            name: "Alice".to_string(),
+            solution_moves: moves,
+            ..test_block(hash)
+        }
+    }
+
+    #[test]
+    fn test_relative_scoring() {
+        let formula = RelativeScoring::default();
+        let context = RoundContext { best_moves: 10 };
+        let hash = calculate_hash(b"scoring");
+        let scramble_length = scramble_from_hash(&hash).len() as u8;
+        assert!(scramble_length > 20);
+
+        assert_eq!(formula.points(&block(&hash, 10), false, &context), 100.0);
+        assert_eq!(formula.points(&block(&hash, 20), false, &context), 50.0);
+        assert_eq!(formula.points(&block(&hash, 20), true, &context), 75.0);
+
+        // Undoing the scramble is trivial, and the score doesn't go negative
+        let trivial = block(&hash, scramble_length);
+        assert!(is_trivial(&trivial));
+        assert!(!is_trivial(&block(&hash, scramble_length - 1)));
+        let expected = (100.0 * 10.0 / scramble_length as f64 - 50.0).max(0.0);
+        assert_eq!(formula.points(&trivial, false, &context), expected);
+        assert_eq!(formula.points(&block(&hash, 255), false, &context), 0.0);
+    }
+
+    struct CountBlocks;
+
+    impl ScoringFormula for CountBlocks {
+        fn points(&self, _: &Block, _: bool, _: &RoundContext) -> f64 {
+            1.0
+        }
+    }
+
+    // Scores every block with the best move count of its round
+    struct RoundBest;
+
+    impl ScoringFormula for RoundBest {
+        fn points(&self, _: &Block, _: bool, round: &RoundContext) -> f64 {
+            round.best_moves as f64
+        }
+    }
+
+    #[test]
+    fn test_round_best_leaves_out_means() {
+        let date = |s: &str| NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap();
+        let season = Season {
+            id: 1,
+            name: "Winter".to_string(),
+            starts_on: date("2025-01-06"),
+            ends_on: date("2025-01-26"),
+        };
+        let at = |block: Block, day: &str| Block {
+            created_at: Some(date(day).and_hms_opt(12, 0, 0).unwrap()),
+            ..block
+        };
+        let challenge = |hash: &str, moves: u8| Block {
+            name: "Bob".to_string(),
+            mean_of: Some(3),
+            ..block(hash, moves)
+        };
+        let blocks = vec![
+            at(block("a", 24), "2025-01-07"),
+            at(challenge("b", 20), "2025-01-08"),
+            // A round with only a challenge still has a bar to score against
+            at(challenge("c", 22), "2025-01-14"),
+        ];
+
+        let tallies = tally(&season, &blocks, &HashSet::new(), &RoundBest);
+        assert_eq!(tallies["Alice"].points, 24.0);
+        assert_eq!(tallies["Bob"].points, 24.0 + 22.0);
+    }
+
+    #[sqlx::test(fixtures("../fixtures/blocks.sql"))]
+    async fn test_recompute_standings(pool: SqlitePool) {
+        let date = |s: &str| NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap();
+        let season = Season::create(&pool, "Winter", date("2024-12-30"), date("2025-03-30"))
+            .await
+            .unwrap();
+        let empty = Season::create(&pool, "Summer", date("2025-06-30"), date("2025-09-28"))
+            .await
+            .unwrap();
+
+        recompute_standings(&pool, &RelativeScoring::default())
+            .await
+            .unwrap();
+        let result = standings(&pool, season.clone(), 10).await.unwrap();
+
+        // Every block except genesis is in one round, and the best solution has one move
+        let names = result
+            .standings
+            .iter()
+            .map(|s| s.name.as_str())
+            .collect::<HashSet<_>>();
+        assert_eq!(names.len(), 6);
+        assert!(!names.contains("Alice"));
+        assert_eq!(result.standings[0].points, 125.0);
+        assert_eq!(result.standings[0].rank, 1);
+        assert!(result.standings.iter().all(|s| s.round_count == 1));
+        let eve = result.standings.iter().find(|s| s.name == "Eve").unwrap();
+        assert_eq!(eve.points, 20.0);
+
+        assert!(
+            standings(&pool, empty.clone(), 10)
+                .await
+                .unwrap()
+                .standings
+                .is_empty()
+        );
+
+        // Changing the formula and recomputing replaces the standings
+        recompute_standings(&pool, &CountBlocks).await.unwrap();
+        let result = standings(&pool, season.clone(), 10).await.unwrap();
+        assert!(result.standings.iter().all(|s| s.points == 1.0 && s.tied));
+
+        // Recomputing a day only replaces the standings of the seasons that include it
+        sqlx::query("INSERT INTO season_standings VALUES (?, 'Frank', 1.0, 1, 1)")
+            .bind(empty.id)
+            .execute(&pool)
+            .await
+            .unwrap();
+        recompute_standings_on(&pool, date("2025-01-01"), &RelativeScoring::default())
+            .await
+            .unwrap();
+        let result = standings(&pool, season, 10).await.unwrap();
+        assert_eq!(result.standings[0].points, 125.0);
+        assert_eq!(
+            standings(&pool, empty, 10).await.unwrap().standings.len(),
+            1
+        );
+    }
+}
@@ -0,0 +1,202 @@
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{self, HeaderName, HeaderValue};
+use actix_web::middleware::Next;
+use actix_web::{HttpMessage, HttpRequest, web};
+use rand_core::{OsRng, RngCore};
+
+use crate::config::{Config, FrameOptions};
+
+const CLOUDFLARE_SCRIPT_SOURCE: &str = "https://static.cloudflareinsights.com";
+const CLOUDFLARE_CONNECT_SOURCE: &str = "https://cloudflareinsights.com";
+
+// Nonce allowing the scripts of the page to run, available to handlers as a request extension
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CspNonce(pub String);
+
+// Nonce to put on the scripts of the page, empty when the middleware doesn't run
+pub fn nonce(request: &HttpRequest) -> String {
+    request
+        .extensions()
+        .get::<CspNonce>()
+        .map(|n| n.0.clone())
+        .unwrap_or_default()
+}
+
+fn new_nonce() -> String {
+    let mut bytes = [0u8; 16];
+    OsRng.fill_bytes(&mut bytes);
+    hex::encode(bytes)
+}
+
+// Scripts only run from the site itself or with the nonce of the page, plus the Cloudflare
+// beacon when analytics are enabled. Alpine evaluates its directives with the Function
+// constructor, hence 'unsafe-eval'. Styles allow inline attributes, which the templates use.
+pub fn content_security_policy(config: &Config, nonce: &str) -> String {
+    let (script_extra, connect_extra) = match config.cloudflare_code {
+        Some(_) => (
+            format!(" {}", CLOUDFLARE_SCRIPT_SOURCE),
+            format!(" {}", CLOUDFLARE_CONNECT_SOURCE),
+        ),
+        None => (String::new(), String::new()),
+    };
+    let frame_ancestors = match config.frame_options {
+        FrameOptions::Deny => "'none'",
+        FrameOptions::SameOrigin => "'self'",
+    };
+    [
+        "default-src 'self'".to_string(),
+        format!(
+            "script-src 'self' 'nonce-{}' 'unsafe-eval'{}",
+            nonce, script_extra
+        ),
+        "style-src 'self' 'unsafe-inline'".to_string(),
+        "img-src 'self' data:".to_string(),
+        format!("connect-src 'self'{}", connect_extra),
+        "object-src 'none'".to_string(),
+        "base-uri 'self'".to_string(),
+        "form-action 'self'".to_string(),
+        format!("frame-ancestors {}", frame_ancestors),
+    ]
+    .join("; ")
+}
+
+// Middleware setting the security headers of every response. The CSP nonce is made per
+// request and handed to the templates through `nonce`.
+pub async fn security_headers(
+    request: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let config = request
+        .app_data::<web::Data<Config>>()
+        .cloned()
+        .ok_or_else(|| actix_web::error::ErrorInternalServerError("Config is not available"))?;
+    let nonce = new_nonce();
+    request.extensions_mut().insert(CspNonce(nonce.clone()));
+
+    let mut response = next.call(request).await?;
+    let csp_header = if config.csp_report_only {
+        header::CONTENT_SECURITY_POLICY_REPORT_ONLY
+    } else {
+        header::CONTENT_SECURITY_POLICY
+    };
+    let frame_options = match config.frame_options {
+        FrameOptions::Deny => "DENY",
+        FrameOptions::SameOrigin => "SAMEORIGIN",
+    };
+
+    let mut headers = vec![
+        (csp_header, content_security_policy(&config, &nonce)),
+        (header::X_CONTENT_TYPE_OPTIONS, "nosniff".to_string()),
+        (header::X_FRAME_OPTIONS, frame_options.to_string()),
+        (header::REFERRER_POLICY, config.referrer_policy.clone()),
+    ];
+    if config.hsts_max_age > 0 {
+        headers.push((
+            header::STRICT_TRANSPORT_SECURITY,
+            format!("max-age={}; includeSubDomains", config.hsts_max_age),
+        ));
+    }
+    for (name, value) in headers {
+        set_header(&mut response, name, &value);
+    }
+    Ok(response)
+}
+
+fn set_header<B>(response: &mut ServiceResponse<B>, name: HeaderName, value: &str) {
+    match HeaderValue::from_str(value) {
+        Ok(value) => {
+            response.headers_mut().insert(name, value);
+        }
+        Err(_) => tracing::warn!(header = %name, "invalid security header value"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::test_config;
+    use actix_web::{App, HttpResponse, get, middleware::from_fn, test};
+
+    #[get("/page")]
+    async fn page(request: HttpRequest) -> HttpResponse {
+        HttpResponse::Ok().body(nonce(&request))
+    }
+
+    fn header_value(resp: &ServiceResponse<impl MessageBody>, name: HeaderName) -> String {
+        resp.headers()
+            .get(name)
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .to_string()
+    }
+
+    #[actix_web::test]
+    async fn test_security_headers() {
+        let app = test::init_service(
+            App::new()
+                .wrap(from_fn(security_headers))
+                .app_data(web::Data::new(test_config()))
+                .service(page),
+        )
+        .await;
+
+        let resp =
+            test::call_service(&app, test::TestRequest::get().uri("/page").to_request()).await;
+        let csp = header_value(&resp, header::CONTENT_SECURITY_POLICY);
+        assert_eq!(
+            header_value(&resp, header::X_CONTENT_TYPE_OPTIONS),
+            "nosniff"
+        );
+        assert_eq!(header_value(&resp, header::X_FRAME_OPTIONS), "DENY");
+        assert_eq!(
+            header_value(&resp, header::REFERRER_POLICY),
+            "strict-origin-when-cross-origin"
+        );
+        assert_eq!(
+            header_value(&resp, header::STRICT_TRANSPORT_SECURITY),
+            "max-age=31536000; includeSubDomains"
+        );
+        assert!(csp.contains("frame-ancestors 'none'"));
+        assert!(csp.contains("object-src 'none'"));
+        assert!(!csp.contains("cloudflareinsights"));
+
+        // The nonce of the page is the one allowed by its policy, and changes every request
+        let nonce = String::from_utf8(test::read_body(resp).await.to_vec()).unwrap();
+        assert!(csp.contains(&format!("'nonce-{}'", nonce)));
+        let resp =
+            test::call_service(&app, test::TestRequest::get().uri("/page").to_request()).await;
+        assert!(!header_value(&resp, header::CONTENT_SECURITY_POLICY).contains(&nonce));
+    }
+
+    #[actix_web::test]
+    async fn test_configured_headers() {
+        let mut config = test_config();
+        config.cloudflare_code = Some("token".to_string());
+        config.frame_options = FrameOptions::SameOrigin;
+        config.hsts_max_age = 0;
+        config.csp_report_only = true;
+        let app = test::init_service(
+            App::new()
+                .wrap(from_fn(security_headers))
+                .app_data(web::Data::new(config))
+                .service(page),
+        )
+        .await;
+
+        let resp =
+            test::call_service(&app, test::TestRequest::get().uri("/page").to_request()).await;
+        assert!(!resp.headers().contains_key(header::CONTENT_SECURITY_POLICY));
+        assert!(
+            !resp
+                .headers()
+                .contains_key(header::STRICT_TRANSPORT_SECURITY)
+        );
+        assert_eq!(header_value(&resp, header::X_FRAME_OPTIONS), "SAMEORIGIN");
+        let csp = header_value(&resp, header::CONTENT_SECURITY_POLICY_REPORT_ONLY);
+        assert!(csp.contains(CLOUDFLARE_SCRIPT_SOURCE));
+        assert!(csp.contains(&format!("connect-src 'self' {}", CLOUDFLARE_CONNECT_SOURCE)));
+        assert!(csp.contains("frame-ancestors 'self'"));
+    }
+}
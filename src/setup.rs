@@ -1,5 +1,6 @@
 use sqlx::SqlitePool;
 
+use crate::leaderboard;
 use crate::models::Block;
 use crate::utils;
 
@@ -42,23 +43,19 @@ pub async fn run_setup(db: &SqlitePool) -> std::io::Result<()> {
         .await
         .expect("Failed to run migrations");
 
-    let blocks = Block::find_all(db, false, None, None)
+    // The chain itself is verified by the caller, which keeps serving while it is invalid
+    let is_empty = Block::find_tip(db)
         .await
-        .expect("Unable to fetch blocks");
-
-    if let Some(invalid_block) = blocks.iter().find(|b| !b.is_valid()) {
-        panic!(
-            "Invalid block found in database: {:?} at height {}",
-            invalid_block.hash, invalid_block.height
-        );
-    } else {
-        println!("No invalid blocks found in database.");
-    }
-
-    if blocks.is_empty() {
+        .expect("Unable to fetch the tip")
+        .is_none();
+    if is_empty {
         create_genesis_block(db).await?;
-        println!("Created genesis block");
+        tracing::info!("created genesis block");
     }
 
+    leaderboard::refresh(db)
+        .await
+        .expect("Failed to refresh leaderboard");
+
     Ok(())
 }
@@ -0,0 +1,253 @@
+use chrono::NaiveDateTime;
+use std::collections::{BTreeSet, HashSet};
+use std::fmt::Write;
+
+use crate::models::Block;
+use crate::utils::{escape_xml, start_of_week};
+
+const CHART_WIDTH: f64 = 640.0;
+const CHART_HEIGHT: f64 = 240.0;
+const CHART_MARGIN: f64 = 36.0;
+const POINT_RADIUS: f64 = 4.0;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct UserStats {
+    pub block_count: usize,
+    // Move counts of single solutions, leaving out the means of challenge blocks
+    pub mean_moves: Option<f64>,
+    pub best_moves: Option<u8>,
+    pub worst_moves: Option<u8>,
+    // Best rounded mean of the mean of 3 challenge blocks
+    pub best_mean: Option<u8>,
+    pub main_chain_count: usize,
+    pub longest_round_streak: usize,
+}
+
+impl UserStats {
+    // Compute the statistics of the blocks of a single name
+    pub fn from_blocks(blocks: &[Block], main_chain_hashes: &HashSet<String>) -> Self {
+        let (singles, challenges): (Vec<&Block>, Vec<&Block>) =
+            blocks.iter().partition(|b| b.mean_of.is_none());
+        let moves = singles.iter().map(|b| b.solution_moves);
+        let mean_moves = (!singles.is_empty())
+            .then(|| moves.clone().map(|m| m as f64).sum::<f64>() / singles.len() as f64);
+        let rounds = blocks
+            .iter()
+            .filter_map(|b| b.created_at)
+            .map(start_of_week)
+            .collect::<BTreeSet<_>>();
+
+        UserStats {
+            block_count: blocks.len(),
+            mean_moves,
+            best_moves: moves.clone().min(),
+            worst_moves: moves.max(),
+            best_mean: challenges.iter().map(|b| b.solution_moves).min(),
+            main_chain_count: blocks
+                .iter()
+                .filter(|b| main_chain_hashes.contains(&b.hash))
+                .count(),
+            longest_round_streak: longest_streak(&rounds),
+        }
+    }
+
+    pub fn mean_moves_display(&self) -> String {
+        self.mean_moves
+            .map(|m| format!("{:.2}", m))
+            .unwrap_or_else(|| "-".to_string())
+    }
+}
+
+// Longest run of rounds (weeks) that directly follow each other
+fn longest_streak(rounds: &BTreeSet<NaiveDateTime>) -> usize {
+    let mut longest = 0;
+    let mut current = 0;
+    let mut previous: Option<NaiveDateTime> = None;
+
+    for &round in rounds {
+        current = match previous {
+            Some(p) if round - p == chrono::Duration::weeks(1) => current + 1,
+            _ => 1,
+        };
+        longest = longest.max(current);
+        previous = Some(round);
+    }
+
+    longest
+}
+
+// Plot the move count of every block over time, linking each point to its block
+pub fn render_moves_chart(blocks: &[Block], main_chain_hashes: &HashSet<String>) -> String {
+    let points = blocks
+        .iter()
+        .filter_map(|b| b.created_at.map(|t| (t, b)))
+        .collect::<Vec<_>>();
+
+    let mut svg = String::new();
+    let _ = write!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" class="moves-chart" width="{CHART_WIDTH}" height="{CHART_HEIGHT}" viewBox="0 0 {CHART_WIDTH} {CHART_HEIGHT}" font-family="sans-serif" font-size="10">"#
+    );
+    let _ = write!(
+        svg,
+        r##"<line x1="{m}" y1="{bottom}" x2="{right}" y2="{bottom}" stroke="#888888"/><line x1="{m}" y1="{m}" x2="{m}" y2="{bottom}" stroke="#888888"/>"##,
+        m = CHART_MARGIN,
+        bottom = CHART_HEIGHT - CHART_MARGIN,
+        right = CHART_WIDTH - CHART_MARGIN,
+    );
+
+    let (Some(first), Some(last)) = (
+        points.iter().map(|(t, _)| *t).min(),
+        points.iter().map(|(t, _)| *t).max(),
+    ) else {
+        svg.push_str("</svg>");
+        return svg;
+    };
+    let min_moves = points
+        .iter()
+        .map(|(_, b)| b.solution_moves)
+        .min()
+        .unwrap_or(0);
+    let max_moves = points
+        .iter()
+        .map(|(_, b)| b.solution_moves)
+        .max()
+        .unwrap_or(0);
+
+    let time_span = (last - first).num_seconds().max(1) as f64;
+    let move_span = (max_moves - min_moves).max(1) as f64;
+    let plot_width = CHART_WIDTH - 2.0 * CHART_MARGIN;
+    let plot_height = CHART_HEIGHT - 2.0 * CHART_MARGIN;
+    let position = |time: NaiveDateTime, moves: u8| {
+        let x = if first == last {
+            CHART_MARGIN + plot_width / 2.0
+        } else {
+            CHART_MARGIN + (time - first).num_seconds() as f64 / time_span * plot_width
+        };
+        let y = CHART_HEIGHT - CHART_MARGIN - (moves - min_moves) as f64 / move_span * plot_height;
+        (x, y)
+    };
+
+    // Axis labels: move range on the left, time range below
+    let _ = write!(
+        svg,
+        r##"<text x="{x}" y="{y_max:.1}" text-anchor="end" fill="#888888">{max_moves}</text><text x="{x}" y="{y_min:.1}" text-anchor="end" fill="#888888">{min_moves}</text>"##,
+        x = CHART_MARGIN - 6.0,
+        y_max = CHART_MARGIN + 3.5,
+        y_min = CHART_HEIGHT - CHART_MARGIN + 3.5,
+    );
+    let _ = write!(
+        svg,
+        r##"<text x="{left}" y="{y}" fill="#888888">{first}</text><text x="{right}" y="{y}" text-anchor="end" fill="#888888">{last}</text>"##,
+        left = CHART_MARGIN,
+        right = CHART_WIDTH - CHART_MARGIN,
+        y = CHART_HEIGHT - CHART_MARGIN + 16.0,
+        first = first.format("%Y-%m-%d"),
+        last = last.format("%Y-%m-%d"),
+    );
+
+    let line = points
+        .iter()
+        .map(|(t, b)| {
+            let (x, y) = position(*t, b.solution_moves);
+            format!("{:.1},{:.1}", x, y)
+        })
+        .collect::<Vec<_>>()
+        .join(" ");
+    let _ = write!(
+        svg,
+        r##"<polyline points="{line}" fill="none" stroke="#888888" stroke-width="1"/>"##
+    );
+
+    for (time, block) in &points {
+        let (x, y) = position(*time, block.solution_moves);
+        let fill = if main_chain_hashes.contains(&block.hash) {
+            "#4f80f0"
+        } else {
+            "#52a87b"
+        };
+        let _ = write!(
+            svg,
+            r#"<a href="/b/{hash}"><title>{moves} moves on {time}</title><circle cx="{x:.1}" cy="{y:.1}" r="{POINT_RADIUS}" fill="{fill}"/></a>"#,
+            hash = escape_xml(&block.hash),
+            moves = block.solution_moves,
+            time = time.format("%Y-%m-%d %H:%M"),
+        );
+    }
+
+    svg.push_str("</svg>");
+    svg
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::test_block;
+
+    fn block(hash: &str, moves: u8, created_at: &str) -> Block {
+        Block {
+            parent_hash: Some("parent".to_string()),
+            height: 1,
+            name: "Alice".to_string(),
+            solution_moves: moves,
+            created_at: Some(
+                NaiveDateTime::parse_from_str(created_at, "%Y-%m-%d %H:%M:%S").unwrap(),
+            ),
+            ..test_block(hash)
+        }
+    }
+
+    #[test]
+    fn test_user_stats() {
+        // 2025-01-06, 2025-01-13 and 2025-01-27 are Mondays
+        let blocks = vec![
+            block("a", 30, "2025-01-06 10:00:00"),
+            block("b", 24, "2025-01-12 23:59:59"),
+            block("c", 27, "2025-01-13 00:00:00"),
+            block("d", 21, "2025-01-27 12:00:00"),
+        ];
+        let main_chain = HashSet::from(["b".to_string(), "d".to_string()]);
+        let stats = UserStats::from_blocks(&blocks, &main_chain);
+
+        assert_eq!(stats.block_count, 4);
+        assert_eq!(stats.mean_moves, Some(25.5));
+        assert_eq!(stats.mean_moves_display(), "25.50");
+        assert_eq!(stats.best_moves, Some(21));
+        assert_eq!(stats.worst_moves, Some(30));
+        assert_eq!(stats.main_chain_count, 2);
+        assert_eq!(stats.longest_round_streak, 2);
+        assert_eq!(stats.best_mean, None);
+
+        // Means of challenges are reported apart from the single solutions
+        let mut with_challenge = blocks.clone();
+        with_challenge.push(Block {
+            mean_of: Some(3),
+            ..block("e", 19, "2025-02-03 12:00:00")
+        });
+        let stats = UserStats::from_blocks(&with_challenge, &main_chain);
+        assert_eq!(stats.block_count, 5);
+        assert_eq!(stats.mean_moves, Some(25.5));
+        assert_eq!(stats.best_moves, Some(21));
+        assert_eq!(stats.best_mean, Some(19));
+
+        let empty = UserStats::from_blocks(&[], &main_chain);
+        assert_eq!(empty.block_count, 0);
+        assert_eq!(empty.mean_moves_display(), "-");
+        assert_eq!(empty.longest_round_streak, 0);
+    }
+
+    #[test]
+    fn test_render_moves_chart() {
+        let blocks = vec![
+            block("a", 30, "2025-01-06 10:00:00"),
+            block("b\"", 24, "2025-01-13 10:00:00"),
+        ];
+        let svg = render_moves_chart(&blocks, &HashSet::new());
+
+        assert!(svg.starts_with("<svg"));
+        assert_eq!(svg.matches("<circle").count(), 2);
+        assert!(svg.contains(r#"href="/b/b&quot;""#));
+        assert!(svg.contains("2025-01-06"));
+        assert!(render_moves_chart(&[], &HashSet::new()).ends_with("</svg>"));
+    }
+}
@@ -0,0 +1,136 @@
+use actix_web::HttpMessage;
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{HeaderName, HeaderValue};
+use actix_web::middleware::Next;
+use rand_core::{OsRng, RngCore};
+use std::time::Instant;
+use tracing::Instrument;
+use tracing_subscriber::EnvFilter;
+
+use crate::config::LogFormat;
+
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+// Id of the request being handled, available to handlers as a request extension
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RequestId(pub String);
+
+// Log to stdout, as JSON lines or as text. The level comes from RUST_LOG (e.g.
+// "info,fm_chain=debug" to see the database spans), and `log` records from libraries are
+// forwarded too.
+pub fn init(format: LogFormat) {
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
+    let subscriber = tracing_subscriber::fmt().with_env_filter(filter);
+    match format {
+        LogFormat::Json => subscriber
+            .json()
+            .flatten_event(true)
+            .with_current_span(true)
+            .with_span_list(false)
+            .init(),
+        LogFormat::Text => subscriber.init(),
+    }
+}
+
+// Ids from upstream proxies are kept if they look sane, so they can't inject into the logs
+fn is_valid_request_id(id: &str) -> bool {
+    !id.is_empty()
+        && id.len() <= 64
+        && id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+fn new_request_id() -> String {
+    let mut bytes = [0u8; 16];
+    OsRng.fill_bytes(&mut bytes);
+    hex::encode(bytes)
+}
+
+// Middleware giving every request an id, taken from the X-Request-Id header or generated. It
+// runs the request in a span carrying the id, logs its outcome and echoes the id back.
+pub async fn request_id(
+    request: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let request_id = request
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|h| h.to_str().ok())
+        .filter(|id| is_valid_request_id(id))
+        .map_or_else(new_request_id, str::to_string);
+    request
+        .extensions_mut()
+        .insert(RequestId(request_id.clone()));
+
+    let span = tracing::info_span!(
+        "request",
+        request_id = %request_id,
+        method = %request.method(),
+        path = %request.path(),
+    );
+    let start = Instant::now();
+    let mut response = next.call(request).instrument(span.clone()).await?;
+
+    span.in_scope(|| {
+        tracing::info!(
+            status = response.status().as_u16(),
+            latency_ms = start.elapsed().as_secs_f64() * 1000.0,
+            "request finished"
+        )
+    });
+    if let Ok(value) = HeaderValue::from_str(&request_id) {
+        response
+            .headers_mut()
+            .insert(HeaderName::from_static(REQUEST_ID_HEADER), value);
+    }
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{App, HttpRequest, HttpResponse, get, middleware::from_fn, test};
+
+    #[get("/id")]
+    async fn echo_id(request: HttpRequest) -> HttpResponse {
+        let id = request.extensions().get::<RequestId>().cloned();
+        HttpResponse::Ok().body(id.map(|id| id.0).unwrap_or_default())
+    }
+
+    #[actix_web::test]
+    async fn test_request_id() {
+        let app = test::init_service(App::new().wrap(from_fn(request_id)).service(echo_id)).await;
+
+        // Upstream ids are propagated
+        let resp = test::call_service(
+            &app,
+            test::TestRequest::get()
+                .uri("/id")
+                .insert_header((REQUEST_ID_HEADER, "abc-123"))
+                .to_request(),
+        )
+        .await;
+        assert_eq!(resp.headers().get(REQUEST_ID_HEADER).unwrap(), "abc-123");
+        assert_eq!(test::read_body(resp).await, "abc-123");
+
+        // Missing or suspicious ids are replaced
+        let resp = test::call_service(
+            &app,
+            test::TestRequest::get()
+                .uri("/id")
+                .insert_header((REQUEST_ID_HEADER, "evil\" id"))
+                .to_request(),
+        )
+        .await;
+        let id = resp
+            .headers()
+            .get(REQUEST_ID_HEADER)
+            .unwrap()
+            .to_str()
+            .unwrap();
+        assert_eq!(id.len(), 32);
+        assert!(is_valid_request_id(id));
+    }
+}
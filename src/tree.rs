@@ -0,0 +1,247 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write;
+
+use crate::models::Block;
+use crate::utils::escape_xml;
+
+const NODE_RADIUS: f64 = 14.0;
+const HORIZONTAL_SPACING: f64 = 48.0;
+const VERTICAL_SPACING: f64 = 64.0;
+const MARGIN: f64 = 32.0;
+
+#[derive(Debug, Clone)]
+pub struct TreeNode {
+    pub hash: String,
+    pub short_hash: String,
+    pub name: String,
+    pub height: i64,
+    pub solution_moves: u8,
+    pub main_chain: bool,
+    pub parent: Option<usize>,
+    pub depth: usize,
+    pub x: f64,
+}
+
+#[derive(Debug)]
+pub struct TreeLayout {
+    pub nodes: Vec<TreeNode>,
+    pub columns: f64,
+    pub rows: usize,
+}
+
+// Lay out the blocks as a tidy tree: leaves take consecutive columns and every parent is
+// centred above its children. Blocks whose parent is not part of `blocks` become roots.
+pub fn layout(blocks: &[Block], main_chain_hashes: &HashSet<String>) -> TreeLayout {
+    let min_height = blocks.iter().map(|b| b.height).min().unwrap_or(0);
+    let index: HashMap<&str, usize> = blocks
+        .iter()
+        .enumerate()
+        .map(|(i, b)| (b.hash.as_str(), i))
+        .collect();
+
+    let mut nodes: Vec<TreeNode> = blocks
+        .iter()
+        .map(|b| TreeNode {
+            hash: b.hash.clone(),
+            short_hash: b.short_hash(),
+            name: b.name.clone(),
+            height: b.height,
+            solution_moves: b.solution_moves,
+            main_chain: main_chain_hashes.contains(&b.hash),
+            parent: b.parent_hash.as_deref().and_then(|h| index.get(h).copied()),
+            depth: (b.height - min_height) as usize,
+            x: 0.0,
+        })
+        .collect();
+
+    let mut children: Vec<Vec<usize>> = vec![Vec::new(); nodes.len()];
+    let mut roots = Vec::new();
+    for (i, node) in nodes.iter().enumerate() {
+        match node.parent {
+            Some(parent) => children[parent].push(i),
+            None => roots.push(i),
+        }
+    }
+
+    // Main chain first, then the most efficient solutions
+    let sort_key = |node: &TreeNode| (!node.main_chain, node.solution_moves, node.hash.clone());
+    for list in children.iter_mut() {
+        list.sort_by_key(|&i| sort_key(&nodes[i]));
+    }
+    roots.sort_by_key(|&i| sort_key(&nodes[i]));
+
+    let mut next_column = 0.0;
+    for root in roots {
+        // Iterative post-order traversal, so deep chains don't overflow the stack
+        let mut stack = vec![(root, false)];
+        while let Some((i, visited)) = stack.pop() {
+            if visited {
+                nodes[i].x = match (children[i].first(), children[i].last()) {
+                    (Some(&first), Some(&last)) => (nodes[first].x + nodes[last].x) / 2.0,
+                    _ => {
+                        next_column += 1.0;
+                        next_column - 1.0
+                    }
+                };
+            } else {
+                stack.push((i, true));
+                stack.extend(children[i].iter().rev().map(|&c| (c, false)));
+            }
+        }
+    }
+
+    let rows = nodes.iter().map(|n| n.depth + 1).max().unwrap_or(0);
+    TreeLayout {
+        nodes,
+        columns: next_column,
+        rows,
+    }
+}
+
+// Colour a node from green (fewest moves) to red (most moves) within the visible range
+fn move_colour(moves: u8, min: u8, max: u8) -> String {
+    let ratio = if max > min {
+        (moves - min) as f64 / (max - min) as f64
+    } else {
+        0.0
+    };
+    let hue = 140.0 * (1.0 - ratio);
+    format!("hsl({:.0}, 65%, 50%)", hue)
+}
+
+pub fn render_svg(layout: &TreeLayout) -> String {
+    let position = |node: &TreeNode| {
+        (
+            MARGIN + node.x * HORIZONTAL_SPACING,
+            MARGIN + node.depth as f64 * VERTICAL_SPACING,
+        )
+    };
+    let width = 2.0 * MARGIN + (layout.columns - 1.0).max(0.0) * HORIZONTAL_SPACING;
+    let height = 2.0 * MARGIN + (layout.rows as f64 - 1.0).max(0.0) * VERTICAL_SPACING;
+
+    let moves = layout
+        .nodes
+        .iter()
+        .filter(|n| n.height > 0)
+        .map(|n| n.solution_moves);
+    let min_moves = moves.clone().min().unwrap_or(0);
+    let max_moves = moves.max().unwrap_or(0);
+
+    let mut svg = String::new();
+    let _ = write!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{width:.0}" height="{height:.0}" viewBox="0 0 {width:.0} {height:.0}" font-family="sans-serif" font-size="10">"#
+    );
+
+    for node in &layout.nodes {
+        if let Some(parent) = node.parent {
+            let (x1, y1) = position(&layout.nodes[parent]);
+            let (x2, y2) = position(node);
+            let (stroke, stroke_width) = if node.main_chain && layout.nodes[parent].main_chain {
+                ("#4f80f0", 3)
+            } else {
+                ("#888888", 1)
+            };
+            let _ = write!(
+                svg,
+                r#"<line x1="{x1:.1}" y1="{y1:.1}" x2="{x2:.1}" y2="{y2:.1}" stroke="{stroke}" stroke-width="{stroke_width}"/>"#
+            );
+        }
+    }
+
+    for node in &layout.nodes {
+        let (x, y) = position(node);
+        let fill = if node.height == 0 {
+            "#ef4444".to_string()
+        } else {
+            move_colour(node.solution_moves, min_moves, max_moves)
+        };
+        let stroke = if node.main_chain { "#4f80f0" } else { "none" };
+        let label = if node.height == 0 {
+            String::new()
+        } else {
+            node.solution_moves.to_string()
+        };
+        let _ = write!(
+            svg,
+            r##"<a href="/b/{hash}"><title>{short_hash} by {name} ({moves} moves, chain length {length})</title><circle cx="{x:.1}" cy="{y:.1}" r="{NODE_RADIUS}" fill="{fill}" stroke="{stroke}" stroke-width="3"/><text x="{x:.1}" y="{text_y:.1}" text-anchor="middle" fill="#ffffff">{label}</text></a>"##,
+            hash = escape_xml(&node.hash),
+            short_hash = escape_xml(&node.short_hash),
+            name = escape_xml(&node.name),
+            moves = node.solution_moves,
+            length = node.height + 1,
+            text_y = y + 3.5,
+        );
+    }
+
+    svg.push_str("</svg>");
+    svg
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::test_block;
+
+    fn block(hash: &str, parent_hash: Option<&str>, height: i64, moves: u8) -> Block {
+        Block {
+            parent_hash: parent_hash.map(|h| h.to_string()),
+            height,
+            name: "<test>".to_string(),
+            solution_moves: moves,
+            ..test_block(hash)
+        }
+    }
+
+    #[test]
+    fn test_layout() {
+        let blocks = vec![
+            block("genesis", None, 0, 10),
+            block("a", Some("genesis"), 1, 30),
+            block("b", Some("genesis"), 1, 25),
+            block("c", Some("b"), 2, 20),
+            block("d", Some("b"), 2, 22),
+        ];
+        let main_chain = HashSet::from(["genesis".to_string(), "b".to_string(), "c".to_string()]);
+        let layout = layout(&blocks, &main_chain);
+        let x = |hash: &str| layout.nodes.iter().find(|n| n.hash == hash).unwrap().x;
+
+        assert_eq!(layout.columns, 3.0);
+        assert_eq!(layout.rows, 3);
+        assert_eq!(x("c"), 0.0);
+        assert_eq!(x("d"), 1.0);
+        assert_eq!(x("b"), 0.5);
+        assert_eq!(x("a"), 2.0);
+        assert_eq!(x("genesis"), 1.25);
+    }
+
+    #[test]
+    fn test_layout_window_roots() {
+        let blocks = vec![
+            block("c", Some("b"), 2, 20),
+            block("d", Some("b"), 2, 22),
+            block("e", Some("c"), 3, 21),
+        ];
+        let layout = layout(&blocks, &HashSet::new());
+
+        assert!(layout.nodes.iter().all(|n| n.depth <= 1));
+        assert_eq!(
+            layout.nodes.iter().filter(|n| n.parent.is_none()).count(),
+            2
+        );
+    }
+
+    #[test]
+    fn test_render_svg_escapes_names() {
+        let blocks = vec![
+            block("genesis", None, 0, 10),
+            block("a", Some("genesis"), 1, 30),
+        ];
+        let svg = render_svg(&layout(&blocks, &HashSet::new()));
+
+        assert!(svg.starts_with("<svg"));
+        assert!(svg.contains(r#"href="/b/a""#));
+        assert!(svg.contains("&lt;test&gt;"));
+        assert!(!svg.contains("<test>"));
+    }
+}
@@ -1,7 +1,9 @@
+use chrono::{Datelike, NaiveDateTime, Weekday};
 use rubiks_moves::moves::Algorithm;
 use sha2::{Digest, Sha256};
 
 use crate::cube::Move;
+use crate::metrics;
 
 pub fn is_htmx_request(request: &actix_web::HttpRequest) -> bool {
     request
@@ -10,6 +12,46 @@ pub fn is_htmx_request(request: &actix_web::HttpRequest) -> bool {
         .is_some_and(|h| h == "true")
 }
 
+// Returns midnight of the Monday in the same week as `now`
+pub fn start_of_week(now: NaiveDateTime) -> NaiveDateTime {
+    let today = now.date();
+    let days_since_monday = match today.weekday() {
+        Weekday::Mon => 0,
+        Weekday::Tue => 1,
+        Weekday::Wed => 2,
+        Weekday::Thu => 3,
+        Weekday::Fri => 4,
+        Weekday::Sat => 5,
+        Weekday::Sun => 6,
+    };
+
+    let start_of_week_date = today - chrono::Duration::days(days_since_monday);
+    start_of_week_date
+        .and_hms_opt(0, 0, 0)
+        .expect("Failed to get start of week")
+}
+
+pub fn escape_xml(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+// Scheme and host the request was made to, e.g. "https://example.com"
+pub fn base_url(request: &actix_web::HttpRequest) -> String {
+    let info = request.connection_info();
+    format!("{}://{}", info.scheme(), info.host())
+}
+
 pub fn parse_moves(s: &str) -> Vec<Move> {
     let mut moves = Vec::new();
     let mut chars = s.chars().peekable();
@@ -42,6 +84,81 @@ pub fn parse_moves(s: &str) -> Vec<Move> {
     moves
 }
 
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NotationError {
+    pub position: usize,
+    pub found: char,
+}
+
+// Parse moves, rejecting anything that is not standard outer-layer notation
+pub fn parse_moves_strict(s: &str) -> Result<Vec<Move>, NotationError> {
+    let mut moves = Vec::new();
+    let mut chars = s.chars().enumerate().peekable();
+
+    while let Some((position, c)) = chars.next() {
+        let face: fn(u8) -> Move = match c {
+            'U' => Move::U,
+            'D' => Move::D,
+            'L' => Move::L,
+            'R' => Move::R,
+            'F' => Move::F,
+            'B' => Move::B,
+            _ if c.is_whitespace() => continue,
+            _ => return Err(NotationError { position, found: c }),
+        };
+
+        let count = match chars.peek() {
+            Some((_, '2')) => {
+                chars.next();
+                2
+            }
+            Some((_, '\'')) => {
+                chars.next();
+                3
+            }
+            _ => 1,
+        };
+
+        moves.push(face(count));
+    }
+
+    Ok(moves)
+}
+
+// Parse step annotations from a solution description, written either as "EO: F R" or "F R // EO"
+pub fn parse_step_annotations(description: &str) -> Vec<(String, Vec<Move>)> {
+    description
+        .lines()
+        .filter_map(|line| {
+            let (label, moves) = match line.split_once("//") {
+                Some((moves, label)) => (label, moves),
+                None => line.split_once(':')?,
+            };
+            let label = label.trim();
+            let moves = parse_moves_strict(moves).ok()?;
+            if label.is_empty() || label.chars().count() > 32 || moves.is_empty() {
+                return None;
+            }
+            Some((label.to_string(), moves))
+        })
+        .collect()
+}
+
+// Number of solution moves after which each annotated step ends. Annotations only count as long
+// as they line up with the solution itself.
+pub fn step_ends(solution: &[Move], annotations: &[(String, Vec<Move>)]) -> Vec<(usize, String)> {
+    let mut ends = Vec::new();
+    let mut position = 0;
+    for (label, moves) in annotations {
+        if !solution[position..].starts_with(moves) {
+            break;
+        }
+        position += moves.len();
+        ends.push((position, label.clone()));
+    }
+    ends
+}
+
 pub fn format_data(parent_hash: &str, name: &str, message: &str) -> Vec<u8> {
     format!("{}|{}|{}", parent_hash, name, message)
         .as_bytes()
@@ -150,7 +267,9 @@ pub fn format_moves(moves: &[Move]) -> String {
     formatted
 }
 
+#[tracing::instrument(level = "debug", skip_all, fields(solution_moves = solution.len()))]
 pub fn verify_solution(scramble: &[Move], solution: &[Move]) -> bool {
+    let _timer = metrics::VERIFICATION_DURATION.start_timer();
     let scramble = Algorithm::from(&format_moves(scramble)).unwrap();
     let solution = Algorithm::from(&format_moves(solution)).unwrap();
     solution.solves(&scramble)
@@ -181,6 +300,67 @@ mod tests {
         assert_eq!(scramble, expected);
     }
 
+    #[test]
+    fn test_parse_moves_strict() {
+        assert_eq!(
+            parse_moves_strict("R U2  F'\nD"),
+            Ok(vec![Move::R(1), Move::U(2), Move::F(3), Move::D(1)])
+        );
+        assert_eq!(parse_moves_strict(""), Ok(vec![]));
+        assert_eq!(
+            parse_moves_strict("R Uw"),
+            Err(NotationError {
+                position: 3,
+                found: 'w'
+            })
+        );
+        assert_eq!(
+            parse_moves_strict("R x'"),
+            Err(NotationError {
+                position: 2,
+                found: 'x'
+            })
+        );
+        assert_eq!(parse_moves_strict("RU'"), Ok(vec![Move::R(1), Move::U(3)]));
+        assert_eq!(
+            parse_moves_strict("R2'"),
+            Err(NotationError {
+                position: 2,
+                found: '\''
+            })
+        );
+    }
+
+    #[test]
+    fn test_step_annotations() {
+        let description = "Nice one!\nEO: F R\nDR: U2 D'\nL2 B // Finish\nR: no moves here";
+        let annotations = parse_step_annotations(description);
+        assert_eq!(
+            annotations,
+            vec![
+                ("EO".to_string(), vec![Move::F(1), Move::R(1)]),
+                ("DR".to_string(), vec![Move::U(2), Move::D(3)]),
+                ("Finish".to_string(), vec![Move::L(2), Move::B(1)]),
+            ]
+        );
+
+        let solution = parse_moves("F R U2 D' L2 B");
+        assert_eq!(
+            step_ends(&solution, &annotations),
+            vec![
+                (2, "EO".to_string()),
+                (4, "DR".to_string()),
+                (6, "Finish".to_string())
+            ]
+        );
+
+        let solution = parse_moves("F R U2 D L2 B");
+        assert_eq!(
+            step_ends(&solution, &annotations),
+            vec![(2, "EO".to_string())]
+        );
+    }
+
     #[test]
     fn test_verify_solution_valid() {
         let scramble_hash = "0123456789ABCDEF";
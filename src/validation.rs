@@ -0,0 +1,201 @@
+use unicode_normalization::UnicodeNormalization;
+
+use crate::api::ApiError;
+use crate::config::Config;
+
+// First block version whose name and message follow the rules below. Older blocks were
+// hashed from the text exactly as it was submitted, and keep verifying that way.
+pub const NORMALIZED_VERSION: u8 = 3;
+
+// Maximum lengths of the free text fields of a block, in characters
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FieldLimits {
+    pub name: usize,
+    pub message: usize,
+    pub solution_description: usize,
+}
+
+impl FieldLimits {
+    pub fn from_config(config: &Config) -> Self {
+        FieldLimits {
+            name: config.max_name_length,
+            message: config.max_message_length,
+            solution_description: config.max_description_length,
+        }
+    }
+}
+
+// Free text of a submitted block, normalised and checked
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlockText {
+    pub name: String,
+    pub message: String,
+    pub solution_description: String,
+}
+
+impl BlockText {
+    pub fn parse(
+        name: &str,
+        message: &str,
+        solution_description: &str,
+        limits: &FieldLimits,
+    ) -> Result<Self, ApiError> {
+        Ok(BlockText {
+            name: check_field("name", name, limits.name, false)?,
+            message: check_field("message", message, limits.message, true)?,
+            solution_description: check_field(
+                "solution_description",
+                solution_description,
+                limits.solution_description,
+                true,
+            )?,
+        })
+    }
+}
+
+// Browsers submit line breaks as "\r\n", which are stored as "\n". Text is then put in NFC,
+// so the same characters always give the same hash however they were typed.
+pub fn normalize(value: &str) -> String {
+    value.replace("\r\n", "\n").nfc().collect()
+}
+
+// Embeddings, overrides and isolates of the bidirectional algorithm, and the invisible marks
+// setting the direction of the text around them. They can make a name or a message display
+// as something other than what was hashed.
+fn is_bidi_control(c: char) -> bool {
+    matches!(
+        c,
+        '\u{061C}' | '\u{200E}' | '\u{200F}' | '\u{202A}'..='\u{202E}' | '\u{2066}'..='\u{2069}'
+    )
+}
+
+// Names fit on a single line, messages and descriptions can use line breaks and tabs
+fn is_allowed(c: char, multiline: bool) -> bool {
+    if c.is_control() {
+        return multiline && (c == '\n' || c == '\t');
+    }
+    !is_bidi_control(c)
+}
+
+// Normalise a field and check it against the rules, explaining what is wrong with it
+pub fn check_field(
+    field: &str,
+    value: &str,
+    max_length: usize,
+    multiline: bool,
+) -> Result<String, ApiError> {
+    let label = field.replace('_', " ");
+    let invalid = |detail: String| ApiError::InvalidField {
+        field: field.to_string(),
+        detail,
+    };
+
+    let normalized = normalize(value);
+    if let Some(c) = normalized.chars().find(|&c| !is_allowed(c, multiline)) {
+        let detail = if is_bidi_control(c) {
+            format!(
+                "The {} contains a character changing the direction of the text (U+{:04X}), which could disguise what it says.",
+                label, c as u32
+            )
+        } else if c == '\n' {
+            format!("The {} must fit on a single line.", label)
+        } else {
+            format!(
+                "The {} contains a control character (U+{:04X}).",
+                label, c as u32
+            )
+        };
+        return Err(invalid(detail));
+    }
+    let length = normalized.chars().count();
+    if length > max_length {
+        return Err(invalid(format!(
+            "The {} is {} characters long, the maximum is {}.",
+            label, length, max_length
+        )));
+    }
+    Ok(normalized)
+}
+
+// Whether the stored text of a block follows the rules of its version, so what is displayed
+// is what was hashed. Lengths aren't checked, as the limits can change.
+pub fn follows_rules(version: u8, name: &str, message: &str) -> bool {
+    version < NORMALIZED_VERSION
+        || [(name, false), (message, true)]
+            .iter()
+            .all(|&(value, multiline)| {
+                normalize(value) == value && value.chars().all(|c| is_allowed(c, multiline))
+            })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LIMITS: FieldLimits = FieldLimits {
+        name: 8,
+        message: 20,
+        solution_description: 20,
+    };
+
+    fn error_detail(result: Result<BlockText, ApiError>) -> (String, String) {
+        match result {
+            Err(ApiError::InvalidField { field, detail }) => (field, detail),
+            other => panic!("Expected an invalid field, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_normalization() {
+        // "e" followed by a combining acute accent is composed into "é"
+        let text = BlockText::parse("Jose\u{301}", "Line 1\r\nLine 2", "R U\tR'", &LIMITS)
+            .expect("Text should be valid");
+        assert_eq!(text.name, "José");
+        assert_eq!(text.message, "Line 1\nLine 2");
+        assert_eq!(text.solution_description, "R U\tR'");
+
+        // Lengths count characters, after normalisation
+        assert!(BlockText::parse("Jose\u{301}phin", "", "", &LIMITS).is_ok());
+    }
+
+    #[test]
+    fn test_rejected_text() {
+        let (field, detail) =
+            error_detail(BlockText::parse("Alice", "Hi \u{202E}olleh", "", &LIMITS));
+        assert_eq!(field, "message");
+        assert!(detail.contains("U+202E"), "{}", detail);
+
+        let (field, detail) = error_detail(BlockText::parse("Al\u{2067}ice", "", "", &LIMITS));
+        assert_eq!(field, "name");
+        assert!(detail.contains("direction"), "{}", detail);
+
+        let (field, detail) = error_detail(BlockText::parse("Alice", "", "Bell\u{7}", &LIMITS));
+        assert_eq!(field, "solution_description");
+        assert_eq!(
+            detail,
+            "The solution description contains a control character (U+0007)."
+        );
+
+        let (field, detail) = error_detail(BlockText::parse("Ali\nce", "", "", &LIMITS));
+        assert_eq!(field, "name");
+        assert_eq!(detail, "The name must fit on a single line.");
+
+        let (field, detail) = error_detail(BlockText::parse("Alice", "A lone \r", "", &LIMITS));
+        assert_eq!(field, "message");
+        assert!(detail.contains("U+000D"), "{}", detail);
+
+        let (field, detail) = error_detail(BlockText::parse("Alexander", "", "", &LIMITS));
+        assert_eq!(field, "name");
+        assert_eq!(detail, "The name is 9 characters long, the maximum is 8.");
+    }
+
+    #[test]
+    fn test_follows_rules() {
+        assert!(follows_rules(3, "José", "Line 1\nLine 2"));
+        assert!(!follows_rules(3, "Jose\u{301}", "Message"));
+        assert!(!follows_rules(3, "Alice", "\u{202E}olleh"));
+        assert!(!follows_rules(3, "Alice", "Line 1\r\nLine 2"));
+        // Older versions were hashed as submitted
+        assert!(follows_rules(2, "Jose\u{301}", "\u{202E}olleh"));
+    }
+}
@@ -1,24 +1,63 @@
 use askama::Template;
-use std::collections::HashSet;
+use chrono::Utc;
+use std::collections::{HashMap, HashSet};
 
-use crate::models::Block;
+use crate::attempts::Attempt;
+use crate::challenges::{self, ChallengeAttempt};
+use crate::cube::{CubeState, Face};
+use crate::identity::Bindings;
+use crate::leaderboard::Leaderboard;
+use crate::messages::FlashMessage;
+use crate::models::{Block, BlockReplay};
+use crate::scoring::{Season, Standings};
+use crate::stats::UserStats;
 
 #[derive(Template)]
 #[template(path = "index.html")]
 struct IndexTemplate {
-    cloudflare_code: Option<String>,
+    page: Page,
+    flash_html: String,
     modal: Option<String>,
     recommended_block_count: usize,
 }
 
-pub fn get_index(cloudflare_code: Option<String>, recommended_block_count: usize) -> String {
+// Parts of the full pages that depend on the request
+#[derive(Debug, Clone, Default)]
+pub struct Page {
+    pub cloudflare_code: Option<String>,
+    pub csrf_token: String,
+    pub csp_nonce: String,
+    pub flash: Option<FlashMessage>,
+}
+
+fn render_index(
+    page: Page,
+    modal: Option<String>,
+    recommended_block_count: usize,
+) -> askama::Result<String> {
     IndexTemplate {
-        cloudflare_code,
-        modal: None,
+        flash_html: get_partial_flash(page.flash.as_ref(), false)?,
+        page,
+        modal,
         recommended_block_count,
     }
     .render()
-    .expect("Failed to render template")
+}
+
+#[derive(Template)]
+#[template(path = "flash_message.html")]
+struct FlashTemplate<'a> {
+    flash: Option<&'a FlashMessage>,
+    oob: bool,
+}
+
+// The flash message container, swapped out-of-band when added to HTMX responses
+pub fn get_partial_flash(flash: Option<&FlashMessage>, oob: bool) -> askama::Result<String> {
+    FlashTemplate { flash, oob }.render()
+}
+
+pub fn get_index(page: Page, recommended_block_count: usize) -> askama::Result<String> {
+    render_index(page, None, recommended_block_count)
 }
 
 #[derive(Template)]
@@ -26,87 +65,82 @@ pub fn get_index(cloudflare_code: Option<String>, recommended_block_count: usize
 struct ParentFormTemplate {
     blocks: Vec<Block>,
     optimal_height: i64,
+    bindings: Bindings,
 }
 
-pub fn get_partial_parent(blocks: Vec<Block>, optimal_height: i64) -> String {
+pub fn get_partial_parent(
+    blocks: Vec<Block>,
+    optimal_height: i64,
+    bindings: Bindings,
+) -> askama::Result<String> {
     ParentFormTemplate {
         blocks,
         optimal_height,
+        bindings,
     }
     .render()
-    .expect("Failed to render template")
 }
 
 pub fn get_parent(
-    cloudflare_code: Option<String>,
+    page: Page,
     recommended_block_count: usize,
     blocks: Vec<Block>,
     optimal_height: i64,
-) -> String {
+    bindings: Bindings,
+) -> askama::Result<String> {
     let modal = ParentFormTemplate {
         blocks,
         optimal_height,
+        bindings,
     }
-    .render()
-    .expect("Failed to render template");
+    .render()?;
 
-    IndexTemplate {
-        cloudflare_code,
-        modal: Some(modal),
-        recommended_block_count,
-    }
-    .render()
-    .expect("Failed to render template")
+    render_index(page, Some(modal), recommended_block_count)
 }
 
 #[derive(Template)]
 #[template(path = "block_form.html")]
 struct BlockFormTemplate<'a> {
     parent_hash: &'a str,
+    // Name locked to the logged in account
+    username: Option<&'a str>,
     message: Option<String>,
     solution_html: Option<String>,
 }
 
-pub fn get_partial_block(parent_hash: &str) -> String {
+pub fn get_partial_block(parent_hash: &str, username: Option<&str>) -> askama::Result<String> {
     BlockFormTemplate {
         parent_hash,
+        username,
         message: None,
         solution_html: None,
     }
     .render()
-    .expect("Failed to render template")
 }
 
 pub fn get_block(
-    cloudflare_code: Option<String>,
+    page: Page,
     parent_hash: &str,
     recommended_block_count: usize,
-) -> String {
+    username: Option<&str>,
+) -> askama::Result<String> {
     let modal = BlockFormTemplate {
         parent_hash,
+        username,
         message: None,
         solution_html: None,
     }
-    .render()
-    .expect("Failed to render template");
+    .render()?;
 
-    IndexTemplate {
-        cloudflare_code,
-        modal: Some(modal),
-        recommended_block_count,
-    }
-    .render()
-    .expect("Failed to render template")
+    render_index(page, Some(modal), recommended_block_count)
 }
 
 #[derive(Template)]
 #[template(path = "solution_form_placeholder.html")]
 struct SolutionFormPlaceholderTemplate;
 
-pub fn get_solution_placeholder() -> String {
-    SolutionFormPlaceholderTemplate
-        .render()
-        .expect("Failed to render template")
+pub fn get_solution_placeholder() -> askama::Result<String> {
+    SolutionFormPlaceholderTemplate.render()
 }
 
 #[derive(Template)]
@@ -117,6 +151,10 @@ struct SolutionFormTemplate<'a> {
     message: &'a str,
     scramble: &'a str,
     hash: &'a str,
+    // Seconds left in a running timed attempt
+    remaining_seconds: Option<i64>,
+    // (attempt index, hash, scramble) of every attempt of a mean of 3 challenge
+    challenge_scrambles: &'a [(u8, String, String)],
 }
 
 #[allow(clippy::too_many_arguments)]
@@ -126,53 +164,183 @@ pub fn get_partial_solution(
     message: &str,
     scramble: &str,
     hash: &str,
-) -> String {
+    remaining_seconds: Option<i64>,
+    challenge_scrambles: &[(u8, String, String)],
+) -> askama::Result<String> {
     SolutionFormTemplate {
         parent_hash,
         name,
         message,
         scramble,
         hash,
+        remaining_seconds,
+        challenge_scrambles,
     }
     .render()
-    .expect("Failed to render template")
 }
 
 #[allow(clippy::too_many_arguments)]
 pub fn get_solution(
-    cloudflare_code: Option<String>,
+    page: Page,
     parent_hash: &str,
     name: &str,
     message: &str,
     scramble: &str,
     hash: &str,
     recommended_block_count: usize,
-) -> String {
+    username: Option<&str>,
+    remaining_seconds: Option<i64>,
+    challenge_scrambles: &[(u8, String, String)],
+) -> askama::Result<String> {
     let solution_partial = SolutionFormTemplate {
         parent_hash,
         name,
         message,
         scramble,
         hash,
+        remaining_seconds,
+        challenge_scrambles,
     }
-    .render()
-    .expect("Failed to render template");
+    .render()?;
 
     let modal = BlockFormTemplate {
         parent_hash,
+        username,
         message: Some(message.to_string()),
         solution_html: Some(solution_partial),
     }
-    .render()
-    .expect("Failed to render template");
+    .render()?;
 
-    IndexTemplate {
-        cloudflare_code,
-        modal: Some(modal),
-        recommended_block_count,
+    render_index(page, Some(modal), recommended_block_count)
+}
+
+#[derive(Template)]
+#[template(path = "profile.html")]
+struct ProfileTemplate<'a> {
+    name: &'a str,
+    stats: &'a UserStats,
+    chart_svg: &'a str,
+    blocks: &'a [Block],
+    main_chain_hashes: &'a HashSet<String>,
+}
+
+pub fn get_partial_profile(
+    name: &str,
+    stats: &UserStats,
+    chart_svg: &str,
+    blocks: &[Block],
+    main_chain_hashes: &HashSet<String>,
+) -> askama::Result<String> {
+    ProfileTemplate {
+        name,
+        stats,
+        chart_svg,
+        blocks,
+        main_chain_hashes,
     }
     .render()
-    .expect("Failed to render template")
+}
+
+pub fn get_profile(
+    page: Page,
+    recommended_block_count: usize,
+    name: &str,
+    stats: &UserStats,
+    chart_svg: &str,
+    blocks: &[Block],
+    main_chain_hashes: &HashSet<String>,
+) -> askama::Result<String> {
+    let modal = get_partial_profile(name, stats, chart_svg, blocks, main_chain_hashes)?;
+
+    render_index(page, Some(modal), recommended_block_count)
+}
+
+#[derive(Template)]
+#[template(path = "leaderboard.html")]
+struct LeaderboardTemplate<'a> {
+    board: &'a Leaderboard,
+    rounds: &'a [chrono::NaiveDate],
+}
+
+pub fn get_partial_leaderboard(
+    board: &Leaderboard,
+    rounds: &[chrono::NaiveDate],
+) -> askama::Result<String> {
+    LeaderboardTemplate { board, rounds }.render()
+}
+
+pub fn get_leaderboard(
+    page: Page,
+    recommended_block_count: usize,
+    board: &Leaderboard,
+    rounds: &[chrono::NaiveDate],
+) -> askama::Result<String> {
+    render_index(
+        page,
+        Some(get_partial_leaderboard(board, rounds)?),
+        recommended_block_count,
+    )
+}
+
+#[derive(Template)]
+#[template(path = "seasons.html")]
+struct SeasonsTemplate<'a> {
+    standings: Option<&'a Standings>,
+    seasons: &'a [Season],
+}
+
+pub fn get_partial_seasons(
+    standings: Option<&Standings>,
+    seasons: &[Season],
+) -> askama::Result<String> {
+    SeasonsTemplate { standings, seasons }.render()
+}
+
+pub fn get_seasons(
+    page: Page,
+    recommended_block_count: usize,
+    standings: Option<&Standings>,
+    seasons: &[Season],
+) -> askama::Result<String> {
+    render_index(
+        page,
+        Some(get_partial_seasons(standings, seasons)?),
+        recommended_block_count,
+    )
+}
+
+#[derive(Template)]
+#[template(path = "login.html")]
+struct LoginTemplate;
+
+pub fn get_partial_login() -> askama::Result<String> {
+    LoginTemplate.render()
+}
+
+pub fn get_login(page: Page, recommended_block_count: usize) -> askama::Result<String> {
+    render_index(page, Some(get_partial_login()?), recommended_block_count)
+}
+
+#[derive(Template)]
+#[template(path = "register.html")]
+struct RegisterTemplate;
+
+pub fn get_partial_register() -> askama::Result<String> {
+    RegisterTemplate.render()
+}
+
+pub fn get_register(page: Page, recommended_block_count: usize) -> askama::Result<String> {
+    render_index(page, Some(get_partial_register()?), recommended_block_count)
+}
+
+#[derive(Template)]
+#[template(path = "account_nav.html")]
+struct AccountNavTemplate<'a> {
+    username: Option<&'a str>,
+}
+
+pub fn get_partial_account_nav(username: Option<&str>) -> askama::Result<String> {
+    AccountNavTemplate { username }.render()
 }
 
 #[derive(Template)]
@@ -184,8 +352,12 @@ struct BlocksTemplate {
     page_size: u32,
     show_all: bool,
     optimal_height: i64,
+    bindings: Bindings,
+    subscribe: bool,
 }
 
+// The first page of the overview subscribes to chain events, so the list stays up to date
+#[allow(clippy::too_many_arguments)]
 pub fn get_partial_blocks(
     blocks: Vec<Block>,
     main_chain_hashes: HashSet<String>,
@@ -193,7 +365,9 @@ pub fn get_partial_blocks(
     page_size: u32,
     show_all: bool,
     optimal_height: i64,
-) -> String {
+    bindings: Bindings,
+    subscribe: bool,
+) -> askama::Result<String> {
     BlocksTemplate {
         blocks,
         main_chain_hashes,
@@ -201,7 +375,217 @@ pub fn get_partial_blocks(
         page_size,
         show_all,
         optimal_height,
+        bindings,
+        subscribe,
+    }
+    .render()
+}
+
+#[derive(Template)]
+#[template(path = "block_details.html")]
+struct BlockDetailsTemplate<'a> {
+    block: &'a Block,
+    children: &'a [Block],
+    ancestors: &'a [Block],
+    main_chain_hashes: &'a HashSet<String>,
+    fork_height: Option<i64>,
+    optimal_height: i64,
+    bindings: &'a Bindings,
+    attempt: Option<&'a Attempt>,
+    challenge_attempts: &'a [ChallengeAttempt],
+}
+
+impl BlockDetailsTemplate<'_> {
+    fn challenge_mean(&self) -> String {
+        challenges::mean_display(self.challenge_attempts)
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn get_partial_block_details(
+    block: &Block,
+    children: &[Block],
+    ancestors: &[Block],
+    main_chain_hashes: &HashSet<String>,
+    fork_height: Option<i64>,
+    optimal_height: i64,
+    bindings: &Bindings,
+    attempt: Option<&Attempt>,
+    challenge_attempts: &[ChallengeAttempt],
+) -> askama::Result<String> {
+    BlockDetailsTemplate {
+        block,
+        children,
+        ancestors,
+        main_chain_hashes,
+        fork_height,
+        optimal_height,
+        bindings,
+        attempt,
+        challenge_attempts,
+    }
+    .render()
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn get_block_details(
+    page: Page,
+    recommended_block_count: usize,
+    block: &Block,
+    children: &[Block],
+    ancestors: &[Block],
+    main_chain_hashes: &HashSet<String>,
+    fork_height: Option<i64>,
+    optimal_height: i64,
+    bindings: &Bindings,
+    attempt: Option<&Attempt>,
+    challenge_attempts: &[ChallengeAttempt],
+) -> askama::Result<String> {
+    let modal = get_partial_block_details(
+        block,
+        children,
+        ancestors,
+        main_chain_hashes,
+        fork_height,
+        optimal_height,
+        bindings,
+        attempt,
+        challenge_attempts,
+    )?;
+
+    render_index(page, Some(modal), recommended_block_count)
+}
+
+#[derive(Template)]
+#[template(path = "block_disambiguation.html")]
+struct BlockDisambiguationTemplate<'a> {
+    prefix: &'a str,
+    blocks: Vec<Block>,
+}
+
+pub fn get_partial_block_disambiguation(
+    prefix: &str,
+    blocks: Vec<Block>,
+) -> askama::Result<String> {
+    BlockDisambiguationTemplate { prefix, blocks }.render()
+}
+
+pub fn get_block_disambiguation(
+    page: Page,
+    recommended_block_count: usize,
+    prefix: &str,
+    blocks: Vec<Block>,
+) -> askama::Result<String> {
+    let modal = get_partial_block_disambiguation(prefix, blocks)?;
+
+    render_index(page, Some(modal), recommended_block_count)
+}
+
+#[derive(Template)]
+#[template(path = "replay.html")]
+struct ReplayTemplate<'a> {
+    block: &'a Block,
+    replay_json: String,
+    stickers: Vec<(u32, u32)>,
+    sticker_size: u32,
+    width: u32,
+    height: u32,
+}
+
+pub fn get_partial_replay(block: &Block, replay: &BlockReplay) -> askama::Result<String> {
+    let colours = Face::ALL
+        .iter()
+        .map(|f| (f.letter().to_string(), f.colour()))
+        .collect::<HashMap<_, _>>();
+    // Escape '<' so the JSON can't close the surrounding script tag
+    let replay_json = serde_json::json!({ "colours": colours, "steps": replay.steps })
+        .to_string()
+        .replace('<', "\\u003c");
+    let sticker_size = 20;
+    let (width, height) = CubeState::net_size(sticker_size);
+
+    ReplayTemplate {
+        block,
+        replay_json,
+        stickers: CubeState::net_positions(sticker_size),
+        sticker_size,
+        width,
+        height,
     }
     .render()
-    .expect("Failed to render template")
+}
+
+pub fn get_replay(
+    page: Page,
+    recommended_block_count: usize,
+    block: &Block,
+    replay: &BlockReplay,
+) -> askama::Result<String> {
+    let modal = get_partial_replay(block, replay)?;
+
+    render_index(page, Some(modal), recommended_block_count)
+}
+
+#[derive(Template)]
+#[template(path = "feed.xml")]
+struct FeedTemplate<'a> {
+    title: &'a str,
+    base_url: &'a str,
+    self_url: &'a str,
+    updated: String,
+    blocks: Vec<Block>,
+}
+
+pub fn get_feed(
+    title: &str,
+    base_url: &str,
+    self_url: &str,
+    blocks: Vec<Block>,
+) -> askama::Result<String> {
+    let updated = blocks
+        .iter()
+        .map(|b| b.created_at_rfc3339())
+        .max()
+        .unwrap_or_else(|| Utc::now().format("%Y-%m-%dT%H:%M:%SZ").to_string());
+
+    FeedTemplate {
+        title,
+        base_url,
+        self_url,
+        updated,
+        blocks,
+    }
+    .render()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::test_block;
+    use chrono::NaiveDateTime;
+
+    #[test]
+    fn test_feed_escapes_content() {
+        let block = Block {
+            name: "<script>".to_string(),
+            message: "Fish & chips".to_string(),
+            created_at: Some(
+                NaiveDateTime::parse_from_str("2025-01-01 10:00:00", "%Y-%m-%d %H:%M:%S").unwrap(),
+            ),
+            ..test_block("A0C1E2G3")
+        };
+        let feed = get_feed(
+            "Feed",
+            "https://example.com",
+            "https://example.com/feed.atom",
+            vec![block],
+        )
+        .unwrap();
+
+        assert!(feed.contains("<updated>2025-01-01T10:00:00Z</updated>"));
+        assert!(feed.contains("<id>https://example.com/b/A0C1E2G3</id>"));
+        assert!(feed.contains("&#60;script&#62;"));
+        assert!(feed.contains("Fish &#38; chips"));
+        assert!(!feed.contains("<script>"));
+    }
 }
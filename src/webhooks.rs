@@ -0,0 +1,525 @@
+use std::time::Duration;
+
+use actix_web::rt;
+use chrono::{NaiveDateTime, Utc};
+use hmac::{Hmac, Mac};
+use serde::Serialize;
+use sha2::Sha256;
+use sqlx::{Acquire, FromRow, Sqlite, SqliteExecutor, SqlitePool};
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::events::{ChainEvent, EventBus};
+
+const MAX_ATTEMPTS: i64 = 10;
+const BASE_RETRY_DELAY: Duration = Duration::from_secs(10);
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(60 * 60);
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+const BATCH_SIZE: i64 = 20;
+
+pub const SIGNATURE_HEADER: &str = "X-Webhook-Signature";
+pub const EVENT_HEADER: &str = "X-Webhook-Event";
+pub const DELIVERY_HEADER: &str = "X-Webhook-Delivery";
+
+// Events stored in the outbox when blocks are added
+pub const EVENTS: &[&str] = &["block_created", "tip_changed"];
+
+#[derive(Debug, Clone, FromRow, Serialize)]
+pub struct Webhook {
+    pub id: i64,
+    pub url: String,
+    #[serde(skip)]
+    pub secret: String,
+    pub events: String,
+}
+
+#[derive(Debug, Clone, FromRow)]
+pub struct Delivery {
+    pub id: i64,
+    pub webhook_id: i64,
+    pub event: String,
+    pub payload: String,
+    pub attempts: i64,
+    pub next_attempt_at: NaiveDateTime,
+    pub last_error: Option<String>,
+    pub delivered_at: Option<NaiveDateTime>,
+    pub failed_at: Option<NaiveDateTime>,
+}
+
+// A due delivery together with the target it should be sent to
+#[derive(Debug, FromRow)]
+struct PendingDelivery {
+    id: i64,
+    event: String,
+    payload: String,
+    attempts: i64,
+    url: String,
+    secret: String,
+}
+
+impl Webhook {
+    pub async fn create(
+        db: &SqlitePool,
+        url: &str,
+        secret: &str,
+        events: &str,
+    ) -> Result<Self, sqlx::Error> {
+        // Commit explicitly: a dropped `INSERT .. RETURNING` statement might not be finalized yet
+        let mut tx = db.begin().await?;
+        let webhook = sqlx::query_as::<_, Webhook>(
+            "INSERT INTO webhooks (url, secret, events) VALUES (?1, ?2, ?3)
+            RETURNING id, url, secret, events",
+        )
+        .bind(url)
+        .bind(secret)
+        .bind(events)
+        .fetch_one(&mut *tx)
+        .await?;
+        tx.commit().await?;
+
+        Ok(webhook)
+    }
+
+    pub async fn find_all<'e>(db: impl SqliteExecutor<'e>) -> Result<Vec<Self>, sqlx::Error> {
+        sqlx::query_as::<_, Webhook>("SELECT id, url, secret, events FROM webhooks ORDER BY id")
+            .fetch_all(db)
+            .await
+    }
+
+    // Remove a webhook along with its deliveries, returning whether it existed
+    pub async fn delete(db: &SqlitePool, id: i64) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query("DELETE FROM webhooks WHERE id = ?1")
+            .bind(id)
+            .execute(db)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    // Check the target of a new webhook, explaining what is wrong with it
+    pub fn validate(url: &str, events: &str) -> Result<(), String> {
+        if !(url.starts_with("https://") || url.starts_with("http://")) {
+            return Err(format!("{} is not an HTTP(S) URL", url));
+        }
+        match events
+            .split(',')
+            .map(str::trim)
+            .find(|e| *e != "*" && !EVENTS.contains(e))
+        {
+            Some(event) => Err(format!(
+                "Unknown event '{}', expected {} or *",
+                event,
+                EVENTS.join(", ")
+            )),
+            None => Ok(()),
+        }
+    }
+
+    // Whether this webhook subscribed to the given event ("*" matches everything)
+    pub fn accepts(&self, event: &str) -> bool {
+        self.events
+            .split(',')
+            .map(str::trim)
+            .any(|e| e == "*" || e == event)
+    }
+}
+
+impl Delivery {
+    pub async fn find_by_webhook(
+        db: &SqlitePool,
+        webhook_id: i64,
+    ) -> Result<Vec<Self>, sqlx::Error> {
+        sqlx::query_as::<_, Delivery>(
+            "SELECT id, webhook_id, event, payload, attempts, next_attempt_at, last_error, delivered_at, failed_at
+            FROM webhook_deliveries
+            WHERE webhook_id = ?1
+            ORDER BY id",
+        )
+        .bind(webhook_id)
+        .fetch_all(db)
+        .await
+    }
+}
+
+// Hex encoded HMAC-SHA256 of the request body, sent as "sha256=<hex>"
+pub fn sign(secret: &str, body: &str) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any size");
+    mac.update(body.as_bytes());
+    hex::encode(mac.finalize().into_bytes())
+}
+
+// Exponential backoff after the given number of failed attempts: 10s, 20s, 40s, ... up to an hour
+pub fn retry_delay(attempts: i64) -> Duration {
+    let exponent = attempts.saturating_sub(1).clamp(0, 16) as u32;
+    BASE_RETRY_DELAY
+        .saturating_mul(2u32.pow(exponent))
+        .min(MAX_RETRY_DELAY)
+}
+
+// Store a delivery in the outbox for every webhook subscribed to the event. Given the
+// transaction storing what the event is about, the deliveries are only kept if it commits.
+pub async fn enqueue<'c>(
+    db: impl Acquire<'c, Database = Sqlite>,
+    event: &ChainEvent,
+) -> Result<usize, sqlx::Error> {
+    let payload = serde_json::to_string(event).expect("Events should serialize");
+    let mut tx = db.begin().await?;
+    let webhooks = Webhook::find_all(&mut *tx).await?;
+    let mut count = 0;
+
+    for webhook in webhooks.iter().filter(|w| w.accepts(event.name())) {
+        sqlx::query(
+            "INSERT INTO webhook_deliveries (webhook_id, event, payload, next_attempt_at)
+            VALUES (?1, ?2, ?3, ?4)",
+        )
+        .bind(webhook.id)
+        .bind(event.name())
+        .bind(&payload)
+        .bind(Utc::now().naive_utc())
+        .execute(&mut *tx)
+        .await?;
+        count += 1;
+    }
+
+    tx.commit().await?;
+    Ok(count)
+}
+
+async fn send(client: &reqwest::Client, delivery: &PendingDelivery) -> Result<(), String> {
+    let signature = format!("sha256={}", sign(&delivery.secret, &delivery.payload));
+    let response = client
+        .post(&delivery.url)
+        .header("Content-Type", "application/json")
+        .header(EVENT_HEADER, &delivery.event)
+        .header(DELIVERY_HEADER, delivery.id.to_string())
+        .header(SIGNATURE_HEADER, signature)
+        .body(delivery.payload.clone())
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+
+    if response.status().is_success() {
+        Ok(())
+    } else {
+        Err(format!("Unexpected status {}", response.status()))
+    }
+}
+
+// Attempt every delivery that is due at `now`, returning how many succeeded
+pub async fn deliver_due(
+    db: &SqlitePool,
+    client: &reqwest::Client,
+    now: NaiveDateTime,
+) -> Result<usize, sqlx::Error> {
+    let pending = sqlx::query_as::<_, PendingDelivery>(
+        "SELECT d.id, d.event, d.payload, d.attempts, w.url, w.secret
+        FROM webhook_deliveries d
+        JOIN webhooks w ON w.id = d.webhook_id
+        WHERE d.delivered_at IS NULL AND d.failed_at IS NULL AND d.next_attempt_at <= ?1
+        ORDER BY d.id
+        LIMIT ?2",
+    )
+    .bind(now)
+    .bind(BATCH_SIZE)
+    .fetch_all(db)
+    .await?;
+
+    let mut delivered = 0;
+    for delivery in pending {
+        match send(client, &delivery).await {
+            Ok(()) => {
+                sqlx::query(
+                    "UPDATE webhook_deliveries SET attempts = attempts + 1, delivered_at = ?2, last_error = NULL WHERE id = ?1",
+                )
+                .bind(delivery.id)
+                .bind(Utc::now().naive_utc())
+                .execute(db)
+                .await?;
+                delivered += 1;
+            }
+            Err(error) => {
+                let attempts = delivery.attempts + 1;
+                let failed_at = (attempts >= MAX_ATTEMPTS).then(|| Utc::now().naive_utc());
+                let next_attempt_at = now
+                    + chrono::Duration::from_std(retry_delay(attempts))
+                        .expect("Retry delay should fit");
+                sqlx::query(
+                    "UPDATE webhook_deliveries
+                    SET attempts = ?2, next_attempt_at = ?3, last_error = ?4, failed_at = ?5
+                    WHERE id = ?1",
+                )
+                .bind(delivery.id)
+                .bind(attempts)
+                .bind(next_attempt_at)
+                .bind(&error)
+                .bind(failed_at)
+                .execute(db)
+                .await?;
+                tracing::warn!(
+                    delivery_id = delivery.id,
+                    url = %delivery.url,
+                    attempts,
+                    %error,
+                    "webhook delivery failed"
+                );
+            }
+        }
+    }
+
+    Ok(delivered)
+}
+
+pub fn client() -> reqwest::Client {
+    reqwest::Client::builder()
+        .timeout(REQUEST_TIMEOUT)
+        .build()
+        .expect("Failed to build HTTP client")
+}
+
+// Work through the outbox periodically, and right away whenever a new event is published.
+// Pending deliveries survive restarts because they are only ever read from the database.
+pub fn start_delivery_task(db: SqlitePool, events: &EventBus) {
+    let mut receiver = events.subscribe();
+    let client = client();
+
+    rt::spawn(async move {
+        loop {
+            if let Err(e) = deliver_due(&db, &client, Utc::now().naive_utc()).await {
+                tracing::error!(error = %e, "failed to process webhook outbox");
+            }
+
+            tokio::select! {
+                _ = rt::time::sleep(POLL_INTERVAL) => {}
+                result = receiver.recv() => {
+                    if let Err(RecvError::Closed) = result {
+                        break;
+                    }
+                }
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::sync::mpsc;
+
+    struct ReceivedRequest {
+        headers: Vec<(String, String)>,
+        body: String,
+    }
+
+    impl ReceivedRequest {
+        fn header(&self, name: &str) -> Option<&str> {
+            self.headers
+                .iter()
+                .find(|(key, _)| key.eq_ignore_ascii_case(name))
+                .map(|(_, value)| value.as_str())
+        }
+    }
+
+    // Minimal HTTP server standing in for a webhook receiver, answering with the given statuses
+    fn start_receiver(statuses: Vec<u16>) -> (String, mpsc::Receiver<ReceivedRequest>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let (sender, receiver) = mpsc::channel();
+
+        std::thread::spawn(move || {
+            for (stream, status) in listener.incoming().zip(statuses) {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut headers = Vec::new();
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                loop {
+                    line.clear();
+                    reader.read_line(&mut line).unwrap();
+                    match line.trim_end().split_once(": ") {
+                        Some((key, value)) => headers.push((key.to_string(), value.to_string())),
+                        None => break,
+                    }
+                }
+                let length = headers
+                    .iter()
+                    .find(|(key, _)| key.eq_ignore_ascii_case("content-length"))
+                    .map(|(_, value)| value.parse().unwrap())
+                    .unwrap_or(0);
+                let mut body = vec![0; length];
+                reader.read_exact(&mut body).unwrap();
+
+                write!(
+                    stream,
+                    "HTTP/1.1 {} Status\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                    status
+                )
+                .unwrap();
+                let _ = sender.send(ReceivedRequest {
+                    headers,
+                    body: String::from_utf8(body).unwrap(),
+                });
+            }
+        });
+
+        (url, receiver)
+    }
+
+    #[test]
+    fn test_sign() {
+        // Test vector from RFC 4231, test case 2
+        assert_eq!(
+            sign("Jefe", "what do ya want for nothing?"),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+
+    #[test]
+    fn test_retry_delay() {
+        assert_eq!(retry_delay(1), Duration::from_secs(10));
+        assert_eq!(retry_delay(2), Duration::from_secs(20));
+        assert_eq!(retry_delay(4), Duration::from_secs(80));
+        assert_eq!(retry_delay(40), MAX_RETRY_DELAY);
+    }
+
+    #[test]
+    fn test_event_filter() {
+        let webhook = Webhook {
+            id: 1,
+            url: "http://localhost".to_string(),
+            secret: "secret".to_string(),
+            events: "block_created, tip_changed".to_string(),
+        };
+        assert!(webhook.accepts("tip_changed"));
+        assert!(!webhook.accepts("round_started"));
+
+        let webhook = Webhook {
+            events: "*".to_string(),
+            ..webhook
+        };
+        assert!(webhook.accepts("round_started"));
+    }
+
+    #[sqlx::test]
+    async fn test_delivery_with_retry(db: SqlitePool) {
+        let (url, requests) = start_receiver(vec![500, 200]);
+        let webhook = Webhook::create(&db, &url, "s3cret", "tip_changed")
+            .await
+            .unwrap();
+        let client = client();
+
+        let block_created = ChainEvent::BlockCreated {
+            hash: "child".to_string(),
+            parent_hash: Some("parent".to_string()),
+            height: 1,
+            name: "Alice".to_string(),
+            solution_moves: 25,
+        };
+        let tip_changed = ChainEvent::TipChanged {
+            hash: "child".to_string(),
+            previous_hash: Some("parent".to_string()),
+            height: 1,
+        };
+        assert_eq!(enqueue(&db, &block_created).await.unwrap(), 0);
+        assert_eq!(enqueue(&db, &tip_changed).await.unwrap(), 1);
+
+        // The first attempt fails and is scheduled for a retry
+        let now = Utc::now().naive_utc();
+        assert_eq!(deliver_due(&db, &client, now).await.unwrap(), 0);
+        let failed = requests.recv().unwrap();
+        let deliveries = Delivery::find_by_webhook(&db, webhook.id).await.unwrap();
+        assert_eq!(deliveries.len(), 1);
+        assert_eq!(deliveries[0].attempts, 1);
+        assert!(deliveries[0].last_error.is_some());
+        assert!(deliveries[0].next_attempt_at > now);
+
+        // Nothing is sent before the retry is due
+        assert_eq!(deliver_due(&db, &client, now).await.unwrap(), 0);
+
+        let later = now + chrono::Duration::minutes(1);
+        assert_eq!(deliver_due(&db, &client, later).await.unwrap(), 1);
+        let delivered = requests.recv().unwrap();
+        let deliveries = Delivery::find_by_webhook(&db, webhook.id).await.unwrap();
+        assert!(deliveries[0].delivered_at.is_some());
+        assert_eq!(deliveries[0].attempts, 2);
+
+        for request in [&failed, &delivered] {
+            assert_eq!(request.header(EVENT_HEADER), Some("tip_changed"));
+            assert_eq!(
+                request.header(SIGNATURE_HEADER),
+                Some(format!("sha256={}", sign("s3cret", &request.body)).as_str())
+            );
+            assert_eq!(
+                request.header(DELIVERY_HEADER),
+                Some(deliveries[0].id.to_string().as_str())
+            );
+        }
+        let body: serde_json::Value = serde_json::from_str(&delivered.body).unwrap();
+        assert_eq!(body["type"], "tip_changed");
+        assert_eq!(body["hash"], "child");
+
+        // Delivered events are not sent again
+        assert_eq!(deliver_due(&db, &client, later).await.unwrap(), 0);
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(
+            Webhook::validate("https://example.com/hook", "block_created, tip_changed").is_ok()
+        );
+        assert!(Webhook::validate("https://example.com/hook", "*").is_ok());
+        assert!(Webhook::validate("ftp://example.com", "*").is_err());
+        assert_eq!(
+            Webhook::validate("https://example.com/hook", "tip_changed,round_started"),
+            Err(
+                "Unknown event 'round_started', expected block_created, tip_changed or *"
+                    .to_string()
+            )
+        );
+    }
+
+    #[sqlx::test]
+    async fn test_outbox_follows_transaction(db: SqlitePool) {
+        let webhook = Webhook::create(&db, "http://localhost", "secret", "*")
+            .await
+            .unwrap();
+        let tip_changed = ChainEvent::TipChanged {
+            hash: "child".to_string(),
+            previous_hash: None,
+            height: 1,
+        };
+
+        // Deliveries enqueued in a transaction that is rolled back are never sent
+        let mut tx = db.begin().await.unwrap();
+        assert_eq!(enqueue(&mut *tx, &tip_changed).await.unwrap(), 1);
+        tx.rollback().await.unwrap();
+        assert!(
+            Delivery::find_by_webhook(&db, webhook.id)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+
+        let mut tx = db.begin().await.unwrap();
+        enqueue(&mut *tx, &tip_changed).await.unwrap();
+        tx.commit().await.unwrap();
+        assert_eq!(
+            Delivery::find_by_webhook(&db, webhook.id)
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+
+        // Removing a webhook drops its deliveries
+        assert!(Webhook::delete(&db, webhook.id).await.unwrap());
+        assert!(!Webhook::delete(&db, webhook.id).await.unwrap());
+        assert!(
+            Delivery::find_by_webhook(&db, webhook.id)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+    }
+}